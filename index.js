@@ -1,18 +1,50 @@
+import { AsyncAnn } from "./little_annoy_wasm/js/async.js";
+
 const rust = import("./little_annoy_wasm/pkg");
 
 rust.then((m) => {
-  let ann = m.Ann.new(2);
-  
+  let ann = m.Ann.new("euclidean", 2, "float32");
+
   ann.add_item(0, [1.0, 1.0]);
   ann.add_item(1, [5.0, 5.0]);
   ann.add_item(2, [2.0, 2.0]);
   ann.add_item(3, [4.0, 4.0]);
   ann.add_item(4, [10.0, 10.0]);
 
-  ann.build(1000);
+  ann.build(10);
 
   let r = ann.get_nns_by_vector([1.0, 1.0], 10, -1);
 
   console.log(r.result())
   console.log(r.distance())
+
+  // An index can be shipped as bytes and restored with the same metric, dimension and dtype.
+  let bytes = ann.serialize();
+  let copy = m.Ann.deserialize("euclidean", 2, bytes, "float32");
+
+  console.log(copy.get_nns_by_item(0, 3, -1).result())
+  console.log(copy.get_distance(0, 4))
+
+  try {
+    copy.add_item(5, [1.0]);
+  } catch (e) {
+    console.log(e instanceof RangeError, e.message)
+  }
 });
+
+// Large builds run in a Web Worker so the page stays responsive.
+(async () => {
+  const ann = await AsyncAnn.create("euclidean", 2, "float32");
+  const ids = Array.from({ length: 1000 }, (_, i) => i);
+
+  await ann.addItems(ids, ids.map((i) => [i % 31, i % 17]));
+  await ann.build(1000, {
+    onProgress: (trees) => {
+      document.querySelector(".container").textContent = `${trees} / 1000 trees`;
+    },
+  });
+
+  const [nearest] = await ann.getNnsByVector([[1.0, 1.0]], 10);
+  console.log(nearest.ids, nearest.distances);
+  ann.terminate();
+})();
@@ -3,5 +3,6 @@ use criterion::criterion_main;
 mod benchmarks;
 
 criterion_main! {
-    benchmarks::simple::benches
+    benchmarks::simple::benches,
+    benchmarks::compare::benches
 }
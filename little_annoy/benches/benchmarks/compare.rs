@@ -0,0 +1,66 @@
+use criterion::{criterion_group, Criterion};
+
+use little_annoy::{AnnIndex, Annoy, BruteForce, Euclidean, Hnsw};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+const F: usize = 32;
+
+fn dataset(n: usize) -> Vec<Vec<f64>> {
+    let mut rng = StdRng::seed_from_u64(0);
+    (0..n)
+        .map(|_| (0..F).map(|_| rng.gen_range(-1.0..1.0)).collect())
+        .collect()
+}
+
+/// Times building `new()` over the same items with parameter `q`, then
+/// querying it.
+fn bench_index<I: AnnIndex<f64>>(c: &mut Criterion, name: &str, new: impl Fn() -> I, q: i64) {
+    let items = dataset(2000);
+    let fill = || {
+        let mut index = new();
+        for (i, v) in items.iter().enumerate() {
+            index.add_item(i as i64, v).unwrap();
+        }
+        index
+    };
+
+    c.bench_function(&format!("{} build 2000", name), |b| {
+        b.iter(|| fill().build(q).unwrap())
+    });
+
+    let mut index = fill();
+    index.build(q).unwrap();
+    c.bench_function(&format!("{} query 10", name), |b| {
+        b.iter(|| index.get_nns_by_vector(&items[7], 10, -1))
+    });
+}
+
+pub fn annoy(c: &mut Criterion) {
+    let new = || {
+        let mut ann: Annoy<f64, Euclidean> = Annoy::new(F);
+        ann.set_seed(1);
+        ann
+    };
+    bench_index(c, "annoy", new, 10);
+}
+
+pub fn hnsw(c: &mut Criterion) {
+    let new = || {
+        let mut hnsw: Hnsw<f64, Euclidean> = Hnsw::new(F);
+        hnsw.set_seed(1);
+        hnsw
+    };
+    bench_index(c, "hnsw", new, -1);
+}
+
+pub fn brute_force(c: &mut Criterion) {
+    bench_index(
+        c,
+        "brute force",
+        || BruteForce::<f64, Euclidean>::new(F),
+        -1,
+    );
+}
+
+criterion_group!(benches, annoy, hnsw, brute_force);
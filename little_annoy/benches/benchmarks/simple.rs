@@ -6,25 +6,25 @@ use rand;
 pub fn build(c: &mut Criterion) {
     let mut ann: Annoy<f64, Euclidean> = Annoy::new(2);
 
-    ann.add_item(0, &[1.0, 1.0]);
-    ann.add_item(1, &[5.0, 5.0]);
-    ann.add_item(2, &[2.0, 2.0]);
-    ann.add_item(3, &[4.0, 4.0]);
+    ann.add_item(0, &[1.0, 1.0]).unwrap();
+    ann.add_item(1, &[5.0, 5.0]).unwrap();
+    ann.add_item(2, &[2.0, 2.0]).unwrap();
+    ann.add_item(3, &[4.0, 4.0]).unwrap();
 
     for z in 4..10 {
-        ann.add_item(z, &[10.0, 10.0]);
+        ann.add_item(z, &[10.0, 10.0]).unwrap();
     }
 
-    c.bench_function("build 2", |b| b.iter(|| ann.build(2)));
-    c.bench_function("build 10", |b| b.iter(|| ann.build(10)));
-    c.bench_function("build 100", |b| b.iter(|| ann.build(100)));
+    c.bench_function("build 2", |b| b.iter(|| ann.rebuild(2).unwrap()));
+    c.bench_function("build 10", |b| b.iter(|| ann.rebuild(10).unwrap()));
+    c.bench_function("build 100", |b| b.iter(|| ann.rebuild(100).unwrap()));
 }
 
 pub fn add_item(c: &mut Criterion) {
     fn create_item<const N: usize>() -> [f64; N] {
         let mut arr = [0.0; N];
-        for i in 0..N {
-            arr[i] = rand::random();
+        for v in arr.iter_mut() {
+            *v = rand::random();
         }
         arr
     }
@@ -33,7 +33,7 @@ pub fn add_item(c: &mut Criterion) {
         b.iter(|| {
             let mut ann: Annoy<f64, Euclidean> = Annoy::new(2);
             for i in 0..100 {
-                ann.add_item(i, &create_item::<2>());
+                ann.add_item(i, &create_item::<2>()).unwrap();
             }
             ann.build(100)
         })
@@ -42,7 +42,7 @@ pub fn add_item(c: &mut Criterion) {
         b.iter(|| {
             let mut ann: Annoy<f64, Euclidean> = Annoy::new(100);
             for i in 0..100 {
-                ann.add_item(i, &create_item::<100>());
+                ann.add_item(i, &create_item::<100>()).unwrap();
             }
             ann.build(100)
         })
@@ -51,7 +51,7 @@ pub fn add_item(c: &mut Criterion) {
         b.iter(|| {
             let mut ann: Annoy<f64, Euclidean> = Annoy::new(10_000);
             for i in 0..100 {
-                ann.add_item(i, &create_item::<10_000>());
+                ann.add_item(i, &create_item::<10_000>()).unwrap();
             }
             ann.build(100)
         })
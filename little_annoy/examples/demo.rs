@@ -4,13 +4,14 @@ fn main() {
     let mut ann: Annoy<f64, Euclidean> = Annoy::new(2);
 
     println!("Add an item to the ANN.");
-    ann.add_item(0, &[1.0, 1.0]);
-    ann.add_item(1, &[5.0, 5.0]);
-    ann.add_item(2, &[2.0, 2.0]);
-    ann.add_item(3, &[4.0, 4.0]);
+    ann.add_item(0, &[1.0, 1.0]).unwrap();
+    ann.add_item(1, &[5.0, 5.0]).unwrap();
+    ann.add_item(2, &[2.0, 2.0]).unwrap();
+    ann.add_item(3, &[4.0, 4.0]).unwrap();
 
     for z in 4..10_000 {
-        ann.add_item(z, &[10.0 + z as f64, 10.0 + z as f64]);
+        ann.add_item(z, &[10.0 + z as f64, 10.0 + z as f64])
+            .unwrap();
     }
 
     println!("Building index ...");
@@ -11,7 +11,7 @@ fn vec_to_fixed_slice<T: Copy + Default, const N: usize>(v: Vec<T>) -> [T; N] {
 pub fn to_binary(elem: u8) -> Vec<bool> {
     let byte_vec = elem.to_le_bytes().to_vec();
     let bv = BitVec::from_bytes(&byte_vec);
-    return bv.iter().collect::<Vec<bool>>();
+    bv.iter().collect::<Vec<bool>>()
 }
 
 fn main() {
@@ -28,15 +28,13 @@ fn main() {
     let image1_binary: Vec<u8> = hash1
         .as_bytes()
         .iter()
-        .map(|x| to_binary(*x))
-        .flatten()
+        .flat_map(|x| to_binary(*x))
         .map(|x| x as u8)
         .collect();
     let image2_binary: Vec<u8> = hash2
         .as_bytes()
         .iter()
-        .map(|x| to_binary(*x))
-        .flatten()
+        .flat_map(|x| to_binary(*x))
         .map(|x| x as u8)
         .collect();
 
@@ -51,10 +49,10 @@ fn main() {
         v2[i] = image2_binary[i] as f64;
     }
 
-    ann.add_item(1, &v1);
-    ann.add_item(2, &v1);
-    ann.add_item(3, &v1);
-    ann.add_item(4, &v2);
+    ann.add_item(1, &v1).unwrap();
+    ann.add_item(2, &v1).unwrap();
+    ann.add_item(3, &v1).unwrap();
+    ann.add_item(4, &v2).unwrap();
 
     ann.build(100);
 
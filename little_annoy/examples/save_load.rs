@@ -10,10 +10,10 @@ fn print_distance(ann: &mut Annoy<f64, Euclidean>) {
 fn main() {
     let mut ann: Annoy<f64, Euclidean> = Annoy::new(2);
 
-    ann.add_item(0, &[1.0, 1.0]);
-    ann.add_item(1, &[5.0, 5.0]);
-    ann.add_item(2, &[2.0, 2.0]);
-    ann.add_item(3, &[4.0, 4.0]);
+    ann.add_item(0, &[1.0, 1.0]).unwrap();
+    ann.add_item(1, &[5.0, 5.0]).unwrap();
+    ann.add_item(2, &[2.0, 2.0]).unwrap();
+    ann.add_item(3, &[4.0, 4.0]).unwrap();
     ann.build(1000);
 
     println!("Print distance.");
@@ -21,7 +21,7 @@ fn main() {
 
     let file = std::fs::File::create("/tmp/hoge.db").expect("Could not create temp file");
     println!("Save nodes.");
-    let _ = ann.save(file);
+    ann.save(file);
 
     println!();
 
@@ -1,34 +1,57 @@
-use futures::future;
-use rand::thread_rng;
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::hash_map::Entry;
 use std::collections::BinaryHeap;
-use std::collections::HashMap;
-use std::io::BufWriter;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufWriter, Write};
 use std::marker::PhantomData;
-use std::sync::{atomic::AtomicI64, atomic::Ordering::SeqCst, Arc, Mutex, RwLock};
-use std::usize;
-use tokio::runtime::Builder;
+use std::path::Path;
+use std::sync::atomic::{AtomicI64, Ordering::SeqCst};
+use std::sync::{Arc, Mutex, RwLock};
+use web_time::Instant;
 
+use crate::checksum::{ChecksumReader, ChecksumWriter};
 use crate::distance::{Distance, NodeImpl};
+use crate::error::{check_vector, Error};
+use crate::format::{self, Kind};
 use crate::item::Item;
-use crate::Numeric;
+use crate::neighbor::{AnnResult, NeighborIter, Neighbors};
+use crate::options::{BuildOptions, BuildProgress, CancellationToken, QueryOptions};
+use crate::store::DiskStore;
 
-#[derive(PartialEq, PartialOrd)]
-struct AnnResult<T>(T, i64);
-
-impl<T: PartialEq> Eq for AnnResult<T> {}
-
-#[allow(clippy::derive_ord_xor_partial_ord)]
-impl<T: PartialOrd> Ord for AnnResult<T> {
-    fn cmp(&self, other: &AnnResult<T>) -> std::cmp::Ordering {
-        self.0.partial_cmp(&other.0).unwrap()
-    }
-}
+pub const DEFAULT_N_THREADS: usize = 10;
 
 pub struct AnnoyThreadBuilder<T: Item, D: Distance<T>> {
     n_nodes: AtomicI64,
     nodes: RwLock<HashMap<i64, D::Node>>,
     roots: RwLock<Vec<i64>>,
+    cancel: Option<CancellationToken>,
+    // New nodes go here instead of `nodes` for an on-disk build.
+    store: Option<Mutex<DiskStore>>,
+    failed: Mutex<Option<io::Error>>,
+}
+
+impl<T: Item, D: Distance<T>> AnnoyThreadBuilder<T, D> {
+    fn is_cancelled(&self) -> bool {
+        self.cancel.as_ref().is_some_and(|c| c.is_cancelled())
+            || self.failed.lock().unwrap().is_some()
+    }
+
+    /// Stores a node created by the build. A write error stops the build.
+    fn insert(&self, item: i64, node: D::Node) {
+        match &self.store {
+            Some(store) => {
+                if let Err(e) = store.lock().unwrap().append(item, &node) {
+                    self.failed.lock().unwrap().get_or_insert(e);
+                }
+            }
+            None => {
+                self.nodes.write().unwrap().insert(item, node);
+            }
+        }
+    }
 }
 
 impl<T: Item + Sync + Send, D: Distance<T>> AnnoyThreadBuilder<T, D>
@@ -40,87 +63,237 @@ where
             n_nodes: AtomicI64::new(n_nodes),
             nodes: RwLock::new(nodes),
             roots: RwLock::new(roots),
+            cancel: None,
+            store: None,
+            failed: Mutex::new(None),
         }
     }
 
-    pub fn build(annoy: Arc<Mutex<&mut Annoy<T, D>>>, n_thread: usize, q: i64)
+    /// Builds the trees described by `options` into `annoy`. Trees finished
+    /// before a cancellation are kept and the nodes of the interrupted ones
+    /// are dropped.
+    ///
+    /// The nodes of `annoy` are moved into the builder for the duration of
+    /// the build and moved back afterwards.
+    #[allow(non_snake_case)]
+    pub fn build(
+        annoy: Arc<Mutex<&mut Annoy<T, D>>>,
+        options: BuildOptions<'_>,
+    ) -> Result<(), Error>
     where
         T: Item + Sync + Send + 'static,
         D: Distance<T> + 'static,
         D::Node: Send + Sync,
     {
-        let rt = Builder::new_current_thread().enable_all().build().unwrap();
-
-        let (_nodes, _f, _K, _n_items, _n_nodes, _roots) = {
-            let ann = annoy.lock().unwrap();
+        let (_nodes, _f, _K, _n_items, _n_nodes, _roots, _seed, store) = {
+            let mut ann = annoy.lock().unwrap();
             (
-                ann._nodes.clone(),
+                std::mem::take(&mut ann._nodes),
                 ann._f,
                 ann._K,
                 ann._n_items,
                 ann._n_nodes,
-                ann._roots.clone(),
+                std::mem::take(&mut ann._roots),
+                ann._seed,
+                ann._store.take(),
             )
         };
 
-        let thread_policy = Arc::new(Self::new(_n_nodes, _nodes, _roots));
-        let mut threads = vec![];
+        let BuildOptions {
+            n_trees: q,
+            n_threads: n_thread,
+            progress,
+            cancel,
+        } = options;
+
+        let mut thread_policy = Self::new(_n_nodes, _nodes, _roots);
+        thread_policy.cancel = cancel;
+        thread_policy.store = store.map(Mutex::new);
+
+        // The items go first so that the file holds a complete index. They
+        // stay in memory until the trees are built.
+        if let Some(store) = thread_policy.store.as_mut() {
+            let store = store.get_mut().unwrap();
+            let nodes = thread_policy.nodes.get_mut().unwrap();
+
+            for (i, node) in nodes.iter().filter(|(i, _)| **i < _n_items) {
+                if let Err(e) = store.append(*i, node) {
+                    *thread_policy.failed.get_mut().unwrap() = Some(e);
+                    break;
+                }
+            }
+        }
+
+        // One generator per worker, each building its share of the trees.
+        let mut workers: Vec<(StdRng, i64)> = (0..n_thread)
+            .map(|thread_idx| {
+                let rng = match _seed {
+                    Some(seed) => StdRng::seed_from_u64(seed.wrapping_add(thread_idx as u64)),
+                    None => StdRng::from_entropy(),
+                };
+                let trees_per_thread = if q == -1 {
+                    -1
+                } else {
+                    (q + thread_idx as i64) / n_thread as i64
+                };
+                (rng, trees_per_thread)
+            })
+            .collect();
+
+        let indices: Vec<i64> = {
+            let nodes = thread_policy.nodes.read().unwrap();
+            (0.._n_items)
+                .filter(|i| nodes.get(i).is_some_and(|n| n.descendant() >= 1))
+                .collect()
+        };
 
-        for thread_idx in 0..n_thread {
-            let trees_per_thread = if q == -1 {
-                -1
-            } else {
-                (q + thread_idx as i64) / n_thread as i64
-            };
-            let thread_policy = Arc::clone(&thread_policy);
-
-            let handle = rt.spawn(async move {
-                let mut thread_roots = Vec::new();
-
-                loop {
-                    if q == -1 {
-                        {
-                            if thread_policy.n_nodes.load(SeqCst) >= _n_items * 2 {
-                                break;
-                            }
-                        }
-                    } else if thread_roots.len() >= (trees_per_thread as usize) {
-                        break;
-                    }
+        // Every worker builds one tree per round. The trees are numbered and
+        // stored in worker order once the round is over, so that a seed
+        // gives the same index whether or not the workers ran in parallel.
+        let start = Instant::now();
+        let mut progress = progress;
+        let mut trees_built = 0;
+        let done = |policy: &Self| q == -1 && policy.n_nodes.load(SeqCst) >= _n_items * 2;
 
-                    let mut indices: Vec<i64> = Vec::new();
-                    {
-                        let _nodes = thread_policy.nodes.read().unwrap();
-                        for i in 0.._n_items {
-                            if let Some(n) = _nodes.get(&i) {
-                                if n.descendant() >= 1 {
-                                    indices.push(i)
-                                }
-                            }
-                        }
-                    }
+        for round in 0.. {
+            if done(&thread_policy) || thread_policy.is_cancelled() {
+                break;
+            }
 
-                    let ind = _make_tree::<D, T>(&*thread_policy, _f, _K, _n_items, true, &indices);
+            let rngs: Vec<&mut StdRng> = workers
+                .iter_mut()
+                .filter(|(_, trees)| *trees == -1 || *trees > round)
+                .map(|(rng, _)| rng)
+                .collect();
+            if rngs.is_empty() {
+                break;
+            }
 
-                    thread_roots.push(ind);
+            for tree in thread_policy
+                .build_round(rngs, &indices, _f, _K, _n_items)
+                .into_iter()
+                .flatten()
+            {
+                if done(&thread_policy) {
+                    break;
                 }
 
-                {
-                    let mut _roots = thread_policy.roots.write().unwrap();
-                    _roots.append(&mut thread_roots);
-                }
-            });
+                thread_policy.commit(tree, _n_items);
+                trees_built += 1;
 
-            threads.push(handle);
+                if let Some(progress) = progress.as_mut() {
+                    progress(&BuildProgress {
+                        trees_built,
+                        nodes_created: (thread_policy.n_nodes.load(SeqCst) - _n_nodes) as usize,
+                        elapsed: start.elapsed(),
+                    });
+                }
+            }
         }
 
-        rt.block_on(future::join_all(threads));
+        let AnnoyThreadBuilder {
+            n_nodes,
+            nodes,
+            roots,
+            cancel,
+            store,
+            failed,
+        } = thread_policy;
+
+        let mut nodes = nodes.into_inner().unwrap();
+        let roots = roots.into_inner().unwrap();
+        let mut store = store.map(|store| store.into_inner().unwrap());
+        let mut failed = failed.into_inner().unwrap();
+        let cancelled = cancel.is_some_and(|c| c.is_cancelled());
+
+        if let Some(store) = store.as_mut() {
+            if let Err(e) = store.finish() {
+                failed.get_or_insert(e);
+            }
+        }
+        if cancelled || failed.is_some() {
+            let reachable = reachable::<T, D>(&nodes, store.as_ref(), &roots, _n_items);
+            nodes.retain(|i, _| *i < _n_items || reachable.contains(i));
+            if let Some(store) = store.as_mut() {
+                store.retain(|i| i < _n_items || reachable.contains(&i));
+            }
+        }
+        // Once the file is complete, items are read from it like the rest.
+        if store.is_some() && failed.is_none() {
+            nodes.clear();
+        }
 
         let mut ann = annoy.lock().unwrap();
-        ann._n_nodes = thread_policy.n_nodes.load(SeqCst);
-        ann._roots = thread_policy.roots.read().unwrap().clone();
-        ann._nodes = thread_policy.nodes.read().unwrap().clone();
+        ann._n_nodes = n_nodes.into_inner();
+        ann._roots = roots;
+        ann._nodes = nodes;
+        ann._store = store;
+
+        match failed {
+            Some(e) => Err(Error::Io(e)),
+            None if cancelled => Err(Error::Cancelled),
+            None => Ok(()),
+        }
+    }
+
+    /// Builds a tree with each generator, each on a thread of its own with
+    /// the `parallel` feature. A tree is `None` when the build was
+    /// cancelled before it was finished.
+    #[allow(non_snake_case)]
+    fn build_round(
+        &self,
+        rngs: Vec<&mut StdRng>,
+        indices: &[i64],
+        _f: usize,
+        _K: usize,
+        _n_items: i64,
+    ) -> Vec<Option<Tree<D::Node>>> {
+        let build = |rng: &mut StdRng| {
+            let mut nodes = Vec::new();
+            let root =
+                _make_tree::<D, T, _>(self, &mut nodes, rng, _f, _K, _n_items, true, indices)?;
+            Some(Tree { root, nodes })
+        };
+
+        #[cfg(feature = "parallel")]
+        {
+            std::thread::scope(|s| {
+                let handles: Vec<_> = rngs
+                    .into_iter()
+                    .map(|rng| s.spawn(move || build(rng)))
+                    .collect();
+
+                handles.into_iter().map(|h| h.join().unwrap()).collect()
+            })
+        }
+
+        #[cfg(not(feature = "parallel"))]
+        {
+            rngs.into_iter().map(build).collect()
+        }
     }
+
+    /// Numbers the nodes of `tree` from the current node count on, stores
+    /// them and adds the tree to the roots.
+    fn commit(&self, tree: Tree<D::Node>, _n_items: i64) {
+        let base = self.n_nodes.fetch_add(tree.nodes.len() as i64, SeqCst);
+        let id = |i: i64| if i < _n_items { i } else { base + i - _n_items };
+
+        for (k, mut node) in tree.nodes.into_iter().enumerate() {
+            let children = node.children().into_iter().map(id).collect();
+            node.set_children(children);
+            self.insert(base + k as i64, node);
+        }
+
+        self.roots.write().unwrap().push(id(tree.root));
+    }
+}
+
+/// A tree built by one worker. Its nodes are numbered from `_n_items` on in
+/// the order of `nodes` until the tree is committed.
+struct Tree<N> {
+    root: i64,
+    nodes: Vec<N>,
 }
 
 #[allow(non_snake_case)]
@@ -128,15 +301,20 @@ pub struct Annoy<T: Item, D>
 where
     D: Distance<T>,
 {
-    pub _f: usize,
-    pub _K: usize,
-    pub _n_nodes: i64,
-    pub _n_items: i64,
+    pub(crate) _f: usize,
+    pub(crate) _K: usize,
+    pub(crate) _n_nodes: i64,
+    pub(crate) _n_items: i64,
 
-    pub _nodes: HashMap<i64, D::Node>,
-    pub _roots: Vec<i64>,
+    pub(crate) _nodes: HashMap<i64, D::Node>,
+    pub(crate) _roots: Vec<i64>,
+    pub(crate) _seed: Option<u64>,
 
-    pub t: PhantomData<T>,
+    t: PhantomData<T>,
+
+    _store: Option<DiskStore>,
+    // Set by a build or load; items can only be added while it is unset.
+    _built: bool,
 }
 
 impl<T: Item + Sync + Send + 'static, D: Distance<T>> Annoy<T, D> {
@@ -148,11 +326,21 @@ impl<T: Item + Sync + Send + 'static, D: Distance<T>> Annoy<T, D> {
             _n_nodes: 0,
             _f: f,
             _K: 6,
+            _seed: None,
             t: PhantomData,
+            _store: None,
+            _built: false,
         }
     }
 
-    pub fn add_item(&mut self, item: i64, w: &[T]) {
+    /// Adds or replaces `item`. Fails with `Error::AlreadyBuilt` once the
+    /// index is built; `unbuild` first to add more items.
+    pub fn add_item(&mut self, item: i64, w: &[T]) -> Result<(), Error> {
+        if self._built {
+            return Err(Error::AlreadyBuilt);
+        }
+        check_vector(w, self._f)?;
+
         let f = self._f;
         let n = self._nodes.entry(item).or_insert(D::Node::new(f));
         n.reset(w);
@@ -160,154 +348,424 @@ impl<T: Item + Sync + Send + 'static, D: Distance<T>> Annoy<T, D> {
         if item >= self._n_items {
             self._n_items = item + 1;
         }
+
+        Ok(())
     }
 
+    pub fn set_seed(&mut self, seed: u64) {
+        self._seed = Some(seed);
+    }
+
+    /// Number of values in every vector.
+    pub fn dimension(&self) -> usize {
+        self._f
+    }
+
+    /// One more than the largest item id, as in Annoy. Ids below it that
+    /// were never added count too.
+    pub fn n_items(&self) -> usize {
+        self._n_items as usize
+    }
+
+    pub fn n_trees(&self) -> usize {
+        self._roots.len()
+    }
+
+    pub fn contains(&self, item: i64) -> bool {
+        (0..self._n_items).contains(&item)
+            && (self._nodes.contains_key(&item)
+                || self
+                    ._store
+                    .as_ref()
+                    .is_some_and(|store| store.contains(item)))
+    }
+
+    /// The vector of `item`, read from the file of an index built on disk.
+    pub fn get_item_vector(&self, item: i64) -> Option<Cow<'_, [T]>> {
+        if item < 0 || item >= self._n_items {
+            return None;
+        }
+
+        self._try_node(item).map(|node| match node {
+            Cow::Borrowed(node) => Cow::Borrowed(node.as_slice()),
+            Cow::Owned(node) => Cow::Owned(node.as_slice().to_vec()),
+        })
+    }
+
+    /// Iterates over the added items and their vectors in id order.
+    pub fn items(&self) -> impl Iterator<Item = (i64, Cow<'_, [T]>)> + '_ {
+        (0..self._n_items).filter_map(move |i| self.get_item_vector(i).map(|v| (i, v)))
+    }
+
+    /// Makes the next build write its tree nodes to the file at `path`
+    /// and items to the file at `path` instead of keeping them in memory.
+    /// Once built, the file is a complete index that `load` accepts, so
+    /// there is no need to `save`; queries read the nodes back from it.
+    pub fn on_disk_build<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Error> {
+        if self._built {
+            return Err(Error::AlreadyBuilt);
+        }
+
+        self._store = Some(DiskStore::create(path.as_ref())?);
+        Ok(())
+    }
+
+    pub fn is_built(&self) -> bool {
+        self._built
+    }
+
+    /// Panics if the index is already built; `build_with` returns the
+    /// error instead.
     pub fn build(&mut self, q: i64)
     where
         D: 'static,
         T: 'static,
         <D as Distance<T>>::Node: Sync + Send,
     {
+        self.build_with_threads(q, DEFAULT_N_THREADS);
+    }
+
+    pub fn build_with_threads(&mut self, q: i64, n_threads: usize)
+    where
+        D: 'static,
+        T: 'static,
+        <D as Distance<T>>::Node: Sync + Send,
+    {
+        if let Err(e) = self.build_with(BuildOptions::new(q).n_threads(n_threads)) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Builds trees as configured by `options`. Fails with
+    /// `Error::AlreadyBuilt` if the index is built; see `rebuild_with`.
+    ///
+    /// When cancelled the index keeps the trees finished so far, is usable
+    /// as such, and `Error::Cancelled` is returned.
+    pub fn build_with(&mut self, options: BuildOptions<'_>) -> Result<(), Error>
+    where
+        D: 'static,
+        T: 'static,
+        <D as Distance<T>>::Node: Sync + Send,
+    {
+        if self._built {
+            return Err(Error::AlreadyBuilt);
+        }
+
         self._n_nodes = self._n_items;
-        AnnoyThreadBuilder::build(Arc::new(Mutex::new(self)), 10, q);
+        self._built = true;
+        AnnoyThreadBuilder::build(Arc::new(Mutex::new(self)), options)
+    }
+
+    /// Drops the trees and keeps the items, so that items can be added and
+    /// the index built again. An index built on disk forgets its file and
+    /// builds in memory unless `on_disk_build` is called again.
+    pub fn unbuild(&mut self) -> Result<(), Error> {
+        if !self._built {
+            return Err(Error::NotBuilt);
+        }
+
+        self._restore_items()?;
+
+        let n_items = self._n_items;
+        self._nodes.retain(|i, _| *i < n_items);
+        self._roots.clear();
+        self._n_nodes = n_items;
+        self._store = None;
+        self._built = false;
+
+        Ok(())
+    }
+
+    /// Replaces the trees with `n_trees` new ones.
+    pub fn rebuild(&mut self, n_trees: i64) -> Result<(), Error>
+    where
+        D: 'static,
+        T: 'static,
+        <D as Distance<T>>::Node: Sync + Send,
+    {
+        self.rebuild_with(BuildOptions::new(n_trees))
+    }
+
+    /// Builds new trees as configured by `options` and replaces the current
+    /// ones with them. If the build is cancelled or fails, the current trees
+    /// are kept and none of the new ones.
+    pub fn rebuild_with(&mut self, options: BuildOptions<'_>) -> Result<(), Error>
+    where
+        D: 'static,
+        T: 'static,
+        <D as Distance<T>>::Node: Sync + Send,
+    {
+        self._restore_items()?;
+
+        let n_items = self._n_items;
+        let (items, trees): (HashMap<_, _>, HashMap<_, _>) = std::mem::take(&mut self._nodes)
+            .into_iter()
+            .partition(|(i, _)| *i < n_items);
+        self._nodes = items;
+
+        let roots = std::mem::take(&mut self._roots);
+        let n_nodes = self._n_nodes;
+        let store = self._store.take();
+        let built = std::mem::replace(&mut self._built, false);
+
+        let result = self.build_with(options);
+        if result.is_err() {
+            // The items of an index built on disk are read from its file.
+            if store.is_some() {
+                self._nodes.clear();
+            } else {
+                self._nodes.retain(|i, _| *i < n_items);
+            }
+            self._nodes.extend(trees);
+            self._roots = roots;
+            self._n_nodes = n_nodes;
+            self._store = store;
+            self._built = built;
+        }
+
+        result
     }
 
+    /// Reads the items of an index built on disk back into memory, ahead
+    /// of building without the file.
+    fn _restore_items(&mut self) -> Result<(), Error> {
+        let store = match &self._store {
+            Some(store) => store,
+            None => return Ok(()),
+        };
+
+        for i in 0..self._n_items {
+            if let Entry::Vacant(entry) = self._nodes.entry(i) {
+                if let Some(node) = store.get(i)? {
+                    entry.insert(node);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Panics if `v` contains NaN or an infinity; `query` returns the error
+    /// instead.
     pub fn get_nns_by_vector(&self, v: &[T], n: usize, search_k: i64) -> (Vec<i64>, Vec<f64>)
     where
         D: Distance<T>,
     {
-        self._get_all_nns(v, n, search_k)
+        match self.query(v, QueryOptions::new(n).search_k(search_k)) {
+            Ok(neighbors) => neighbors.into_parts(),
+            Err(e) => panic!("{}", e),
+        }
     }
 
     pub fn get_nns_by_item(&self, item: i64, n: usize, search_k: i64) -> (Vec<i64>, Vec<f64>)
     where
         D: Distance<T>,
     {
-        let m = self._nodes.get(&item).unwrap();
-        let v = m.as_slice();
+        let v = self.get_item_vector(item).unwrap();
+        let options = QueryOptions::new(n).search_k(search_k);
+
+        self._query_iter(&v, options)
+            .take(n)
+            .collect::<Neighbors<T>>()
+            .into_parts()
+    }
+
+    /// Finds the neighbors of `v` as configured by `options`. Fails with
+    /// `Error::DimensionMismatch` if `v` does not have the index's dimension
+    /// and `Error::NonFinite` if it contains NaN or an infinity.
+    pub fn query(&self, v: &[T], options: QueryOptions) -> Result<Neighbors<T>, Error> {
+        Ok(self.query_iter(v, options)?.take(options.n).collect())
+    }
 
-        self._get_all_nns(v, n, search_k)
+    /// Like `query` for the vector of `item`, or `None` if there is no such
+    /// item.
+    pub fn query_by_item(&self, item: i64, options: QueryOptions) -> Option<Neighbors<T>> {
+        let v = self.get_item_vector(item)?;
+
+        Some(self._query_iter(&v, options).take(options.n).collect())
+    }
+
+    /// Yields every candidate the search for `v` finds, nearest first, with
+    /// ties in id order. The `n` of `options` only sets the default
+    /// `search_k`; stop taking neighbors whenever enough were seen.
+    pub fn query_iter(
+        &self,
+        v: &[T],
+        options: QueryOptions,
+    ) -> Result<NeighborIter<'_, T, D>, Error> {
+        check_vector(v, self._f)?;
+
+        Ok(self._query_iter(v, options))
+    }
+
+    // Stored vectors were checked by `add_item`.
+    fn _query_iter(&self, v: &[T], options: QueryOptions) -> NeighborIter<'_, T, D> {
+        let heap = self
+            ._candidates(v, options.n, options.search_k)
+            .into_iter()
+            .map(|j| {
+                let dist = D::distance(v, self._node(j).as_slice(), self._f);
+                Reverse(AnnResult(dist, j))
+            })
+            .collect();
+
+        NeighborIter::new(self, heap, options)
     }
 
-    fn _get_all_nns(&self, v: &[T], n: usize, mut search_k: i64) -> (Vec<i64>, Vec<f64>)
+    /// Ids of the items the search for `v` inspects, without duplicates.
+    ///
+    /// Like Annoy, nodes are explored best first by `D::pq_distance` and
+    /// `search_k` bounds the items inspected, counting an item again
+    /// each time another tree reaches it. A leaf holding several items
+    /// spends one unit of `search_k` per item and is cut short when the
+    /// budget runs out.
+    fn _candidates(&self, v: &[T], n: usize, mut search_k: i64) -> Vec<i64>
     where
         D: Distance<T>,
     {
-        let mut nodes = self._nodes.clone();
-        let mut q: BinaryHeap<(Numeric<T>, i64)> = BinaryHeap::new();
-        let f = self._f;
+        // Priorities are kept negated, so that the highest pops first and
+        // NaN, ranked like a NaN distance, pops last.
+        let mut q: BinaryHeap<Reverse<AnnResult>> = BinaryHeap::new();
 
         if search_k == -1 {
             search_k = (n as i64) * self._roots.len() as i64;
         }
+        let search_k = search_k.max(0) as usize;
 
         for root in self._roots.iter() {
-            q.push((Numeric(T::zero()), *root))
+            q.push(Reverse(AnnResult(-D::pq_initial_value(), *root)))
         }
 
         let mut nns: Vec<i64> = Vec::new();
-        while nns.len() < (search_k as usize) && !q.is_empty() {
-            let top = q.peek().unwrap();
-            let d: T = top.0 .0;
-            let i = top.1;
-            let nd = nodes.entry(i).or_insert_with(|| D::Node::new(f));
-
-            q.pop();
+        while nns.len() < search_k {
+            let (d, i) = match q.pop() {
+                Some(Reverse(AnnResult(d, i))) => (-d, i),
+                None => break,
+            };
+            let nd = self._node(i);
+            let nd = nd.as_ref();
 
             if nd.descendant() == 1 && i < self._n_items {
                 nns.push(i);
-            } else if nd.descendant() as usize <= self._K {
+            } else if nd.descendant() <= self._K {
                 let dst = nd.children();
-                nns.extend(dst);
+                let take = dst.len().min(search_k - nns.len());
+                nns.extend_from_slice(&dst[..take]);
             } else {
                 let margin = D::margin(nd, v);
-                let a = T::zero() + margin;
-                let b = T::zero() - margin;
+                let children = nd.children();
 
-                let a = Numeric(a);
-                let b = Numeric(b);
-
-                q.push((Numeric(d).min(a), nd.children()[1]));
-                q.push((Numeric(d).min(b), nd.children()[0]));
+                for child in [1, 0] {
+                    let priority = D::pq_distance(d, margin, child);
+                    q.push(Reverse(AnnResult(-priority, children[child])));
+                }
             }
         }
 
         nns.sort_unstable();
-
-        let mut nns_dist: BinaryHeap<AnnResult<T>> = BinaryHeap::new();
-        let mut last = -1;
-
-        for j in &nns {
-            if *j == last {
-                continue;
-            }
-
-            last = *j;
-            let mut _n = nodes.entry(*j).or_insert_with(|| D::Node::new(f));
-            let dist = D::distance(v, _n.as_slice(), self._f);
-            nns_dist.push(AnnResult(dist, *j));
-        }
-
-        let nns_dist = nns_dist.into_sorted_vec();
-        let m = nns_dist.len();
-        let p = if n < m { n } else { m };
-
-        let mut distances = Vec::new();
-        let mut result = Vec::new();
-
-        for AnnResult(dist, idx) in nns_dist.iter().take(p) {
-            distances.push(D::normalized_distance(T::to_f64(dist).unwrap()));
-            result.push(*idx)
-        }
-
-        (result, distances)
+        nns.dedup();
+        nns
     }
 
     pub fn save<W>(&self, w: W)
     where
         W: std::io::Write,
     {
+        self.try_save(w).unwrap();
+    }
+
+    /// Like `save`, but returns the error from writing instead of panicking.
+    pub fn try_save<W: io::Write>(&self, w: W) -> io::Result<()> {
         let mut f = BufWriter::new(w);
-        bincode::serialize_into(&mut f, &self._nodes).unwrap();
+
+        match &self._store {
+            Some(store) => {
+                store.copy_to(&mut f)?;
+            }
+            None => {
+                let mut f = ChecksumWriter::new(&mut f);
+                format::write_header(&mut f, Kind::Annoy)?;
+                bincode::serialize_into(&mut f, &self._nodes).map_err(io::Error::other)?;
+                f.finish()?;
+            }
+        }
+
+        f.flush()
     }
 
+    /// Reads an index written by `save`. Returns false when the data cannot
+    /// be decoded, is of another format version or index type or does not
+    /// match its checksum, leaving the index as it was. The dimension is
+    /// taken from the loaded items.
     pub fn load<R>(&mut self, reader: R) -> bool
     where
         R: std::io::BufRead,
     {
-        let mut m = -1;
-
-        self._nodes = bincode::deserialize_from(reader).unwrap();
-        self._roots = Vec::default();
+        self.try_load(reader).is_ok()
+    }
 
-        for (i, node) in self._nodes.iter() {
-            let k = node.descendant() as i64;
-
-            if m == -1 || k == m {
-                self._roots.push(*i);
-                m = k;
-            } else {
-                break;
-            }
-        }
+    /// Like `load`, but returns why the index could not be read.
+    pub(crate) fn try_load<R: io::BufRead>(&mut self, reader: R) -> io::Result<()> {
+        let mut reader = ChecksumReader::new(reader);
+        format::read_header(&mut reader, Kind::Annoy)?;
+        let nodes = bincode::deserialize_from(&mut reader).map_err(format::to_io)?;
+        reader.verify()?;
+
+        self._nodes = nodes;
+        self._store = None;
+        self._built = true;
+
+        // Every root covers all items, so roots are the nodes with the largest descendant count.
+        let m = self
+            ._nodes
+            .values()
+            .map(|node| node.descendant() as i64)
+            .max()
+            .unwrap_or(0);
+
+        self._roots = self
+            ._nodes
+            .iter()
+            .filter(|(_, node)| node.descendant() as i64 == m)
+            .map(|(i, _)| *i)
+            .collect();
+        self._roots.sort_unstable();
 
         self._n_items = m;
+        self._n_nodes = self._nodes.keys().max().map_or(0, |i| i + 1);
+        let f = self.items().next().map(|(_, v)| v.len());
+        self._f = f.unwrap_or(self._f);
 
-        true
+        Ok(())
     }
 
-    fn _get(&self, i: i64) -> &D::Node {
-        &self._nodes[&i]
+    /// Looks a node up in memory, then in the on-disk store. Unknown ids
+    /// read as an empty node.
+    fn _node(&self, i: i64) -> Cow<'_, D::Node> {
+        self._try_node(i)
+            .unwrap_or_else(|| Cow::Owned(D::Node::new(self._f)))
+    }
+
+    pub(crate) fn _try_node(&self, i: i64) -> Option<Cow<'_, D::Node>> {
+        if let Some(node) = self._nodes.get(&i) {
+            return Some(Cow::Borrowed(node));
+        }
+
+        self._store
+            .as_ref()
+            .and_then(|store| store.get(i).ok().flatten())
+            .map(Cow::Owned)
     }
 
-    pub fn get_distance(self, i: i64, j: i64) -> f64 {
-        let dist = D::distance(self._get(i).as_slice(), self._get(j).as_slice(), self._f);
-        D::normalized_distance(dist.to_f64().unwrap_or(0.))
+    pub fn get_distance(&self, i: i64, j: i64) -> f64 {
+        let dist = D::distance(self._node(i).as_slice(), self._node(j).as_slice(), self._f);
+        D::normalized_distance(dist)
     }
 }
 
-fn random_split_index<T, D>(
+fn random_split_index<T, D, R>(
     _nodes: &HashMap<i64, D::Node>,
+    rng: &mut R,
     _f: usize,
     m: &mut D::Node,
     indices: &[i64],
@@ -316,20 +774,20 @@ fn random_split_index<T, D>(
 where
     T: Item + Sync + Send,
     D: Distance<T>,
+    R: Rng,
 {
-    let mut rng = thread_rng();
-    D::create_split(children, m, _f, &mut rng);
+    D::create_split(children, m, _f, rng);
 
     let mut children_indices = (Vec::new(), Vec::new());
 
     for i in indices.iter() {
         if let Some(n) = _nodes.get(i) {
-            let side = D::side(m, n.as_slice(), &mut rng);
-
-            if side {
-                children_indices.0.push(*i);
-            } else {
+            // Child 1 takes the side the query favours, see
+            // `Distance::pq_distance`.
+            if D::side(m, n.as_slice(), rng) {
                 children_indices.1.push(*i);
+            } else {
+                children_indices.0.push(*i);
             }
         }
     }
@@ -350,44 +808,42 @@ where
     children_indices
 }
 
-fn _make_tree<D, T>(
+#[allow(non_snake_case, clippy::too_many_arguments)]
+fn _make_tree<D, T, R>(
     thread_policy: &AnnoyThreadBuilder<T, D>,
+    tree: &mut Vec<D::Node>,
+    rng: &mut R,
     _f: usize,
     _K: usize,
     _n_items: i64,
     is_root: bool,
     indices: &[i64],
-) -> i64
+) -> Option<i64>
 where
     T: Item + Sync + Send,
     D: Distance<T>,
+    R: Rng,
 {
     if indices.len() == 1 && !is_root {
-        return indices[0];
+        return Some(indices[0]);
     }
 
-    if indices.len() <= (_K as usize) && (!is_root || _n_items <= (_K as i64) || indices.len() == 1)
-    {
-        let item = {
-            let item = thread_policy.n_nodes.load(SeqCst);
-            thread_policy.n_nodes.fetch_add(1, SeqCst);
-            item
-        };
-
-        {
-            let mut _nodes = thread_policy.nodes.write().unwrap();
-            let m = _nodes.entry(item).or_insert(D::Node::new(_f));
-
-            m.set_descendant(if is_root {
-                _n_items as usize
-            } else {
-                indices.len()
-            });
+    if indices.len() <= _K && (!is_root || _n_items <= (_K as i64) || indices.len() == 1) {
+        let mut m = D::Node::new(_f);
+        m.set_descendant(if is_root {
+            _n_items as usize
+        } else {
+            indices.len()
+        });
+        m.set_children(indices.to_owned());
+        tree.push(m);
 
-            m.set_children(indices.to_owned());
-        }
+        return Some(_n_items + tree.len() as i64 - 1);
+    }
 
-        return item;
+    // Returning None abandons the tree along with its finished subtrees.
+    if thread_policy.is_cancelled() {
+        return None;
     }
 
     let mut m = D::Node::new(_f);
@@ -401,7 +857,7 @@ where
             }
         });
 
-        random_split_index::<T, D>(&_nodes, _f, &mut m, indices, &children)
+        random_split_index::<T, D, R>(&_nodes, rng, _f, &mut m, indices, &children)
     };
 
     let flip = (children_indices.0.len() > children_indices.1.len()) as usize;
@@ -420,22 +876,317 @@ where
         };
 
         let mut v = m.children();
-        v[ii] = _make_tree::<D, T>(thread_policy, _f, _K, _n_items, false, a);
+        v[ii] = _make_tree::<D, T, R>(thread_policy, tree, rng, _f, _K, _n_items, false, a)?;
 
         m.set_children(v);
     }
 
-    let item = {
-        let item = thread_policy.n_nodes.load(SeqCst);
-        thread_policy.n_nodes.fetch_add(1, SeqCst);
-        item
-    };
+    tree.push(m);
 
-    {
-        let mut _nodes = thread_policy.nodes.write().unwrap();
-        let n = _nodes.entry(item).or_insert_with(|| D::Node::new(_f));
-        n.copy(m);
+    Some(_n_items + tree.len() as i64 - 1)
+}
+
+/// The nodes some root leads to, not counting items.
+fn reachable<T, D>(
+    nodes: &HashMap<i64, D::Node>,
+    store: Option<&DiskStore>,
+    roots: &[i64],
+    n_items: i64,
+) -> HashSet<i64>
+where
+    T: Item,
+    D: Distance<T>,
+{
+    let mut reachable = HashSet::new();
+    let mut stack = roots.to_vec();
+
+    while let Some(i) = stack.pop() {
+        if i < n_items || !reachable.insert(i) {
+            continue;
+        }
+
+        let node = match nodes.get(&i) {
+            Some(node) => Some(node.clone()),
+            None => store.and_then(|store| store.get::<D::Node>(i).ok().flatten()),
+        };
+        if let Some(node) = node {
+            stack.extend(node.children());
+        }
+    }
+
+    reachable
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Euclidean;
+
+    fn grid(ann: &mut Annoy<f64, Euclidean>) {
+        for i in 0..100 {
+            ann.add_item(i, &[(i % 10) as f64, (i / 10) as f64])
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_save_load_roots() {
+        let mut ann: Annoy<f64, Euclidean> = Annoy::new(2);
+        grid(&mut ann);
+        ann.build(5);
+
+        let mut buf = Vec::new();
+        ann.save(&mut buf);
+
+        let mut loaded: Annoy<f64, Euclidean> = Annoy::new(2);
+        loaded.load(buf.as_slice());
+
+        let mut roots = ann._roots.clone();
+        roots.sort_unstable();
+
+        assert_eq!(loaded._roots, roots);
+        assert_eq!(loaded._n_items, 100);
+    }
+
+    #[test]
+    fn test_accessors() {
+        let mut ann: Annoy<f64, Euclidean> = Annoy::new(2);
+        ann.add_item(0, &[1., 2.]).unwrap();
+        ann.add_item(3, &[3., 4.]).unwrap();
+        ann.build(2);
+
+        assert_eq!(ann.dimension(), 2);
+        assert_eq!(ann.n_items(), 4);
+        assert_eq!(ann.n_trees(), 2);
+        assert!(ann.contains(3) && !ann.contains(1) && !ann.contains(-1));
+        assert_eq!(ann.get_item_vector(3).as_deref(), Some(&[3., 4.][..]));
+        assert_eq!(ann.get_item_vector(4), None);
+        assert_eq!(
+            ann.items()
+                .map(|(i, v)| (i, v.into_owned()))
+                .collect::<Vec<_>>(),
+            vec![(0, vec![1., 2.]), (3, vec![3., 4.])]
+        );
+    }
+
+    #[test]
+    fn test_lifecycle() {
+        let mut ann: Annoy<f64, Euclidean> = Annoy::new(2);
+        assert!(matches!(ann.unbuild(), Err(Error::NotBuilt)));
+        grid(&mut ann);
+        ann.build(3);
+
+        assert!(ann.is_built());
+        assert!(matches!(
+            ann.add_item(100, &[0., 0.]),
+            Err(Error::AlreadyBuilt)
+        ));
+        assert!(matches!(
+            ann.build_with(BuildOptions::new(1)),
+            Err(Error::AlreadyBuilt)
+        ));
+        assert_eq!(ann.n_trees(), 3);
+
+        ann.unbuild().unwrap();
+        assert_eq!((ann.n_trees(), ann._nodes.len()), (0, 100));
+        ann.add_item(100, &[0.5, 0.5]).unwrap();
+        ann.build(2);
+        assert_eq!(ann.n_trees(), 2);
+        assert!(ann.verify().is_ok());
+
+        ann.rebuild(4).unwrap();
+        assert_eq!(ann.n_trees(), 4);
+        assert!(ann.verify().is_ok());
+
+        // A cancelled rebuild keeps the old trees and none of the new ones.
+        let roots = ann._roots.clone();
+        let nodes = ann._nodes.len();
+        let token = CancellationToken::new();
+        token.cancel();
+        let result = ann.rebuild_with(BuildOptions::new(5).cancel_token(token));
+        assert!(matches!(result, Err(Error::Cancelled)));
+        assert_eq!((&ann._roots, ann._nodes.len()), (&roots, nodes));
+        assert!(ann.verify().is_ok());
     }
 
-    item
+    #[test]
+    fn test_seeded_build() {
+        let build = |q: i64| {
+            let mut ann: Annoy<f64, Euclidean> = Annoy::new(2);
+            ann.set_seed(42);
+            grid(&mut ann);
+            ann.build_with_threads(q, 4);
+
+            let mut children: Vec<(i64, Vec<i64>)> =
+                ann._nodes.iter().map(|(i, n)| (*i, n.children())).collect();
+            children.sort();
+            (ann._roots.clone(), children)
+        };
+
+        assert_eq!(build(3), build(3));
+        // Builds until the node count doubles, which workers running in
+        // parallel must not race for.
+        assert_eq!(build(-1), build(-1));
+    }
+
+    #[test]
+    fn test_unsigned_items() {
+        // Far corners of the byte range, whose squared distances overflow
+        // u8 and i16.
+        let mut ann: Annoy<u8, Euclidean> = Annoy::new(2);
+        for i in 0..100u8 {
+            ann.add_item(i as i64, &[(i % 10) * 25, (i / 10) * 25])
+                .unwrap();
+        }
+        ann.build(3);
+
+        let found = ann
+            .query(&[250, 250], QueryOptions::new(100).search_k(10000))
+            .unwrap();
+        assert_eq!(found.ids()[..2], [99, 89]);
+        assert_eq!(found[1].raw_distance, (25 * 25 + 50 * 50) as f64);
+        assert_eq!(found[99].id, 0);
+        assert_eq!(found[99].raw_distance, (2 * 250 * 250) as f64);
+
+        // Bitsets that differ in the high bits of the first word.
+        let mut ann: Annoy<u64, crate::Hamming> = Annoy::new(2);
+        for i in 0..64 {
+            ann.add_item(i, &[u64::MAX << i, i as u64]).unwrap();
+        }
+        ann.build(5);
+
+        let found = ann.get_nns_by_vector(&[u64::MAX << 10, 10], 3, 1000);
+        assert_eq!(found.0, vec![10, 11, 8]);
+        assert_eq!(found.1, vec![0., 2., 3.]);
+    }
+
+    #[test]
+    fn test_build_progress() {
+        let mut ann: Annoy<f64, Euclidean> = Annoy::new(2);
+        grid(&mut ann);
+
+        let mut reported = Vec::new();
+        ann.build_with(
+            BuildOptions::new(5)
+                .n_threads(2)
+                .on_progress(|p| reported.push((p.trees_built, p.nodes_created))),
+        )
+        .unwrap();
+
+        assert_eq!(reported.len(), 5);
+        assert_eq!(
+            reported.iter().map(|r| r.0).collect::<Vec<_>>(),
+            vec![1, 2, 3, 4, 5]
+        );
+        assert!(reported.windows(2).all(|w| w[0].1 < w[1].1));
+        assert_eq!(ann._roots.len(), 5);
+    }
+
+    #[test]
+    fn test_build_cancelled() {
+        let mut ann: Annoy<f64, Euclidean> = Annoy::new(2);
+        grid(&mut ann);
+
+        let token = CancellationToken::new();
+        let cancel = token.clone();
+        let result = ann.build_with(
+            BuildOptions::new(10)
+                .n_threads(1)
+                .cancel_token(token)
+                .on_progress(move |p| {
+                    if p.trees_built == 2 {
+                        cancel.cancel();
+                    }
+                }),
+        );
+
+        assert!(matches!(result, Err(Error::Cancelled)));
+        assert_eq!(ann._roots.len(), 2);
+        assert_eq!(ann.get_nns_by_item(0, 1, -1).0.len(), 1);
+    }
+
+    #[test]
+    fn test_on_disk_build() {
+        let path = std::env::temp_dir().join(format!("little-annoy-{}.ann", std::process::id()));
+        let build = |on_disk: bool| {
+            let mut ann: Annoy<f64, Euclidean> = Annoy::new(2);
+            ann.set_seed(3);
+            grid(&mut ann);
+            if on_disk {
+                ann.on_disk_build(&path).unwrap();
+            }
+            ann.build(4);
+            ann
+        };
+
+        let memory = build(false);
+        let mut disk = build(true);
+        // Neither the items nor the trees stay in memory.
+        assert!(disk._nodes.is_empty());
+        assert!(memory._nodes.len() > 100);
+
+        let query = |ann: &Annoy<f64, Euclidean>| ann.get_nns_by_vector(&[3.5, 4.5], 5, -1);
+        assert_eq!(query(&disk), query(&memory));
+        assert_eq!(disk.get_item_vector(34), memory.get_item_vector(34));
+        assert_eq!(disk.items().count(), 100);
+        assert!(disk.contains(99) && !disk.contains(100));
+        assert_eq!(disk.get_distance(0, 11), memory.get_distance(0, 11));
+        assert!(disk.verify().is_ok());
+        assert!(matches!(
+            disk.build_with(BuildOptions::new(1)),
+            Err(Error::AlreadyBuilt)
+        ));
+
+        let mut saved = Vec::new();
+        disk.save(&mut saved);
+        assert_eq!(saved, std::fs::read(&path).unwrap());
+
+        let mut loaded: Annoy<f64, Euclidean> = Annoy::new(2);
+        assert!(loaded.load(saved.as_slice()));
+        assert_eq!(loaded._nodes.len(), memory._nodes.len());
+        assert_eq!(query(&loaded), query(&memory));
+
+        disk.unbuild().unwrap();
+        assert_eq!(disk._nodes.len(), 100);
+        disk.build(4);
+        assert_eq!(query(&disk), query(&memory));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_cancel_between_splits() {
+        let mut ann: Annoy<f64, Euclidean> = Annoy::new(2);
+        grid(&mut ann);
+
+        let mut thread_policy: AnnoyThreadBuilder<f64, Euclidean> =
+            AnnoyThreadBuilder::new(100, ann._nodes.clone(), Vec::new());
+        let token = CancellationToken::new();
+        token.cancel();
+        thread_policy.cancel = Some(token);
+
+        let indices: Vec<i64> = (0..100).collect();
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut tree = Vec::new();
+        let root = _make_tree(
+            &thread_policy,
+            &mut tree,
+            &mut rng,
+            2,
+            6,
+            100,
+            true,
+            &indices,
+        );
+        assert_eq!(root, None);
+
+        // An orphaned leaf left behind by an abandoned tree.
+        let mut nodes = thread_policy.nodes.into_inner().unwrap();
+        let mut leaf = <Euclidean as Distance<f64>>::Node::new(2);
+        leaf.set_children(vec![1, 2]);
+        nodes.insert(100, leaf.clone());
+        nodes.insert(101, leaf);
+        let reachable = reachable::<f64, Euclidean>(&nodes, None, &[101], 100);
+        assert_eq!(reachable, vec![101].into_iter().collect());
+    }
 }
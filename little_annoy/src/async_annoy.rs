@@ -0,0 +1,118 @@
+use std::sync::Arc;
+
+use tokio::sync::RwLock;
+use tokio::task::{self, JoinError};
+
+use crate::ann::Annoy;
+use crate::distance::Distance;
+use crate::error::Error;
+use crate::item::Item;
+use crate::neighbor::Neighbors;
+use crate::options::{BuildOptions, QueryOptions};
+
+/// An `Annoy` for async code on a tokio runtime.
+///
+/// Builds and queries run on the blocking thread pool of the current
+/// runtime through `spawn_blocking`, so they neither stall its workers nor
+/// start a runtime of their own. A build holds the index exclusively, so
+/// queries wait for it to finish. Clones share the same index.
+pub struct AsyncAnnoy<T: Item, D: Distance<T>> {
+    inner: Arc<RwLock<Annoy<T, D>>>,
+}
+
+impl<T: Item, D: Distance<T>> Clone for AsyncAnnoy<T, D> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+/// Re-raises the panic of a blocking task. Tasks are only cancelled when
+/// the runtime shuts down, which drops the caller too.
+fn unwrap_join<R>(result: Result<R, JoinError>) -> R {
+    match result {
+        Ok(r) => r,
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
+
+impl<T, D> AsyncAnnoy<T, D>
+where
+    T: Item + Sync + Send + 'static,
+    D: Distance<T> + 'static,
+    D::Node: Sync + Send,
+{
+    pub fn new(ann: Annoy<T, D>) -> Self {
+        Self::from(ann)
+    }
+
+    /// The index, unless another clone still shares it.
+    pub fn into_inner(self) -> Option<Annoy<T, D>> {
+        Arc::try_unwrap(self.inner).ok().map(RwLock::into_inner)
+    }
+
+    /// Like `Annoy::add_item`, once no build or query holds the index.
+    pub async fn add_item(&self, item: i64, w: &[T]) -> Result<(), Error> {
+        self.inner.write().await.add_item(item, w)
+    }
+
+    /// Builds `n_trees` trees on the blocking thread pool; see
+    /// `Annoy::build_with`.
+    pub async fn build(&self, n_trees: i64) -> Result<(), Error> {
+        let mut ann = Arc::clone(&self.inner).write_owned().await;
+
+        unwrap_join(task::spawn_blocking(move || ann.build_with(BuildOptions::new(n_trees))).await)
+    }
+
+    /// Like `Annoy::query`, on the blocking thread pool.
+    pub async fn query(&self, v: &[T], options: QueryOptions) -> Result<Neighbors<T>, Error> {
+        let ann = Arc::clone(&self.inner).read_owned().await;
+        let v = v.to_vec();
+
+        unwrap_join(task::spawn_blocking(move || ann.query(&v, options)).await)
+    }
+
+    /// Like `Annoy::query_by_item`, on the blocking thread pool.
+    pub async fn query_by_item(&self, item: i64, options: QueryOptions) -> Option<Neighbors<T>> {
+        let ann = Arc::clone(&self.inner).read_owned().await;
+
+        unwrap_join(task::spawn_blocking(move || ann.query_by_item(item, options)).await)
+    }
+}
+
+impl<T: Item, D: Distance<T>> From<Annoy<T, D>> for AsyncAnnoy<T, D> {
+    fn from(ann: Annoy<T, D>) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(ann)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Euclidean;
+
+    #[tokio::test]
+    async fn test_build_query() {
+        let ann: AsyncAnnoy<f64, Euclidean> = Annoy::new(2).into();
+        for i in 0..100 {
+            ann.add_item(i, &[(i % 10) as f64, (i / 10) as f64])
+                .await
+                .unwrap();
+        }
+        ann.build(5).await.unwrap();
+        assert!(matches!(ann.build(5).await, Err(Error::AlreadyBuilt)));
+
+        let options = QueryOptions::new(3).search_k(1000);
+        let found = ann.query(&[2.2, 3.1], options).await.unwrap();
+        assert_eq!(found.ids(), vec![32, 33, 42]);
+        assert_eq!(ann.query_by_item(55, options).await.unwrap()[0].id, 55);
+
+        // The blocking calls work inside a runtime too.
+        let mut ann = ann.into_inner().unwrap();
+        ann.rebuild(2).unwrap();
+        assert_eq!(ann.n_trees(), 2);
+    }
+}
@@ -0,0 +1,177 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::io::{self, BufWriter};
+
+use crate::checksum::{ChecksumReader, ChecksumWriter};
+use crate::distance::{Distance, NodeImpl};
+use crate::error::{check_vector, Error};
+use crate::format::{self, Kind};
+use crate::item::Item;
+use crate::neighbor::{AnnResult, Neighbor, Neighbors};
+use crate::options::QueryOptions;
+
+/// The nearest `options.n` of `items`, nearest first.
+pub(crate) fn brute_force<'a, T, D, I>(
+    items: I,
+    v: &[T],
+    f: usize,
+    options: QueryOptions,
+) -> Vec<Neighbor<T>>
+where
+    T: Item + 'a,
+    D: Distance<T>,
+    I: Iterator<Item = (i64, &'a [T])>,
+{
+    let mut vectors = HashMap::new();
+    let mut heap: BinaryHeap<Reverse<AnnResult>> = items
+        .map(|(id, w)| {
+            vectors.insert(id, w);
+            Reverse(AnnResult(D::distance(v, w, f), id))
+        })
+        .collect();
+
+    let mut nns = Vec::new();
+    while nns.len() < options.n {
+        let Reverse(AnnResult(raw_distance, id)) = match heap.pop() {
+            Some(top) => top,
+            None => break,
+        };
+        nns.push(Neighbor::new::<D>(
+            id,
+            raw_distance,
+            options,
+            vectors.get(&id).copied(),
+        ));
+    }
+
+    nns
+}
+
+/// An exact index that compares the query with every item.
+///
+/// It needs no build, takes items at any time and ignores `search_k`, which
+/// makes it the reference to check approximate indexes against and a
+/// stand-in for them in tests.
+pub struct BruteForce<T: Item, D: Distance<T>> {
+    f: usize,
+    n_items: i64,
+    nodes: HashMap<i64, D::Node>,
+}
+
+impl<T: Item + Sync + Send + 'static, D: Distance<T>> BruteForce<T, D> {
+    pub fn new(f: usize) -> Self {
+        Self {
+            f,
+            n_items: 0,
+            nodes: HashMap::new(),
+        }
+    }
+
+    /// Adds or replaces `item`.
+    pub fn add_item(&mut self, item: i64, w: &[T]) -> Result<(), Error> {
+        check_vector(w, self.f)?;
+
+        let f = self.f;
+        let n = self.nodes.entry(item).or_insert(D::Node::new(f));
+        n.reset(w);
+
+        if item >= self.n_items {
+            self.n_items = item + 1;
+        }
+
+        Ok(())
+    }
+
+    /// Number of values in every vector.
+    pub fn dimension(&self) -> usize {
+        self.f
+    }
+
+    /// One more than the largest item id, as for `Annoy`.
+    pub fn n_items(&self) -> usize {
+        self.n_items as usize
+    }
+
+    pub fn contains(&self, item: i64) -> bool {
+        self.nodes.contains_key(&item)
+    }
+
+    pub fn get_item_vector(&self, item: i64) -> Option<&[T]> {
+        self.nodes.get(&item).map(|node| node.as_slice())
+    }
+
+    /// Iterates over the added items and their vectors in id order.
+    pub fn items(&self) -> impl Iterator<Item = (i64, &[T])> + '_ {
+        (0..self.n_items).filter_map(move |i| self.get_item_vector(i).map(|v| (i, v)))
+    }
+
+    /// Finds the `n` of `options` nearest neighbors of `v`, ties in id
+    /// order. Fails with `Error::DimensionMismatch` if `v` does not have
+    /// the index's dimension and `Error::NonFinite` if it contains NaN or an
+    /// infinity.
+    pub fn query(&self, v: &[T], options: QueryOptions) -> Result<Neighbors<T>, Error> {
+        check_vector(v, self.f)?;
+
+        Ok(self._query(v, options))
+    }
+
+    /// Like `query` for the vector of `item`, or `None` if there is no such
+    /// item.
+    pub fn query_by_item(&self, item: i64, options: QueryOptions) -> Option<Neighbors<T>> {
+        let v = self.get_item_vector(item)?;
+
+        Some(self._query(v, options))
+    }
+
+    fn _query(&self, v: &[T], options: QueryOptions) -> Neighbors<T> {
+        let items = self.nodes.iter().map(|(i, node)| (*i, node.as_slice()));
+
+        brute_force::<T, D, _>(items, v, self.f, options)
+            .into_iter()
+            .collect()
+    }
+
+    pub fn save<W>(&self, w: W)
+    where
+        W: std::io::Write,
+    {
+        self.try_save(w).unwrap();
+    }
+
+    pub(crate) fn try_save<W: io::Write>(&self, w: W) -> io::Result<()> {
+        let mut f = BufWriter::new(w);
+        let mut f = ChecksumWriter::new(&mut f);
+        format::write_header(&mut f, Kind::BruteForce)?;
+
+        bincode::serialize_into(&mut f, &self.nodes).map_err(io::Error::other)?;
+        f.finish()?;
+
+        Ok(())
+    }
+
+    /// Reads an index written by `save`. Returns false when the data cannot
+    /// be decoded, is of another format version or index type or does not
+    /// match its checksum, leaving the index as it was. The dimension is
+    /// taken from the loaded items.
+    pub fn load<R>(&mut self, reader: R) -> bool
+    where
+        R: std::io::BufRead,
+    {
+        self.try_load(reader).is_ok()
+    }
+
+    pub(crate) fn try_load<R: io::BufRead>(&mut self, reader: R) -> io::Result<()> {
+        let mut reader = ChecksumReader::new(reader);
+        format::read_header(&mut reader, Kind::BruteForce)?;
+        let nodes: HashMap<i64, D::Node> =
+            bincode::deserialize_from(&mut reader).map_err(format::to_io)?;
+        reader.verify()?;
+        let f = format::dimension(nodes.values().map(|node| node.as_slice()))?;
+
+        self.f = f.unwrap_or(self.f);
+        self.n_items = nodes.keys().max().map_or(0, |i| i + 1);
+        self.nodes = nodes;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,105 @@
+use std::io::{self, Read, Write};
+
+use crc32fast::Hasher;
+
+/// Marks the trailer written after the nodes of a saved index: the magic
+/// followed by the CRC-32 of everything before it, little endian.
+const MAGIC: [u8; 4] = *b"LAck";
+
+pub(crate) const TRAILER_LEN: usize = 8;
+
+pub(crate) fn trailer(crc: u32) -> [u8; TRAILER_LEN] {
+    let mut trailer = [0; TRAILER_LEN];
+    trailer[..4].copy_from_slice(&MAGIC);
+    trailer[4..].copy_from_slice(&crc.to_le_bytes());
+    trailer
+}
+
+/// Hashes everything written through it.
+pub(crate) struct ChecksumWriter<W> {
+    inner: W,
+    hasher: Hasher,
+}
+
+impl<W: Write> ChecksumWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: Hasher::new(),
+        }
+    }
+
+    /// Writes the trailer for the bytes written so far.
+    pub fn finish(mut self) -> io::Result<W> {
+        let crc = self.hasher.clone().finalize();
+        self.inner.write_all(&trailer(crc))?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Hashes everything read through it.
+pub(crate) struct ChecksumReader<R> {
+    inner: R,
+    hasher: Hasher,
+}
+
+impl<R: Read> ChecksumReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: Hasher::new(),
+        }
+    }
+
+    /// Checks that the trailer for the bytes read so far follows them and
+    /// ends the data. Every file with a format header has one, so a file
+    /// cut right before it is rejected like any other truncated file.
+    pub fn verify(mut self) -> io::Result<()> {
+        let crc = self.hasher.clone().finalize();
+
+        let mut rest = Vec::new();
+        self.inner.read_to_end(&mut rest)?;
+
+        if rest == trailer(crc) {
+            Ok(())
+        } else if rest.is_empty() {
+            Err(invalid("the checksum trailer is missing"))
+        } else if rest.len() == TRAILER_LEN && rest[..4] == MAGIC {
+            Err(invalid("checksum mismatch"))
+        } else {
+            Err(invalid("unexpected data after the index"))
+        }
+    }
+}
+
+impl<R: Read> Read for ChecksumReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+/// CRC-32 of the first `len` bytes of `r`.
+pub(crate) fn crc<R: Read>(r: R, len: u64) -> io::Result<u32> {
+    let mut reader = ChecksumReader::new(r.take(len));
+    io::copy(&mut reader, &mut io::sink())?;
+    Ok(reader.hasher.finalize())
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
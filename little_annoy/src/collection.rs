@@ -0,0 +1,243 @@
+use std::cmp::Ordering;
+
+use crate::ann::Annoy;
+use crate::cmp_distance;
+use crate::distance::Distance;
+use crate::error::Error;
+use crate::item::Item;
+use crate::neighbor::Neighbor;
+use crate::options::QueryOptions;
+
+/// A neighbor found by `AnnoyCollection::query`, with the name of the shard
+/// its id belongs to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShardNeighbor<'a, T: Item> {
+    pub shard: &'a str,
+    pub neighbor: Neighbor<T>,
+}
+
+/// Built indexes of the same dimension and metric, queried as one.
+///
+/// Every shard has a name and its own item ids, so the same id may appear in
+/// several shards; results carry the shard name to tell them apart. Shards
+/// are replaced as a whole by `insert_shard`, which borrows the collection
+/// mutably, so no shard serves queries meanwhile. An index that must keep
+/// serving while it is swapped belongs in an `AnnoyHandle`.
+pub struct AnnoyCollection<T: Item, D: Distance<T>> {
+    f: usize,
+    shards: Vec<(String, Annoy<T, D>)>,
+}
+
+impl<T, D> AnnoyCollection<T, D>
+where
+    T: Item + Sync + Send + 'static,
+    D: Distance<T>,
+    D::Node: Sync,
+{
+    /// An empty collection for vectors of `f` values.
+    pub fn new(f: usize) -> Self {
+        Self {
+            f,
+            shards: Vec::new(),
+        }
+    }
+
+    pub fn dimension(&self) -> usize {
+        self.f
+    }
+
+    pub fn len(&self) -> usize {
+        self.shards.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.is_empty()
+    }
+
+    /// Adds `ann` under `name`, returning the shard it replaces. Fails with
+    /// `Error::NotBuilt` or `Error::DimensionMismatch` if `ann` is not built
+    /// or its dimension differs from the collection's.
+    pub fn insert_shard(
+        &mut self,
+        name: &str,
+        ann: Annoy<T, D>,
+    ) -> Result<Option<Annoy<T, D>>, Error> {
+        if !ann.is_built() {
+            return Err(Error::NotBuilt);
+        }
+        if ann.dimension() != self.f {
+            return Err(Error::DimensionMismatch {
+                expected: self.f,
+                found: ann.dimension(),
+            });
+        }
+
+        match self.shards.iter_mut().find(|(n, _)| n == name) {
+            Some((_, shard)) => Ok(Some(std::mem::replace(shard, ann))),
+            None => {
+                self.shards.push((name.to_string(), ann));
+                Ok(None)
+            }
+        }
+    }
+
+    pub fn remove_shard(&mut self, name: &str) -> Option<Annoy<T, D>> {
+        let i = self.shards.iter().position(|(n, _)| n == name)?;
+        Some(self.shards.remove(i).1)
+    }
+
+    pub fn shard(&self, name: &str) -> Option<&Annoy<T, D>> {
+        self.shards
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, shard)| shard)
+    }
+
+    /// The shard names in the order the shards were inserted.
+    pub fn shard_names(&self) -> impl Iterator<Item = &str> + '_ {
+        self.shards.iter().map(|(n, _)| n.as_str())
+    }
+
+    /// Finds the `n` of `options` nearest neighbors of `v` over all shards.
+    /// Every shard is searched with `options`, so `search_k` applies per
+    /// shard. Equal distances are ordered by shard, then id.
+    pub fn query(
+        &self,
+        v: &[T],
+        options: QueryOptions,
+    ) -> Result<Vec<ShardNeighbor<'_, T>>, Error> {
+        if v.len() != self.f {
+            return Err(Error::DimensionMismatch {
+                expected: self.f,
+                found: v.len(),
+            });
+        }
+
+        let per_shard = self.query_shards(v, options)?;
+        Ok(self.merge(per_shard, options.n))
+    }
+
+    /// Like `query` for the vector of `item` in shard `shard`, or `None` if
+    /// there is no such item.
+    pub fn query_by_item(
+        &self,
+        shard: &str,
+        item: i64,
+        options: QueryOptions,
+    ) -> Option<Vec<ShardNeighbor<'_, T>>> {
+        let v = self.shard(shard)?.get_item_vector(item)?;
+
+        // Stored vectors have the right length and are finite.
+        self.query(&v, options).ok()
+    }
+
+    #[cfg(feature = "parallel")]
+    fn query_shards(&self, v: &[T], options: QueryOptions) -> Result<Vec<Vec<Neighbor<T>>>, Error> {
+        std::thread::scope(|s| {
+            let handles: Vec<_> = self
+                .shards
+                .iter()
+                .map(|(_, shard)| s.spawn(move || shard.query(v, options)))
+                .collect();
+
+            handles
+                .into_iter()
+                .map(|h| Ok(h.join().unwrap()?.into_vec()))
+                .collect()
+        })
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    fn query_shards(&self, v: &[T], options: QueryOptions) -> Result<Vec<Vec<Neighbor<T>>>, Error> {
+        self.shards
+            .iter()
+            .map(|(_, shard)| Ok(shard.query(v, options)?.into_vec()))
+            .collect()
+    }
+
+    /// Merges the nearest-first results of every shard into the `n` nearest.
+    fn merge(&self, per_shard: Vec<Vec<Neighbor<T>>>, n: usize) -> Vec<ShardNeighbor<'_, T>> {
+        let mut all: Vec<(usize, Neighbor<T>)> = per_shard
+            .into_iter()
+            .enumerate()
+            .flat_map(|(i, neighbors)| neighbors.into_iter().map(move |nb| (i, nb)))
+            .collect();
+
+        all.sort_by(|(i, a), (j, b)| cmp_shard_neighbor(*i, a, *j, b));
+        all.truncate(n);
+
+        all.into_iter()
+            .map(|(i, neighbor)| ShardNeighbor {
+                shard: &self.shards[i].0,
+                neighbor,
+            })
+            .collect()
+    }
+}
+
+fn cmp_shard_neighbor<T: Item>(i: usize, a: &Neighbor<T>, j: usize, b: &Neighbor<T>) -> Ordering {
+    cmp_distance(a.raw_distance, b.raw_distance)
+        .then(i.cmp(&j))
+        .then(a.id.cmp(&b.id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Euclidean;
+
+    fn shard(offset: f64) -> Annoy<f64, Euclidean> {
+        let mut ann = Annoy::new(2);
+        for i in 0..20 {
+            ann.add_item(i, &[offset + i as f64, 0.]).unwrap();
+        }
+        ann.build(3);
+        ann
+    }
+
+    #[test]
+    fn test_query_merges_shards() {
+        let mut collection = AnnoyCollection::new(2);
+        assert!(collection.insert_shard("eu", shard(0.)).unwrap().is_none());
+        assert!(collection.insert_shard("us", shard(0.5)).unwrap().is_none());
+
+        let options = QueryOptions::new(4).search_k(1000);
+        let found: Vec<(&str, i64)> = collection
+            .query(&[3.2, 0.], options)
+            .unwrap()
+            .iter()
+            .map(|n| (n.shard, n.neighbor.id))
+            .collect();
+        assert_eq!(found, vec![("eu", 3), ("us", 3), ("us", 2), ("eu", 4)]);
+
+        let by_item = collection.query_by_item("us", 0, options).unwrap();
+        assert_eq!((by_item[0].shard, by_item[0].neighbor.id), ("us", 0));
+        assert!(collection.query_by_item("asia", 0, options).is_none());
+
+        // Replacing a shard leaves the other one alone.
+        let old = collection.insert_shard("us", shard(100.)).unwrap();
+        assert!(old.is_some());
+        let found = collection.query(&[3.2, 0.], options).unwrap();
+        assert!(found.iter().all(|n| n.shard == "eu"));
+        assert_eq!(collection.shard_names().collect::<Vec<_>>(), ["eu", "us"]);
+    }
+
+    #[test]
+    fn test_insert_shard_errors() {
+        let mut collection = AnnoyCollection::new(3);
+        assert!(matches!(
+            collection.insert_shard("a", shard(0.)),
+            Err(Error::DimensionMismatch {
+                expected: 3,
+                found: 2
+            })
+        ));
+
+        let unbuilt: Annoy<f64, Euclidean> = Annoy::new(3);
+        assert!(matches!(
+            collection.insert_shard("a", unbuilt),
+            Err(Error::NotBuilt)
+        ));
+        assert!(collection.is_empty());
+    }
+}
@@ -1,18 +1,21 @@
 use serde::{Deserialize, Serialize};
 
-use rand::rngs::ThreadRng;
 use rand::Rng;
 
-use crate::distance::{normalize, two_means, Distance, NodeImpl};
+use crate::distance::{dot, floats, normalize, plane, to_f64, two_means, Distance, NodeImpl};
 use crate::item::Item;
 
 pub struct Angular {}
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
+// `Item` brings the serde bounds.
+#[serde(bound = "")]
 pub struct Node<T: Item> {
     pub children: Vec<i64>,
     pub v: Vec<T>,
     pub n_descendants: usize,
+    /// The normal of a split plane.
+    pub normal: Vec<T::Plane>,
     f: usize,
 }
 
@@ -22,6 +25,7 @@ impl<T: Item> NodeImpl<T> for Node<T> {
             children: vec![0, 0],
             v: vec![T::zero(); f],
             n_descendants: 0,
+            normal: Vec::new(),
             f,
         }
     }
@@ -30,6 +34,7 @@ impl<T: Item> NodeImpl<T> for Node<T> {
         self.children[0] = 0;
         self.children[1] = 0;
         self.n_descendants = 1;
+        self.normal = Vec::new();
         self.v = v.to_vec();
     }
 
@@ -57,56 +62,64 @@ impl<T: Item> NodeImpl<T> for Node<T> {
         self.children = other;
     }
 
+    fn vector_bytes(&self) -> usize {
+        std::mem::size_of_val(self.v.as_slice()) + std::mem::size_of_val(self.normal.as_slice())
+    }
+
     fn copy(&mut self, other: Self) {
         self.n_descendants = other.n_descendants;
         self.children = other.children;
         self.v = other.v;
+        self.normal = other.normal;
     }
 }
 
-impl<T: Item + serde::Serialize + serde::de::DeserializeOwned> Distance<T> for Angular {
-    type Node = Node<T>;
+/// Twice one minus the cosine of the pairs, or 2 when either side is zero.
+/// It is computed in `f64` for every item type, since a cosine is not an
+/// integer and squares of wide integers overflow any accumulator.
+fn cosine_distance(pairs: impl Iterator<Item = (f64, f64)>) -> f64 {
+    let mut pp = 0.0;
+    let mut qq = 0.0;
+    let mut pq = 0.0;
+
+    for (x, y) in pairs {
+        pp += x * x;
+        qq += y * y;
+        pq += x * y;
+    }
 
-    #[inline]
-    fn margin(n: &Self::Node, y: &[T]) -> T {
-        let mut dot = T::zero();
+    if pp * qq > 0.0 {
+        2.0 - 2.0 * pq / (pp * qq).sqrt()
+    } else {
+        2.0
+    }
+}
 
-        for (z, &item) in y.iter().enumerate().take(n.f) {
-            dot += n.v[z] * item;
-        }
+impl<T: Item> Distance<T> for Angular {
+    type Node = Node<T>;
 
-        dot
+    #[inline]
+    fn margin(n: &Self::Node, y: &[T]) -> f64 {
+        dot(&n.normal, y)
     }
 
     #[inline]
-    fn side(n: &Self::Node, y: &[T], rng: &mut ThreadRng) -> bool {
+    fn side<R: Rng>(n: &Self::Node, y: &[T], rng: &mut R) -> bool {
         let dot = Self::margin(n, y);
-        if dot != T::zero() {
-            return dot > T::zero();
+        if dot != 0.0 {
+            return dot > 0.0;
         }
         rng.gen::<bool>()
     }
 
     #[inline]
-    fn distance(x: &[T], y: &[T], f: usize) -> T {
-        let mut pp = T::zero();
-        let mut qq = T::zero();
-        let mut pq = T::zero();
-
-        for z in 0..f {
-            pp += x[z] * x[z];
-            qq += y[z] * y[z];
-            pq += x[z] * y[z];
-        }
-
-        let ppqq = pp * qq;
-        let two = T::from_f32(2.0).unwrap_or_else(T::zero);
-
-        if ppqq > T::zero() {
-            two - two * pq / ppqq.sqrt()
-        } else {
-            T::from_f32(2.0).unwrap_or_else(T::zero)
-        }
+    fn distance(x: &[T], y: &[T], f: usize) -> f64 {
+        cosine_distance(
+            x[..f]
+                .iter()
+                .zip(&y[..f])
+                .map(|(&x, &y)| (to_f64(x), to_f64(y))),
+        )
     }
 
     #[inline]
@@ -115,15 +128,13 @@ impl<T: Item + serde::Serialize + serde::de::DeserializeOwned> Distance<T> for A
     }
 
     #[inline]
-    fn create_split(nodes: &[&Self::Node], n: &mut Self::Node, f: usize, rng: &mut ThreadRng) {
-        let (best_iv, best_jv) = two_means::<T, Angular>(rng, nodes, f);
+    fn create_split<R: Rng>(nodes: &[&Self::Node], n: &mut Self::Node, f: usize, rng: &mut R) {
+        let (best_iv, best_jv) = two_means(rng, nodes, f, |c, y| cosine_distance(floats(c, y)));
 
-        for z in 0..f {
-            let best = best_iv[z] - best_jv[z];
-            n.v[z] = best;
-        }
+        let best: Vec<f64> = (0..f).map(|z| best_iv[z] - best_jv[z]).collect();
 
-        n.v = normalize(&n.v);
+        n.normal = normalize(&best).into_iter().map(plane::<T>).collect();
+        n.v = Vec::new();
     }
 }
 
@@ -140,4 +151,21 @@ mod tests {
         let dist = Angular::distance(x, y, f);
         assert_eq!(dist, 0.0);
     }
+
+    #[test]
+    fn test_unsigned() {
+        let x: &[u16] = &[u16::MAX, 0];
+        let y: &[u16] = &[0, u16::MAX];
+        let diagonal: &[u16] = &[u16::MAX, u16::MAX];
+
+        assert_eq!(Angular::distance(x, x, 2), 0.0);
+        assert_eq!(Angular::distance(x, y, 2), 2.0);
+        assert_eq!(Angular::distance(x, &[0, 0], 2), 2.0);
+        // 45 degrees apart: 2 - 2 cos 45° = 2 - √2.
+        assert!((Angular::distance(x, diagonal, 2) - (2.0 - 2f64.sqrt())).abs() < 1e-12);
+
+        // Squares of u64 values overflow the i128 accumulator.
+        let wide: &[u64] = &[u64::MAX, u64::MAX];
+        assert!((Angular::distance(wide, &[u64::MAX, 0], 2) - (2.0 - 2f64.sqrt())).abs() < 1e-12);
+    }
 }
@@ -1,18 +1,23 @@
-use rand::rngs::ThreadRng;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 
-use crate::distance::{normalize, two_means, Distance, NodeImpl};
-use crate::item::Item;
+use crate::distance::{
+    dot, floats, normalize, plane, to_f64, two_means, widened, Distance, NodeImpl,
+};
+use crate::item::{Item, Number};
 
 pub struct Euclidean {}
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
+// `Item` brings the serde bounds.
+#[serde(bound = "")]
 pub struct Node<T: Item> {
     pub children: Vec<i64>,
     pub v: Vec<T>,
     pub n_descendants: usize,
-    pub a: T,
+    /// The normal of a split plane.
+    pub normal: Vec<T::Plane>,
+    pub a: T::Plane,
     f: usize,
 }
 
@@ -22,7 +27,8 @@ impl<T: Item> NodeImpl<T> for Node<T> {
             children: vec![0, 0],
             v: vec![T::zero(); f],
             n_descendants: 0,
-            a: T::zero(),
+            normal: Vec::new(),
+            a: plane::<T>(0.0),
             f,
         }
     }
@@ -31,7 +37,8 @@ impl<T: Item> NodeImpl<T> for Node<T> {
         self.children[0] = 0;
         self.children[1] = 0;
         self.n_descendants = 1;
-        self.a = T::zero();
+        self.normal = Vec::new();
+        self.a = plane::<T>(0.0);
         self.v = v.to_vec();
         self.f = 0;
     }
@@ -60,51 +67,48 @@ impl<T: Item> NodeImpl<T> for Node<T> {
         self.children = other;
     }
 
+    fn vector_bytes(&self) -> usize {
+        std::mem::size_of_val(self.v.as_slice()) + std::mem::size_of_val(self.normal.as_slice())
+    }
+
     fn copy(&mut self, other: Self) {
         self.n_descendants = other.n_descendants;
         self.children = other.children;
         self.v = other.v;
+        self.normal = other.normal;
         self.a = other.a;
         self.f = other.f;
     }
 }
 
-impl<T: Item + serde::Serialize + serde::de::DeserializeOwned + std::ops::Neg + num::Num>
-    Distance<T> for Euclidean
-{
+/// The sum of squared differences, which stops at the largest value of `A`
+/// instead of overflowing integers.
+fn squared<A: Number>(pairs: impl Iterator<Item = (A, A)>) -> A {
+    pairs.fold(A::zero(), |d, (x, y)| {
+        d.saturating_add((x - y).saturating_mul(x - y))
+    })
+}
+
+impl<T: Item> Distance<T> for Euclidean {
     type Node = Node<T>;
 
     #[inline]
-    fn margin(n: &Self::Node, y: &[T]) -> T {
-        let mut dot = n.a;
-
-        (0..y.len()).for_each(|z| {
-            let v = n.v[z as usize] * y[z as usize];
-            dot += v;
-        });
-
-        dot
+    fn margin(n: &Self::Node, y: &[T]) -> f64 {
+        to_f64(n.a) + dot(&n.normal, y)
     }
 
     #[inline]
-    fn side(n: &Self::Node, y: &[T], rng: &mut ThreadRng) -> bool {
+    fn side<R: Rng>(n: &Self::Node, y: &[T], rng: &mut R) -> bool {
         let dot = Self::margin(n, y);
-        if dot != T::zero() {
-            return dot > T::zero();
+        if dot != 0.0 {
+            return dot > 0.0;
         }
         rng.gen()
     }
 
     #[inline]
-    fn distance(x: &[T], y: &[T], f: usize) -> T {
-        let mut d = T::zero();
-
-        for i in 0..f {
-            let v = (x[i as usize] - y[i as usize]) * (x[i as usize] - y[i as usize]);
-            d += v;
-        }
-
-        d
+    fn distance(x: &[T], y: &[T], f: usize) -> f64 {
+        to_f64(squared(widened(&x[..f], &y[..f])))
     }
 
     #[inline]
@@ -113,21 +117,21 @@ impl<T: Item + serde::Serialize + serde::de::DeserializeOwned + std::ops::Neg +
     }
 
     #[inline]
-    fn create_split(nodes: &[&Self::Node], n: &mut Self::Node, f: usize, rng: &mut ThreadRng) {
-        let (best_iv, best_jv) = two_means::<T, Euclidean>(rng, nodes, f);
+    fn create_split<R: Rng>(nodes: &[&Self::Node], n: &mut Self::Node, f: usize, rng: &mut R) {
+        let (best_iv, best_jv) = two_means(rng, nodes, f, |c, y| squared(floats(c, y)));
 
-        for z in 0..f {
-            let best = best_iv[z] - best_jv[z];
-            n.v[z] = best;
-        }
+        let best: Vec<f64> = (0..f).map(|z| best_iv[z] - best_jv[z]).collect();
 
-        n.v = normalize(&n.v);
-        n.a = T::zero();
+        let normal = normalize(&best);
+        let mut a = 0.0;
 
         for z in 0..f {
-            let v = -n.v[z] * (best_iv[z] + best_jv[z]) / (T::one() + T::one());
-            n.a += v;
+            a += -normal[z] * (best_iv[z] + best_jv[z]) / 2.0;
         }
+
+        n.normal = normal.into_iter().map(plane::<T>).collect();
+        n.a = plane::<T>(a);
+        n.v = Vec::new();
     }
 }
 
@@ -151,9 +155,32 @@ mod tests {
     #[test]
     fn test_side() {
         let mut n = Node::new(2);
-        n.v = vec![2., 4.];
+        n.normal = vec![2., 4.];
         let actual = Euclidean::side(&n, &[1., 2.], &mut thread_rng());
 
         assert!(actual)
     }
+
+    #[test]
+    fn test_unsigned() {
+        // 255² · 4 overflows u8 and i16 but not the i32 accumulator.
+        let x: &[u8] = &[0, 0, 0, 0];
+        let y: &[u8] = &[255, 255, 255, 255];
+
+        assert_eq!(Euclidean::distance(x, y, 4), 260_100.0);
+        assert_eq!(Euclidean::distance(y, x, 4), 260_100.0);
+    }
+
+    #[test]
+    fn test_saturating() {
+        // (2^64 - 1)² overflows the i128 accumulator of u64.
+        let near: &[u64] = &[u64::MAX - 3, u64::MAX];
+        let far: &[u64] = &[0, u64::MAX];
+
+        assert_eq!(Euclidean::distance(near, &[u64::MAX; 2], 2), 9.0);
+        assert_eq!(
+            Euclidean::distance(far, &[u64::MAX; 2], 2),
+            i128::MAX as f64
+        );
+    }
 }
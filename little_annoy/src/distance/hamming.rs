@@ -1,4 +1,3 @@
-use rand::rngs::ThreadRng;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 
@@ -8,11 +7,16 @@ use crate::item::Item;
 pub struct Hamming {}
 
 #[derive(Clone, Deserialize, Serialize)]
+// `Item` brings the serde bounds.
+#[serde(bound = "")]
 pub struct Node<T: Item> {
     pub children: Vec<i64>,
     pub v: Vec<T>,
     pub n_descendants: usize,
     pub f: usize,
+    /// The bit a split looks at, counted from the highest bit of the first
+    /// value.
+    pub bit: usize,
 }
 
 impl<T: Item> NodeImpl<T> for Node<T> {
@@ -22,6 +26,7 @@ impl<T: Item> NodeImpl<T> for Node<T> {
             v: vec![T::zero(); f],
             n_descendants: 0,
             f,
+            bit: 0,
         }
     }
 
@@ -60,38 +65,53 @@ impl<T: Item> NodeImpl<T> for Node<T> {
         self.n_descendants = other.n_descendants;
         self.children = other.children;
         self.v = other.v;
+        self.bit = other.bit;
     }
 }
 
 const MAX_ITERATIONS: usize = 20;
 
-impl<T: Item + Clone + serde::Serialize + serde::de::DeserializeOwned> Distance<T> for Hamming {
+impl<T: Item> Distance<T> for Hamming {
     type Node = Node<T>;
 
     #[inline]
-    fn margin(n: &Self::Node, y: &[T]) -> T {
-        let n_bits = 4 * 8_u64;
-        let chunk = n.v[0].to_u64().unwrap_or_default() / n_bits;
-        let r = (y[chunk as usize].to_i64().unwrap())
-            & (1 << (n_bits - 1 - (n.v[0].to_u64().unwrap() as u64 % n_bits)) != 0) as i64;
+    fn margin(n: &Self::Node, y: &[T]) -> f64 {
+        let chunk = n.bit / T::BITS;
+        let shift = T::BITS - 1 - n.bit % T::BITS;
+        let value = y[chunk].bits();
 
-        T::from_i64(r).unwrap()
+        ((value >> shift) & 1) as f64
     }
 
     #[inline]
-    fn side(n: &Self::Node, y: &[T], _rng: &mut ThreadRng) -> bool {
-        Self::margin(n, y) > T::zero()
+    fn side<R: Rng>(n: &Self::Node, y: &[T], _rng: &mut R) -> bool {
+        Self::margin(n, y) > 0.0
     }
 
+    // The margin is a single bit, so the priority counts the splits that
+    // went against the query's bit.
     #[inline]
-    fn distance(x: &[T], y: &[T], f: usize) -> T {
-        let mut dist = T::zero();
+    fn pq_initial_value() -> f64 {
+        0.0
+    }
+
+    #[inline]
+    fn pq_distance(distance: f64, margin: f64, child: usize) -> f64 {
+        if (margin > 0.0) == (child == 1) {
+            distance
+        } else {
+            distance - 1.0
+        }
+    }
+
+    #[inline]
+    fn distance(x: &[T], y: &[T], f: usize) -> f64 {
+        let mut dist = 0.0;
 
         (0..f).for_each(|i| {
-            let v =
-                ((x[i].to_u64().unwrap() as u64) ^ (y[i].to_u64().unwrap() as u64)).count_ones();
+            let v = (x[i].bits() ^ y[i].bits()).count_ones();
 
-            dist += T::from_u32(v).unwrap();
+            dist += v as f64;
         });
 
         dist
@@ -103,17 +123,19 @@ impl<T: Item + Clone + serde::Serialize + serde::de::DeserializeOwned> Distance<
     }
 
     #[inline]
-    fn create_split(nodes: &[&Self::Node], n: &mut Self::Node, f: usize, rng: &mut ThreadRng) {
+    fn create_split<R: Rng>(nodes: &[&Self::Node], n: &mut Self::Node, f: usize, rng: &mut R) {
+        let n_bits = f * T::BITS;
         let mut cur_size = 0;
         let mut i = 0;
 
+        n.v = Vec::new();
+
         (0..MAX_ITERATIONS).for_each(|_| {
-            let rnd = rng.gen::<usize>() % f;
-            n.v[0] = T::from_usize(rnd).unwrap();
+            n.bit = rng.gen::<usize>() % n_bits;
             cur_size = 0;
 
             for node in nodes.iter() {
-                if Self::side(node, &n.v, rng) {
+                if Self::side(n, &node.v, rng) {
                     cur_size += 1;
                 }
             }
@@ -126,12 +148,12 @@ impl<T: Item + Clone + serde::Serialize + serde::de::DeserializeOwned> Distance<
         });
 
         if i == MAX_ITERATIONS {
-            for j in 0..f {
-                n.v[0] = T::from_usize(j).unwrap_or_else(T::zero);
+            for j in 0..n_bits {
+                n.bit = j;
                 cur_size = 0;
 
                 for node in nodes.iter() {
-                    if Self::side(node, &n.v, rng) {
+                    if Self::side(n, &node.v, rng) {
                         cur_size += 1;
                     }
                 }
@@ -158,4 +180,38 @@ mod tests {
 
         assert_eq!(dist, 1.0);
     }
+
+    #[test]
+    fn test_unsigned() {
+        let x: &[u64] = &[u64::MAX, 0b1010];
+        let y: &[u64] = &[0, 0b0110];
+
+        assert_eq!(Hamming::distance(x, y, 2), 66.0);
+
+        // Bit 64 is the highest bit of the second value, bit 125 its 3rd
+        // lowest.
+        let mut n = Node::new(2);
+        n.bit = 125;
+        assert_eq!(Hamming::margin(&n, x), 0.0);
+        assert_eq!(Hamming::margin(&n, y), 1.0);
+        n.bit = 64;
+        assert_eq!(Hamming::margin(&n, x), 0.0);
+        n.bit = 0;
+        assert_eq!(Hamming::margin(&n, x), 1.0);
+    }
+
+    #[test]
+    fn test_signed() {
+        let x: &[i8] = &[-1, 0];
+        let y: &[i8] = &[0, -128];
+
+        assert_eq!(Hamming::distance(x, y, 2), 9.0);
+
+        let mut n = Node::new(2);
+        n.bit = 8;
+        assert_eq!(Hamming::margin(&n, x), 0.0);
+        assert_eq!(Hamming::margin(&n, y), 1.0);
+        n.bit = 7;
+        assert_eq!(Hamming::margin(&n, x), 1.0);
+    }
 }
@@ -1,32 +1,43 @@
-use rand::rngs::ThreadRng;
+use num::Signed;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 
-use crate::distance::{normalize, two_means, Distance, NodeImpl};
+use crate::distance::{
+    dot, floats, normalize, plane, to_f64, two_means, widened, Distance, NodeImpl,
+};
+use crate::item::{Item, Number};
+
 pub struct Manhattan {}
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct Node {
+// `Item` brings the serde bounds.
+#[serde(bound = "")]
+pub struct Node<T: Item> {
     pub children: Vec<i64>,
-    pub v: Vec<f64>,
+    pub v: Vec<T>,
     pub n_descendants: usize,
-    pub a: f64,
+    /// The normal of a split plane.
+    pub normal: Vec<T::Plane>,
+    pub a: T::Plane,
 }
 
-impl NodeImpl<f64> for Node {
+impl<T: Item> NodeImpl<T> for Node<T> {
     fn new(f: usize) -> Self {
         Node {
             children: vec![0, 0],
-            v: vec![0.0; f],
+            v: vec![T::zero(); f],
             n_descendants: 0,
-            a: 0.0,
+            normal: Vec::new(),
+            a: plane::<T>(0.0),
         }
     }
 
-    fn reset(&mut self, v: &[f64]) {
+    fn reset(&mut self, v: &[T]) {
         self.children[0] = 0;
         self.children[1] = 0;
         self.n_descendants = 1;
+        self.normal = Vec::new();
+        self.a = plane::<T>(0.0);
         self.v = v.to_vec();
     }
 
@@ -38,11 +49,11 @@ impl NodeImpl<f64> for Node {
         self.n_descendants = other;
     }
 
-    fn as_slice(&self) -> &[f64] {
+    fn as_slice(&self) -> &[T] {
         self.v.as_slice()
     }
 
-    fn mut_vector(&mut self) -> &mut Vec<f64> {
+    fn mut_vector(&mut self) -> &mut Vec<T> {
         &mut self.v
     }
 
@@ -54,30 +65,35 @@ impl NodeImpl<f64> for Node {
         self.children = other;
     }
 
+    fn vector_bytes(&self) -> usize {
+        std::mem::size_of_val(self.v.as_slice()) + std::mem::size_of_val(self.normal.as_slice())
+    }
+
     fn copy(&mut self, other: Self) {
         self.n_descendants = other.n_descendants;
         self.children = other.children;
         self.v = other.v;
+        self.normal = other.normal;
         self.a = other.a;
     }
 }
 
-impl Distance<f64> for Manhattan {
-    type Node = Node;
-
-    #[inline]
-    fn margin(n: &Self::Node, y: &[f64]) -> f64 {
-        let mut dot: f64 = n.a;
+/// The sum of absolute differences, which stops at the largest value of
+/// `A` instead of overflowing integers.
+fn absolute<A: Number + Signed>(pairs: impl Iterator<Item = (A, A)>) -> A {
+    pairs.fold(A::zero(), |d, (x, y)| d.saturating_add((x - y).abs()))
+}
 
-        for (z, _y) in y.iter().enumerate() {
-            dot += n.v[z] * y[z];
-        }
+impl<T: Item> Distance<T> for Manhattan {
+    type Node = Node<T>;
 
-        dot
+    #[inline]
+    fn margin(n: &Self::Node, y: &[T]) -> f64 {
+        to_f64(n.a) + dot(&n.normal, y)
     }
 
     #[inline]
-    fn side(n: &Self::Node, y: &[f64], rng: &mut ThreadRng) -> bool {
+    fn side<R: Rng>(n: &Self::Node, y: &[T], rng: &mut R) -> bool {
         let dot = Self::margin(n, y);
 
         if dot != 0.0 {
@@ -88,14 +104,8 @@ impl Distance<f64> for Manhattan {
     }
 
     #[inline]
-    fn distance(x: &[f64], y: &[f64], f: usize) -> f64 {
-        let mut d = 0.0;
-
-        for i in 0..f {
-            d += (x[i] - y[i]).abs();
-        }
-
-        d
+    fn distance(x: &[T], y: &[T], f: usize) -> f64 {
+        to_f64(absolute(widened(&x[..f], &y[..f])))
     }
 
     #[inline]
@@ -104,18 +114,34 @@ impl Distance<f64> for Manhattan {
     }
 
     #[inline]
-    fn create_split(nodes: &[&Self::Node], n: &mut Self::Node, f: usize, rng: &mut ThreadRng) {
-        let (best_iv, best_jv) = two_means::<f64, Manhattan>(rng, nodes, f);
+    fn create_split<R: Rng>(nodes: &[&Self::Node], n: &mut Self::Node, f: usize, rng: &mut R) {
+        let (best_iv, best_jv) = two_means(rng, nodes, f, |c, y| absolute(floats(c, y)));
 
-        for z in 0..f {
-            n.v[z] = best_iv[z] - best_jv[z];
-        }
+        let best: Vec<f64> = (0..f).map(|z| best_iv[z] - best_jv[z]).collect();
 
-        n.v = normalize(&n.v);
-        n.a = 0.0;
+        let normal = normalize(&best);
+        let mut a = 0.0;
 
         for z in 0..f {
-            n.a += -n.v[z] * (best_iv[z] + best_jv[z]) / 2.0;
+            a += -normal[z] * (best_iv[z] + best_jv[z]) / 2.0;
         }
+
+        n.normal = normal.into_iter().map(plane::<T>).collect();
+        n.a = plane::<T>(a);
+        n.v = Vec::new();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unsigned() {
+        let x: &[u8] = &[10, 200];
+        let y: &[u8] = &[250, 0];
+
+        assert_eq!(Manhattan::distance(x, y, 2), 440.0);
+        assert_eq!(Manhattan::distance(y, x, 2), 440.0);
     }
 }
@@ -1,5 +1,7 @@
-use rand::rngs::ThreadRng;
+use num::{FromPrimitive, Zero};
 use rand::Rng;
+use std::fmt;
+use std::str::FromStr;
 
 pub mod angular;
 pub mod euclidean;
@@ -11,54 +13,87 @@ pub use euclidean::Euclidean;
 pub use hamming::Hamming;
 pub use manhattan::Manhattan;
 
-use crate::item::Item;
+use crate::item::{Item, Number};
 
 const ITERATION_STEPS: usize = 200;
 
-fn get_norm<T: Item>(v: &[T]) -> T {
-    v.iter().fold(T::zero(), |acc, &x| acc + (x * x)).sqrt()
+fn to_f64<A: Number>(x: A) -> f64 {
+    x.to_f64().unwrap_or(f64::NAN)
 }
 
-fn normalize<T: Item>(v: &[T]) -> Vec<T> {
-    let norm = get_norm(v);
+/// `v` scaled to unit length, or as it is if it is zero.
+fn normalize(v: &[f64]) -> Vec<f64> {
+    let norm = v.iter().map(|x| x * x).sum::<f64>().sqrt();
+    if norm == 0.0 {
+        return v.to_vec();
+    }
+
     v.iter().map(|&v| v / norm).collect()
 }
 
-fn two_means<T: Item, D: Distance<T>>(
-    rng: &mut ThreadRng,
-    nodes: &[&D::Node],
-    f: usize,
-) -> (Vec<T>, Vec<T>) {
+/// Pairs up the values of `x` and `y` as accumulators.
+fn widened<'a, T: Item>(x: &'a [T], y: &'a [T]) -> impl Iterator<Item = (T::Acc, T::Acc)> + 'a {
+    x.iter().zip(y).map(|(x, y)| (x.widen(), y.widen()))
+}
+
+/// Pairs up the values of `x` and `y` as `f64`.
+fn floats<'a, T: Item>(x: &'a [f64], y: &'a [T]) -> impl Iterator<Item = (f64, f64)> + 'a {
+    x.iter().zip(y).map(|(&x, &y)| (x, to_f64(y)))
+}
+
+/// The dot product of a split plane's normal and `y`.
+fn dot<T: Item>(normal: &[T::Plane], y: &[T]) -> f64 {
+    normal
+        .iter()
+        .zip(y)
+        .map(|(&w, &y)| to_f64(w) * to_f64(y))
+        .sum()
+}
+
+/// A value of a split plane computed in `f64`, stored as `T::Plane`.
+fn plane<T: Item>(x: f64) -> T::Plane {
+    T::Plane::from_f64(x).unwrap_or_else(T::Plane::zero)
+}
+
+/// Two centroids of `nodes`, where `distance` measures from a centroid to
+/// an item. Centroids are `f64` whatever the item type, so that averaging
+/// integers does not round them.
+fn two_means<T, N, R, F>(rng: &mut R, nodes: &[&N], f: usize, distance: F) -> (Vec<f64>, Vec<f64>)
+where
+    T: Item,
+    N: NodeImpl<T>,
+    R: Rng,
+    F: Fn(&[f64], &[T]) -> f64,
+{
     let count = nodes.len();
     let i: u64 = rng.gen::<u64>() % count as u64;
     let mut j: u64 = rng.gen::<u64>() % (count - 1) as u64;
     j += (j >= i) as u64;
 
-    let mut iv = nodes[i as usize].as_slice().to_vec();
-    let mut jv = nodes[j as usize].as_slice().to_vec();
+    let floats = |node: &N| -> Vec<f64> { node.as_slice().iter().map(|&x| to_f64(x)).collect() };
+    let mut iv = floats(nodes[i as usize]);
+    let mut jv = floats(nodes[j as usize]);
 
-    let mut ic = T::one();
-    let mut jc = T::one();
+    let mut ic = 1.0;
+    let mut jc = 1.0;
 
     for _ in 0..ITERATION_STEPS {
-        let k = rng.gen::<usize>() % count as usize;
-        let di = ic * D::distance(&iv, nodes[k].as_slice(), f);
-        let dj = jc * D::distance(&jv, nodes[k].as_slice(), f);
+        let k = rng.gen::<usize>() % count;
+        let di = ic * distance(&iv, nodes[k].as_slice());
+        let dj = jc * distance(&jv, nodes[k].as_slice());
         let nk = &nodes[k].as_slice();
 
         if di < dj {
             for z in 0..f {
-                let v = iv[z] * ic + nk[z];
-                iv[z] = v / (ic + T::one());
+                iv[z] = (iv[z] * ic + to_f64(nk[z])) / (ic + 1.0);
             }
 
-            ic += T::one();
+            ic += 1.0;
         } else if dj < di {
             for z in 0..f {
-                let v = jv[z] * jc + nk[z];
-                jv[z] = v / (jc + T::one());
+                jv[z] = (jv[z] * jc + to_f64(nk[z])) / (jc + 1.0);
             }
-            jc += T::one();
+            jc += 1.0;
         }
     }
 
@@ -80,18 +115,107 @@ pub trait NodeImpl<T> {
 
     fn children(&self) -> Vec<i64>;
     fn set_children(&mut self, other: Vec<i64>);
+
+    /// Bytes the node holds on the heap for its vectors.
+    fn vector_bytes(&self) -> usize {
+        std::mem::size_of_val(self.as_slice())
+    }
 }
 
 pub trait Distance<T: Item> {
     type Node: NodeImpl<T> + Clone + serde::Serialize + serde::de::DeserializeOwned;
 
-    fn distance(x: &[T], y: &[T], f: usize) -> T;
+    /// The distance of the first `f` values of `x` and `y`, as the metric
+    /// compares it. Integer items are summed in `T::Acc`, so the result is
+    /// exact up to 2^53, where `f64` starts rounding.
+    fn distance(x: &[T], y: &[T], f: usize) -> f64;
+
+    fn create_split<R: Rng>(nodes: &[&Self::Node], n: &mut Self::Node, f: usize, rng: &mut R);
 
-    fn create_split(nodes: &[&Self::Node], n: &mut Self::Node, f: usize, rng: &mut ThreadRng);
+    fn side<R: Rng>(n: &Self::Node, y: &[T], rng: &mut R) -> bool;
 
-    fn side(n: &Self::Node, y: &[T], rng: &mut ThreadRng) -> bool;
+    fn margin(n: &Self::Node, y: &[T]) -> f64;
+
+    /// Search priority of a root, at least any priority `pq_distance`
+    /// gives.
+    fn pq_initial_value() -> f64 {
+        f64::MAX
+    }
 
-    fn margin(n: &Self::Node, y: &[T]) -> T;
+    /// Search priority of child `child` of a split reached with priority
+    /// `distance`, where `margin` is the split's margin for the query.
+    /// Items on the side `side` puts them, child 1 when true, get the
+    /// higher priority.
+    fn pq_distance(distance: f64, margin: f64, child: usize) -> f64 {
+        let margin = if child == 0 { -margin } else { margin };
+        margin.min(distance)
+    }
 
     fn normalized_distance(distance: f64) -> f64;
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    Angular,
+    Euclidean,
+    Manhattan,
+    Hamming,
+}
+
+impl Metric {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Metric::Angular => "angular",
+            Metric::Euclidean => "euclidean",
+            Metric::Manhattan => "manhattan",
+            Metric::Hamming => "hamming",
+        }
+    }
+}
+
+impl fmt::Display for Metric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseMetricError(String);
+
+impl fmt::Display for ParseMetricError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "unknown metric `{}` (expected angular, euclidean, manhattan or hamming)",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParseMetricError {}
+
+impl FromStr for Metric {
+    type Err = ParseMetricError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "angular" => Ok(Metric::Angular),
+            "euclidean" => Ok(Metric::Euclidean),
+            "manhattan" => Ok(Metric::Manhattan),
+            "hamming" => Ok(Metric::Hamming),
+            _ => Err(ParseMetricError(s.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_metric() {
+        assert_eq!("Euclidean".parse::<Metric>(), Ok(Metric::Euclidean));
+        assert_eq!(Metric::Hamming.to_string().parse(), Ok(Metric::Hamming));
+        assert!("cosine".parse::<Metric>().is_err());
+    }
+}
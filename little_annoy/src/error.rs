@@ -0,0 +1,72 @@
+use std::fmt;
+use std::io;
+
+use crate::item::Number;
+use crate::verify::Corruption;
+
+/// Errors returned by `Annoy`.
+#[derive(Debug)]
+pub enum Error {
+    /// The build was stopped through its `CancellationToken`.
+    Cancelled,
+    /// The index is already built, so it cannot be built again or take new
+    /// items until it is unbuilt.
+    AlreadyBuilt,
+    /// The index has no trees to unbuild.
+    NotBuilt,
+    /// A vector added or queried contains NaN or an infinity.
+    NonFinite,
+    /// A vector or index does not have the expected number of values.
+    DimensionMismatch { expected: usize, found: usize },
+    /// Reading or writing an index file failed.
+    Io(io::Error),
+    /// `Annoy::verify` found the index to be inconsistent.
+    Corrupt(Corruption),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Cancelled => f.write_str("the build was cancelled"),
+            Error::AlreadyBuilt => f.write_str("the index is already built"),
+            Error::NotBuilt => f.write_str("the index is not built"),
+            Error::NonFinite => f.write_str("the vector contains NaN or infinite values"),
+            Error::DimensionMismatch { expected, found } => {
+                write!(f, "expected dimension {}, found {}", expected, found)
+            }
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Corrupt(c) => write!(f, "corrupt index: {}", c),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+/// Checks that `v` has `f` finite values, as every vector added or queried
+/// must.
+pub(crate) fn check_vector<T: Number>(v: &[T], f: usize) -> Result<(), Error> {
+    if v.len() != f {
+        return Err(Error::DimensionMismatch {
+            expected: f,
+            found: v.len(),
+        });
+    }
+    if !v.iter().all(|x| x.is_finite()) {
+        return Err(Error::NonFinite);
+    }
+
+    Ok(())
+}
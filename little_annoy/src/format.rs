@@ -0,0 +1,108 @@
+use std::io::{self, Read, Write};
+
+/// Starts every saved index, ahead of its version.
+const MAGIC: [u8; 4] = *b"LAix";
+
+/// The layout of saved nodes. Files of another version are rejected rather
+/// than decoded into the wrong fields.
+///
+/// Version 3 tags the header with the `Kind` of index. Version 2 stores
+/// split planes as `Item::Plane`, where version 1 stored them as `f64`.
+/// Files saved before versions were added have no header at all.
+pub(crate) const VERSION: u32 = 3;
+
+pub(crate) const HEADER_LEN: usize = 12;
+
+/// The index type a file was saved by, since their nodes can decode as one
+/// another's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Kind {
+    Annoy = 1,
+    Hnsw = 2,
+    BruteForce = 3,
+}
+
+impl Kind {
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Annoy => "Annoy",
+            Kind::Hnsw => "Hnsw",
+            Kind::BruteForce => "BruteForce",
+        }
+    }
+
+    fn from_u32(kind: u32) -> Option<Self> {
+        [Kind::Annoy, Kind::Hnsw, Kind::BruteForce]
+            .iter()
+            .copied()
+            .find(|&k| k as u32 == kind)
+    }
+}
+
+fn header(kind: Kind) -> [u8; HEADER_LEN] {
+    let mut header = [0; HEADER_LEN];
+    header[..4].copy_from_slice(&MAGIC);
+    header[4..8].copy_from_slice(&VERSION.to_le_bytes());
+    header[8..].copy_from_slice(&(kind as u32).to_le_bytes());
+    header
+}
+
+pub(crate) fn write_header<W: Write>(w: &mut W, kind: Kind) -> io::Result<()> {
+    w.write_all(&header(kind))
+}
+
+/// Checks the header of a saved index, which must be of this version and
+/// saved by an index of type `kind`.
+pub(crate) fn read_header<R: Read>(r: &mut R, kind: Kind) -> io::Result<()> {
+    let mut header = [0; HEADER_LEN];
+    if r.read_exact(&mut header[..8]).is_err() || header[..4] != MAGIC {
+        return Err(invalid(
+            "not an index, or one saved before format versions were added".to_string(),
+        ));
+    }
+
+    let version = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+    if version != VERSION {
+        return Err(invalid(format!(
+            "index format version {} is not supported, expected {}",
+            version, VERSION
+        )));
+    }
+
+    r.read_exact(&mut header[8..])?;
+    let found = u32::from_le_bytes([header[8], header[9], header[10], header[11]]);
+    match Kind::from_u32(found) {
+        Some(found) if found == kind => Ok(()),
+        Some(found) => Err(invalid(format!(
+            "the index was saved by {}, not {}",
+            found.name(),
+            kind.name()
+        ))),
+        None => Err(invalid(format!("unknown index type {}", found))),
+    }
+}
+
+/// The dimension shared by the item `vectors` of a loaded index, or `None`
+/// if it has no items.
+pub(crate) fn dimension<'a, T: 'a>(
+    mut vectors: impl Iterator<Item = &'a [T]>,
+) -> io::Result<Option<usize>> {
+    let f = match vectors.next() {
+        Some(v) => v.len(),
+        None => return Ok(None),
+    };
+    if vectors.any(|v| v.len() != f) {
+        return Err(invalid("items have different dimensions".to_string()));
+    }
+
+    Ok(Some(f))
+}
+
+/// A bincode error as an `io::Error`, the data being what is wrong.
+pub(crate) fn to_io(e: bincode::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
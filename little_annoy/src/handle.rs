@@ -0,0 +1,233 @@
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+use web_time::{SystemTime, UNIX_EPOCH};
+
+use crate::ann::Annoy;
+use crate::distance::Distance;
+use crate::error::Error;
+use crate::item::Item;
+use crate::neighbor::Neighbors;
+use crate::options::QueryOptions;
+
+/// An index served by an `AnnoyHandle`, with where it came from. Derefs to
+/// the index.
+pub struct ServedIndex<T: Item, D: Distance<T>> {
+    ann: Annoy<T, D>,
+    version: u64,
+    built_at: SystemTime,
+    loaded_at: SystemTime,
+}
+
+impl<T: Item, D: Distance<T>> ServedIndex<T, D> {
+    /// Counts the indexes the handle served, starting at 1.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// When the index file was last modified, or when the index was handed
+    /// to the handle if it did not come from a file.
+    pub fn built_at(&self) -> SystemTime {
+        self.built_at
+    }
+
+    /// When the handle started serving the index.
+    pub fn loaded_at(&self) -> SystemTime {
+        self.loaded_at
+    }
+}
+
+impl<T: Item, D: Distance<T>> Deref for ServedIndex<T, D> {
+    type Target = Annoy<T, D>;
+
+    fn deref(&self) -> &Annoy<T, D> {
+        &self.ann
+    }
+}
+
+/// Serves one index at a time and replaces it atomically.
+///
+/// Every query runs against the index that was current when it started,
+/// which stays alive until the last such query is done, so swapping in a
+/// new index never interrupts queries in flight. `load` reads the new index
+/// before taking the old one out of service and can run on a background
+/// thread while queries go on; share the handle through an `Arc`.
+pub struct AnnoyHandle<T: Item, D: Distance<T>> {
+    current: RwLock<Arc<ServedIndex<T, D>>>,
+}
+
+fn from_std(t: std::time::SystemTime) -> SystemTime {
+    UNIX_EPOCH + t.duration_since(std::time::UNIX_EPOCH).unwrap_or_default()
+}
+
+impl<T, D> AnnoyHandle<T, D>
+where
+    T: Item + Sync + Send + 'static,
+    D: Distance<T>,
+{
+    /// Serves `ann` as version 1.
+    pub fn new(ann: Annoy<T, D>) -> Self {
+        Self::serve(ann, SystemTime::now())
+    }
+
+    /// Serves the index in the file at `path` as version 1.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let (ann, built_at) = Self::read(path.as_ref())?;
+
+        Ok(Self::serve(ann, built_at))
+    }
+
+    fn serve(ann: Annoy<T, D>, built_at: SystemTime) -> Self {
+        Self {
+            current: RwLock::new(Arc::new(ServedIndex {
+                ann,
+                version: 1,
+                built_at,
+                loaded_at: SystemTime::now(),
+            })),
+        }
+    }
+
+    /// The index served right now. Hold on to it to run several queries
+    /// against the same index.
+    pub fn current(&self) -> Arc<ServedIndex<T, D>> {
+        Arc::clone(&self.current.read().unwrap())
+    }
+
+    /// Version of the index served right now.
+    pub fn version(&self) -> u64 {
+        self.current().version
+    }
+
+    /// Like `Annoy::get_nns_by_vector` on the current index.
+    pub fn get_nns_by_vector(&self, v: &[T], n: usize, search_k: i64) -> (Vec<i64>, Vec<f64>) {
+        self.current().get_nns_by_vector(v, n, search_k)
+    }
+
+    /// Like `Annoy::query` on the current index.
+    pub fn query(&self, v: &[T], options: QueryOptions) -> Result<Neighbors<T>, Error> {
+        self.current().query(v, options)
+    }
+
+    /// Serves `ann` from now on and returns its version.
+    pub fn swap(&self, ann: Annoy<T, D>) -> u64 {
+        self.swap_built_at(ann, SystemTime::now())
+    }
+
+    fn swap_built_at(&self, ann: Annoy<T, D>, built_at: SystemTime) -> u64 {
+        let mut current = self.current.write().unwrap();
+        let version = current.version + 1;
+
+        *current = Arc::new(ServedIndex {
+            ann,
+            version,
+            built_at,
+            loaded_at: SystemTime::now(),
+        });
+        version
+    }
+
+    /// Loads the index in the file at `path`, which must have the dimension
+    /// of the current one, and serves it from now on. Returns its version,
+    /// or the error that kept it from loading, in which case the current
+    /// index stays in service.
+    pub fn load<P: AsRef<Path>>(&self, path: P) -> Result<u64, Error> {
+        let (ann, built_at) = Self::read(path.as_ref())?;
+
+        let expected = self.current().dimension();
+        if ann.dimension() != expected {
+            return Err(Error::DimensionMismatch {
+                expected,
+                found: ann.dimension(),
+            });
+        }
+
+        Ok(self.swap_built_at(ann, built_at))
+    }
+
+    fn read(path: &Path) -> Result<(Annoy<T, D>, SystemTime), Error> {
+        let file = File::open(path)?;
+        let built_at = file
+            .metadata()?
+            .modified()
+            .map(from_std)
+            .unwrap_or_else(|_| SystemTime::now());
+
+        let mut ann = Annoy::new(0);
+        if !ann.load(BufReader::new(file)) {
+            return Err(Error::Io(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} is not a valid index file", path.display()),
+            )));
+        }
+
+        Ok((ann, built_at))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Euclidean;
+    use std::thread;
+
+    fn line(offset: f64) -> Annoy<f64, Euclidean> {
+        let mut ann = Annoy::new(2);
+        for i in 0..20 {
+            ann.add_item(i, &[offset + i as f64, 0.]).unwrap();
+        }
+        ann.build(3);
+        ann
+    }
+
+    #[test]
+    fn test_swap_load() {
+        let handle = Arc::new(AnnoyHandle::new(line(0.)));
+        assert_eq!(handle.version(), 1);
+        assert_eq!(handle.get_nns_by_vector(&[3.2, 0.], 1, 1000).0, vec![3]);
+
+        let path = std::env::temp_dir().join(format!("handle-{}.ann", std::process::id()));
+        line(10.).save(File::create(&path).unwrap());
+
+        // A query that started before the swap keeps its index.
+        let before = handle.current();
+        let loader = Arc::clone(&handle);
+        let loading = path.clone();
+        let version = thread::spawn(move || loader.load(loading)).join().unwrap();
+        assert_eq!(version.unwrap(), 2);
+        assert_eq!(before.get_nns_by_vector(&[3.2, 0.], 1, 1000).0, vec![3]);
+        assert_eq!(handle.get_nns_by_vector(&[3.2, 0.], 1, 1000).0, vec![0]);
+
+        let served = handle.current();
+        assert_eq!(served.version(), 2);
+        assert!(served.built_at() <= served.loaded_at());
+
+        let served = AnnoyHandle::<f64, Euclidean>::open(&path).unwrap();
+        assert_eq!(served.current().dimension(), 2);
+
+        // A file of another dimension or a broken file leaves the current
+        // index in service.
+        let mut wide: Annoy<f64, Euclidean> = Annoy::new(3);
+        wide.add_item(0, &[0., 0., 0.]).unwrap();
+        wide.build(1);
+        wide.save(File::create(&path).unwrap());
+        assert!(matches!(
+            handle.load(&path),
+            Err(Error::DimensionMismatch {
+                expected: 2,
+                found: 3
+            })
+        ));
+
+        std::fs::write(&path, b"not an index").unwrap();
+        assert!(handle.load(&path).is_err());
+        assert_eq!(handle.version(), 2);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(handle.swap(line(0.)), 3);
+        assert_eq!(handle.get_nns_by_vector(&[3.2, 0.], 1, 1000).0, vec![3]);
+    }
+}
@@ -0,0 +1,495 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::io::{self, BufWriter};
+
+use crate::checksum::{ChecksumReader, ChecksumWriter};
+use crate::distance::{Distance, NodeImpl};
+use crate::error::{check_vector, Error};
+use crate::format::{self, Kind};
+use crate::item::Item;
+use crate::neighbor::{AnnResult, Neighbor, Neighbors};
+use crate::options::QueryOptions;
+
+/// Neighbors kept per item on every layer above the bottom one, which keeps
+/// twice as many.
+pub const DEFAULT_M: usize = 16;
+const DEFAULT_EF_CONSTRUCTION: usize = 200;
+const DEFAULT_EF_SEARCH: usize = 50;
+
+/// A hierarchical navigable small world graph, an alternative to `Annoy`
+/// with higher recall for the same query time at the cost of a slower
+/// build.
+///
+/// The methods mirror those of `Annoy` and any `Distance` works, so the two
+/// can be swapped by changing the type. Where `Annoy` takes a number of
+/// trees to build and a `search_k` to query, `Hnsw` takes the size of the
+/// candidate list kept while linking an item (`ef_construction`) and while
+/// searching (`ef`).
+pub struct Hnsw<T: Item, D: Distance<T>> {
+    f: usize,
+    m: usize,
+    seed: Option<u64>,
+    n_items: i64,
+    nodes: HashMap<i64, D::Node>,
+    // links[&i][level] are the neighbors of item `i` on `level`; an item is
+    // on the levels below `links[&i].len()`.
+    links: HashMap<i64, Vec<Vec<i64>>>,
+    entry: Option<i64>,
+    built: bool,
+}
+
+impl<T: Item + Sync + Send + 'static, D: Distance<T>> Hnsw<T, D> {
+    pub fn new(f: usize) -> Self {
+        Self {
+            f,
+            m: DEFAULT_M,
+            seed: None,
+            n_items: 0,
+            nodes: HashMap::new(),
+            links: HashMap::new(),
+            entry: None,
+            built: false,
+        }
+    }
+
+    /// Sets the neighbors kept per item and layer, `DEFAULT_M` unless set.
+    /// Takes effect at the next build.
+    pub fn set_max_connections(&mut self, m: usize) {
+        self.m = m.max(2);
+    }
+
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = Some(seed);
+    }
+
+    /// Adds or replaces `item`. Fails with `Error::AlreadyBuilt` once the
+    /// index is built; `unbuild` first to add more items.
+    pub fn add_item(&mut self, item: i64, w: &[T]) -> Result<(), Error> {
+        if self.built {
+            return Err(Error::AlreadyBuilt);
+        }
+        check_vector(w, self.f)?;
+
+        let f = self.f;
+        let n = self.nodes.entry(item).or_insert(D::Node::new(f));
+        n.reset(w);
+
+        if item >= self.n_items {
+            self.n_items = item + 1;
+        }
+
+        Ok(())
+    }
+
+    /// Number of values in every vector.
+    pub fn dimension(&self) -> usize {
+        self.f
+    }
+
+    /// One more than the largest item id, as for `Annoy`.
+    pub fn n_items(&self) -> usize {
+        self.n_items as usize
+    }
+
+    pub fn contains(&self, item: i64) -> bool {
+        self.nodes.contains_key(&item)
+    }
+
+    pub fn get_item_vector(&self, item: i64) -> Option<&[T]> {
+        self.nodes.get(&item).map(|node| node.as_slice())
+    }
+
+    /// Iterates over the added items and their vectors in id order.
+    pub fn items(&self) -> impl Iterator<Item = (i64, &[T])> + '_ {
+        (0..self.n_items).filter_map(move |i| self.get_item_vector(i).map(|v| (i, v)))
+    }
+
+    pub fn is_built(&self) -> bool {
+        self.built
+    }
+
+    /// Links every item into the graph, keeping `ef_construction`
+    /// candidates per item, or 200 when -1. Panics if the index is already
+    /// built.
+    pub fn build(&mut self, ef_construction: i64) {
+        if self.built {
+            panic!("{}", Error::AlreadyBuilt);
+        }
+
+        let ef = if ef_construction < 0 {
+            DEFAULT_EF_CONSTRUCTION
+        } else {
+            (ef_construction as usize).max(1)
+        };
+        let mut rng = match self.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+
+        // Level `l` holds about 1 / m^l of the items.
+        let ml = 1. / (self.m as f64).ln();
+        let mut ids: Vec<i64> = self.nodes.keys().copied().collect();
+        ids.sort_unstable();
+
+        for id in ids {
+            let level = (-(1. - rng.gen::<f64>()).ln() * ml) as usize;
+            self.insert(id, level, ef);
+        }
+
+        self.built = true;
+    }
+
+    /// Drops the graph and keeps the items, so that items can be added and
+    /// the index built again.
+    pub fn unbuild(&mut self) -> Result<(), Error> {
+        if !self.built {
+            return Err(Error::NotBuilt);
+        }
+
+        self.links.clear();
+        self.entry = None;
+        self.built = false;
+
+        Ok(())
+    }
+
+    fn distance_to(&self, v: &[T], item: i64) -> f64 {
+        D::distance(v, self.nodes[&item].as_slice(), self.f)
+    }
+
+    /// The `ef` items on `level` nearest to `v` that a greedy search from
+    /// `entry` finds, nearest first.
+    fn search_layer(&self, v: &[T], entry: &[i64], ef: usize, level: usize) -> Vec<AnnResult> {
+        let mut visited: HashSet<i64> = entry.iter().copied().collect();
+        let mut candidates = BinaryHeap::new();
+        // The farthest of the nearest found so far is on top.
+        let mut found = BinaryHeap::new();
+
+        for &e in entry {
+            let d = self.distance_to(v, e);
+            candidates.push(Reverse(AnnResult(d, e)));
+            found.push(AnnResult(d, e));
+        }
+
+        while let Some(Reverse(nearest)) = candidates.pop() {
+            if found.len() >= ef && Some(&nearest) > found.peek() {
+                break;
+            }
+
+            for &e in &self.links[&nearest.1][level] {
+                if !visited.insert(e) {
+                    continue;
+                }
+
+                let r = AnnResult(self.distance_to(v, e), e);
+                if found.len() < ef || Some(&r) < found.peek() {
+                    candidates.push(Reverse(AnnResult(r.0, e)));
+                    found.push(r);
+                    if found.len() > ef {
+                        found.pop();
+                    }
+                }
+            }
+        }
+
+        found.into_sorted_vec()
+    }
+
+    /// Picks up to `m` of `candidates`, sorted nearest first, preferring
+    /// ones nearer to the item than to any picked before so that the links
+    /// point in different directions.
+    fn select_neighbors(&self, candidates: Vec<AnnResult>, m: usize) -> Vec<i64> {
+        let mut selected: Vec<AnnResult> = Vec::with_capacity(m);
+        let mut pruned = Vec::new();
+
+        for c in candidates {
+            if selected.len() >= m {
+                break;
+            }
+
+            let v = self.nodes[&c.1].as_slice();
+            if selected.iter().all(|s| c.0 < self.distance_to(v, s.1)) {
+                selected.push(c);
+            } else {
+                pruned.push(c);
+            }
+        }
+
+        let missing = m.saturating_sub(selected.len());
+        selected.extend(pruned.into_iter().take(missing));
+        selected.into_iter().map(|r| r.1).collect()
+    }
+
+    fn insert(&mut self, id: i64, level: usize, ef: usize) {
+        self.links.insert(id, vec![Vec::new(); level + 1]);
+
+        let entry = match self.entry {
+            Some(entry) => entry,
+            None => {
+                self.entry = Some(id);
+                return;
+            }
+        };
+        let top = self.links[&entry].len() - 1;
+        let v = self.nodes[&id].as_slice().to_vec();
+
+        let mut entries = vec![entry];
+        for l in (level + 1..=top).rev() {
+            entries = vec![self.search_layer(&v, &entries, 1, l)[0].1];
+        }
+
+        for l in (0..=level.min(top)).rev() {
+            let found = self.search_layer(&v, &entries, ef, l);
+            entries = found.iter().map(|r| r.1).collect();
+
+            let neighbors = self.select_neighbors(found, self.m);
+            let max = if l == 0 { 2 * self.m } else { self.m };
+
+            for &e in &neighbors {
+                let mut links = std::mem::take(&mut self.links.get_mut(&e).unwrap()[l]);
+                links.push(id);
+
+                if links.len() > max {
+                    let w = self.nodes[&e].as_slice();
+                    let mut candidates: Vec<AnnResult> = links
+                        .iter()
+                        .map(|&x| AnnResult(self.distance_to(w, x), x))
+                        .collect();
+                    candidates.sort_unstable();
+                    links = self.select_neighbors(candidates, max);
+                }
+
+                self.links.get_mut(&e).unwrap()[l] = links;
+            }
+            self.links.get_mut(&id).unwrap()[l] = neighbors;
+        }
+
+        if level > top {
+            self.entry = Some(id);
+        }
+    }
+
+    /// Panics if `v` contains NaN or an infinity; `query` returns the error
+    /// instead.
+    pub fn get_nns_by_vector(&self, v: &[T], n: usize, ef: i64) -> (Vec<i64>, Vec<f64>) {
+        match self.query(v, QueryOptions::new(n).search_k(ef)) {
+            Ok(neighbors) => neighbors.into_parts(),
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    pub fn get_nns_by_item(&self, item: i64, n: usize, ef: i64) -> (Vec<i64>, Vec<f64>) {
+        let options = QueryOptions::new(n).search_k(ef);
+
+        self.query_by_item(item, options).unwrap().into_parts()
+    }
+
+    /// Finds the neighbors of `v` as configured by `options`, whose
+    /// `search_k` is taken as `ef`: the number of candidates kept during
+    /// the search, at least `n` and 50 when -1. Fails with
+    /// `Error::DimensionMismatch` if `v` does not have the index's dimension
+    /// and `Error::NonFinite` if it contains NaN or an infinity.
+    pub fn query(&self, v: &[T], options: QueryOptions) -> Result<Neighbors<T>, Error> {
+        check_vector(v, self.f)?;
+
+        Ok(self._query(v, options))
+    }
+
+    /// Like `query` for the vector of `item`, or `None` if there is no such
+    /// item.
+    pub fn query_by_item(&self, item: i64, options: QueryOptions) -> Option<Neighbors<T>> {
+        let v = self.get_item_vector(item)?;
+
+        Some(self._query(v, options))
+    }
+
+    fn _query(&self, v: &[T], options: QueryOptions) -> Neighbors<T> {
+        let entry = match self.entry {
+            Some(entry) if self.built => entry,
+            _ => return std::iter::empty().collect(),
+        };
+
+        let ef = if options.search_k < 0 {
+            DEFAULT_EF_SEARCH
+        } else {
+            options.search_k as usize
+        };
+        let ef = ef.max(options.n);
+
+        let mut entries = vec![entry];
+        for l in (1..self.links[&entry].len()).rev() {
+            entries = vec![self.search_layer(v, &entries, 1, l)[0].1];
+        }
+
+        self.search_layer(v, &entries, ef, 0)
+            .into_iter()
+            .take(options.n)
+            .map(|AnnResult(d, id)| Neighbor::new::<D>(id, d, options, self.get_item_vector(id)))
+            .collect()
+    }
+
+    pub fn save<W>(&self, w: W)
+    where
+        W: std::io::Write,
+    {
+        self.try_save(w).unwrap();
+    }
+
+    pub(crate) fn try_save<W: io::Write>(&self, w: W) -> io::Result<()> {
+        let mut f = BufWriter::new(w);
+        let mut f = ChecksumWriter::new(&mut f);
+        format::write_header(&mut f, Kind::Hnsw)?;
+
+        let data = (self.m, self.entry, &self.nodes, &self.links);
+        bincode::serialize_into(&mut f, &data).map_err(io::Error::other)?;
+        f.finish()?;
+
+        Ok(())
+    }
+
+    /// Reads an index written by `save`. Returns false when the data cannot
+    /// be decoded, is of another format version or index type or does not
+    /// match its checksum, leaving the index as it was. The dimension is
+    /// taken from the loaded items.
+    pub fn load<R>(&mut self, reader: R) -> bool
+    where
+        R: std::io::BufRead,
+    {
+        self.try_load(reader).is_ok()
+    }
+
+    pub(crate) fn try_load<R: io::BufRead>(&mut self, reader: R) -> io::Result<()> {
+        let mut reader = ChecksumReader::new(reader);
+        format::read_header(&mut reader, Kind::Hnsw)?;
+        let (m, entry, nodes, links): (usize, Option<i64>, HashMap<i64, D::Node>, _) =
+            bincode::deserialize_from(&mut reader).map_err(format::to_io)?;
+        reader.verify()?;
+        let f = format::dimension(nodes.values().map(|node| node.as_slice()))?;
+
+        self.f = f.unwrap_or(self.f);
+        self.m = m;
+        self.entry = entry;
+        self.n_items = nodes.keys().max().map_or(0, |i| i + 1);
+        self.nodes = nodes;
+        self.links = links;
+        self.built = true;
+
+        Ok(())
+    }
+
+    pub fn get_distance(&self, i: i64, j: i64) -> f64 {
+        let dist = self.distance_to(self.nodes[&i].as_slice(), j);
+        D::normalized_distance(dist)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Angular, Euclidean, Manhattan};
+
+    fn recall<D: Distance<f64>>() -> f64 {
+        let mut rng = StdRng::seed_from_u64(3);
+        let items: Vec<Vec<f64>> = (0..1000)
+            .map(|_| (0..8).map(|_| rng.gen_range(-1.0..1.0)).collect())
+            .collect();
+
+        let mut hnsw: Hnsw<f64, D> = Hnsw::new(8);
+        hnsw.set_seed(1);
+        for (i, v) in items.iter().enumerate() {
+            hnsw.add_item(i as i64, v).unwrap();
+        }
+        hnsw.build(-1);
+
+        let mut found = 0;
+        for q in items.iter().take(50) {
+            let mut all: Vec<AnnResult> = items
+                .iter()
+                .enumerate()
+                .map(|(i, v)| AnnResult(D::distance(q, v, 8), i as i64))
+                .collect();
+            all.sort_unstable();
+
+            let (ids, _) = hnsw.get_nns_by_vector(q, 10, -1);
+            found += all[..10].iter().filter(|r| ids.contains(&r.1)).count();
+        }
+
+        found as f64 / 500.
+    }
+
+    #[test]
+    fn test_recall() {
+        assert!(recall::<Euclidean>() >= 0.95);
+        assert!(recall::<Angular>() >= 0.95);
+        assert!(recall::<Manhattan>() >= 0.95);
+    }
+
+    #[test]
+    fn test_build_save_load() {
+        let mut hnsw: Hnsw<f64, Euclidean> = Hnsw::new(2);
+        for i in 0..100 {
+            hnsw.add_item(i, &[(i % 10) as f64, (i / 10) as f64])
+                .unwrap();
+        }
+        assert!(hnsw.get_nns_by_vector(&[0., 0.], 1, -1).0.is_empty());
+
+        hnsw.build(-1);
+        assert!(matches!(
+            hnsw.add_item(100, &[0., 0.]),
+            Err(Error::AlreadyBuilt)
+        ));
+
+        let (ids, distances) = hnsw.get_nns_by_item(55, 3, -1);
+        assert_eq!(ids[0], 55);
+        assert_eq!(distances, vec![0., 1., 1.]);
+
+        let mut buf = Vec::new();
+        hnsw.save(&mut buf);
+
+        let mut loaded: Hnsw<f64, Euclidean> = Hnsw::new(2);
+        assert!(loaded.load(buf.as_slice()));
+        assert_eq!(loaded.n_items(), 100);
+        assert_eq!(
+            loaded.get_nns_by_vector(&[2.2, 3.1], 5, -1),
+            hnsw.get_nns_by_vector(&[2.2, 3.1], 5, -1)
+        );
+
+        buf[0] ^= 1;
+        assert!(!Hnsw::<f64, Euclidean>::new(2).load(buf.as_slice()));
+
+        hnsw.unbuild().unwrap();
+        hnsw.add_item(100, &[0., 0.]).unwrap();
+    }
+
+    #[test]
+    fn test_load_restores_dimension() {
+        let mut hnsw: Hnsw<f64, Euclidean> = Hnsw::new(2);
+        for i in 0..100 {
+            hnsw.add_item(i, &[(i % 10) as f64, (i / 10) as f64])
+                .unwrap();
+        }
+        hnsw.build(-1);
+        let mut buf = Vec::new();
+        hnsw.save(&mut buf);
+
+        for f in [0, 3] {
+            let mut loaded: Hnsw<f64, Euclidean> = Hnsw::new(f);
+            assert!(loaded.load(buf.as_slice()));
+            assert_eq!(loaded.dimension(), 2);
+            assert_eq!(
+                loaded.get_nns_by_vector(&[2.2, 3.1], 5, -1),
+                hnsw.get_nns_by_vector(&[2.2, 3.1], 5, -1)
+            );
+        }
+
+        hnsw.nodes.get_mut(&7).unwrap().v.push(0.);
+        buf.clear();
+        hnsw.save(&mut buf);
+        let e = Hnsw::<f64, Euclidean>::new(2)
+            .try_load(buf.as_slice())
+            .unwrap_err();
+        assert!(e.to_string().contains("different dimensions"));
+    }
+}
@@ -0,0 +1,291 @@
+use std::borrow::Cow;
+use std::io::{BufRead, Write};
+
+use crate::ann::Annoy;
+use crate::brute::BruteForce;
+use crate::distance::Distance;
+use crate::error::Error;
+use crate::hnsw::Hnsw;
+use crate::item::Item;
+use crate::neighbor::Neighbors;
+use crate::options::{BuildOptions, QueryOptions};
+
+/// The operations every index of the crate offers, so that code can be
+/// generic over the algorithm or hold any index as `dyn AnnIndex<T>`.
+///
+/// Unlike the inherent methods, `build`, `save` and `load` report failures
+/// as errors instead of panicking or returning false.
+pub trait AnnIndex<T: Item> {
+    /// Number of values in every vector.
+    fn dimension(&self) -> usize;
+
+    /// One more than the largest item id.
+    fn n_items(&self) -> usize;
+
+    /// The vector of `item`, owned when it was read from disk.
+    fn get_item_vector(&self, item: i64) -> Option<Cow<'_, [T]>>;
+
+    fn add_item(&mut self, item: i64, w: &[T]) -> Result<(), Error>;
+
+    /// Makes the added items searchable. `q` is the build parameter of the
+    /// index, the number of trees for `Annoy` and `ef_construction` for
+    /// `Hnsw`, where -1 picks a default; `BruteForce` ignores it.
+    fn build(&mut self, q: i64) -> Result<(), Error>;
+
+    fn query(&self, v: &[T], options: QueryOptions) -> Result<Neighbors<T>, Error>;
+
+    fn query_by_item(&self, item: i64, options: QueryOptions) -> Option<Neighbors<T>>;
+
+    fn save(&self, w: &mut dyn Write) -> Result<(), Error>;
+
+    /// Replaces the index with one written by `save`, or leaves it as it
+    /// was on failure.
+    fn load(&mut self, r: &mut dyn BufRead) -> Result<(), Error>;
+
+    /// Panics if `v` contains NaN or an infinity.
+    fn get_nns_by_vector(&self, v: &[T], n: usize, search_k: i64) -> (Vec<i64>, Vec<f64>) {
+        match self.query(v, QueryOptions::new(n).search_k(search_k)) {
+            Ok(neighbors) => neighbors.into_parts(),
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Panics if there is no such item.
+    fn get_nns_by_item(&self, item: i64, n: usize, search_k: i64) -> (Vec<i64>, Vec<f64>) {
+        let options = QueryOptions::new(n).search_k(search_k);
+
+        self.query_by_item(item, options).unwrap().into_parts()
+    }
+}
+
+impl<T, D> AnnIndex<T> for Annoy<T, D>
+where
+    T: Item + Sync + Send + 'static,
+    D: Distance<T> + 'static,
+    D::Node: Sync + Send,
+{
+    fn dimension(&self) -> usize {
+        Annoy::dimension(self)
+    }
+
+    fn n_items(&self) -> usize {
+        Annoy::n_items(self)
+    }
+
+    fn get_item_vector(&self, item: i64) -> Option<Cow<'_, [T]>> {
+        Annoy::get_item_vector(self, item)
+    }
+
+    fn add_item(&mut self, item: i64, w: &[T]) -> Result<(), Error> {
+        Annoy::add_item(self, item, w)
+    }
+
+    fn build(&mut self, q: i64) -> Result<(), Error> {
+        self.build_with(BuildOptions::new(q))
+    }
+
+    fn query(&self, v: &[T], options: QueryOptions) -> Result<Neighbors<T>, Error> {
+        Annoy::query(self, v, options)
+    }
+
+    fn query_by_item(&self, item: i64, options: QueryOptions) -> Option<Neighbors<T>> {
+        Annoy::query_by_item(self, item, options)
+    }
+
+    fn save(&self, w: &mut dyn Write) -> Result<(), Error> {
+        Ok(self.try_save(w)?)
+    }
+
+    fn load(&mut self, r: &mut dyn BufRead) -> Result<(), Error> {
+        Ok(self.try_load(r)?)
+    }
+}
+
+impl<T, D> AnnIndex<T> for Hnsw<T, D>
+where
+    T: Item + Sync + Send + 'static,
+    D: Distance<T>,
+{
+    fn dimension(&self) -> usize {
+        Hnsw::dimension(self)
+    }
+
+    fn n_items(&self) -> usize {
+        Hnsw::n_items(self)
+    }
+
+    fn get_item_vector(&self, item: i64) -> Option<Cow<'_, [T]>> {
+        Hnsw::get_item_vector(self, item).map(Cow::Borrowed)
+    }
+
+    fn add_item(&mut self, item: i64, w: &[T]) -> Result<(), Error> {
+        Hnsw::add_item(self, item, w)
+    }
+
+    fn build(&mut self, q: i64) -> Result<(), Error> {
+        if self.is_built() {
+            return Err(Error::AlreadyBuilt);
+        }
+
+        Hnsw::build(self, q);
+        Ok(())
+    }
+
+    fn query(&self, v: &[T], options: QueryOptions) -> Result<Neighbors<T>, Error> {
+        Hnsw::query(self, v, options)
+    }
+
+    fn query_by_item(&self, item: i64, options: QueryOptions) -> Option<Neighbors<T>> {
+        Hnsw::query_by_item(self, item, options)
+    }
+
+    fn save(&self, w: &mut dyn Write) -> Result<(), Error> {
+        Ok(self.try_save(w)?)
+    }
+
+    fn load(&mut self, r: &mut dyn BufRead) -> Result<(), Error> {
+        Ok(self.try_load(r)?)
+    }
+}
+
+impl<T, D> AnnIndex<T> for BruteForce<T, D>
+where
+    T: Item + Sync + Send + 'static,
+    D: Distance<T>,
+{
+    fn dimension(&self) -> usize {
+        BruteForce::dimension(self)
+    }
+
+    fn n_items(&self) -> usize {
+        BruteForce::n_items(self)
+    }
+
+    fn get_item_vector(&self, item: i64) -> Option<Cow<'_, [T]>> {
+        BruteForce::get_item_vector(self, item).map(Cow::Borrowed)
+    }
+
+    fn add_item(&mut self, item: i64, w: &[T]) -> Result<(), Error> {
+        BruteForce::add_item(self, item, w)
+    }
+
+    fn build(&mut self, _q: i64) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn query(&self, v: &[T], options: QueryOptions) -> Result<Neighbors<T>, Error> {
+        BruteForce::query(self, v, options)
+    }
+
+    fn query_by_item(&self, item: i64, options: QueryOptions) -> Option<Neighbors<T>> {
+        BruteForce::query_by_item(self, item, options)
+    }
+
+    fn save(&self, w: &mut dyn Write) -> Result<(), Error> {
+        Ok(self.try_save(w)?)
+    }
+
+    fn load(&mut self, r: &mut dyn BufRead) -> Result<(), Error> {
+        Ok(self.try_load(r)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Euclidean;
+
+    fn grid(index: &mut dyn AnnIndex<f64>) {
+        for i in 0..100 {
+            index
+                .add_item(i, &[(i % 10) as f64, (i / 10) as f64])
+                .unwrap();
+        }
+        index.build(-1).unwrap();
+    }
+
+    /// Checks `index` against the exact answer and a save/load round trip.
+    fn check(mut index: Box<dyn AnnIndex<f64>>, mut empty: Box<dyn AnnIndex<f64>>) {
+        grid(index.as_mut());
+
+        let mut exact: BruteForce<f64, Euclidean> = BruteForce::new(2);
+        grid(&mut exact);
+
+        let v = [2.2, 3.1];
+        let options = QueryOptions::new(5).search_k(1000);
+        let expected = AnnIndex::query(&exact, &v, options).unwrap();
+        assert_eq!(index.query(&v, options).unwrap().ids(), expected.ids());
+        assert_eq!(index.get_nns_by_item(32, 1, 1000).0, vec![32]);
+        assert!(matches!(
+            index.query(&[f64::NAN, 0.], options),
+            Err(Error::NonFinite)
+        ));
+        assert!(matches!(
+            index.query(&[1.], options),
+            Err(Error::DimensionMismatch {
+                expected: 2,
+                found: 1
+            })
+        ));
+        assert!(matches!(
+            empty.add_item(0, &[1., 2., 3.]),
+            Err(Error::DimensionMismatch {
+                expected: 2,
+                found: 3
+            })
+        ));
+
+        let mut buf = Vec::new();
+        index.save(&mut buf).unwrap();
+        empty.load(&mut buf.as_slice()).unwrap();
+        assert_eq!(empty.n_items(), 100);
+        assert_eq!(
+            empty.get_nns_by_vector(&v, 5, 1000),
+            index.get_nns_by_vector(&v, 5, 1000)
+        );
+
+        buf[0] ^= 1;
+        assert!(matches!(empty.load(&mut buf.as_slice()), Err(Error::Io(_))));
+    }
+
+    #[test]
+    fn test_implementations() {
+        check(
+            Box::new(Annoy::<f64, Euclidean>::new(2)),
+            Box::new(Annoy::<f64, Euclidean>::new(2)),
+        );
+        check(
+            Box::new(Hnsw::<f64, Euclidean>::new(2)),
+            Box::new(Hnsw::<f64, Euclidean>::new(2)),
+        );
+        check(
+            Box::new(BruteForce::<f64, Euclidean>::new(2)),
+            Box::new(BruteForce::<f64, Euclidean>::new(2)),
+        );
+    }
+
+    #[test]
+    fn test_load_checks_kind() {
+        let mut annoy: Annoy<f64, Euclidean> = Annoy::new(2);
+        grid(&mut annoy);
+        let mut saved = Vec::new();
+        annoy.save(&mut saved);
+
+        let mut brute: BruteForce<f64, Euclidean> = BruteForce::new(2);
+        let e = brute.try_load(saved.as_slice()).unwrap_err();
+        assert!(e.to_string().contains("saved by Annoy, not BruteForce"));
+        assert!(!Hnsw::<f64, Euclidean>::new(2).load(saved.as_slice()));
+
+        // The dimension comes from the file.
+        grid(&mut brute);
+        saved.clear();
+        brute.save(&mut saved);
+        let mut loaded: BruteForce<f64, Euclidean> = BruteForce::new(5);
+        assert!(loaded.load(saved.as_slice()));
+        assert_eq!(loaded.dimension(), 2);
+        assert_eq!(
+            loaded.get_nns_by_vector(&[2.2, 3.1], 3, -1),
+            brute.get_nns_by_vector(&[2.2, 3.1], 3, -1)
+        );
+    }
+}
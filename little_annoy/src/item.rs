@@ -1,26 +1,192 @@
-use num::{traits::NumAssign, FromPrimitive, One, Signed, ToPrimitive, Zero};
+use std::fmt::Debug;
 
-pub trait Item:
-    Zero + One + NumAssign + ToPrimitive + FromPrimitive + PartialOrd + Signed + Clone + Copy
+use num::integer::Roots;
+use num::{traits::NumAssign, Bounded, FromPrimitive, One, Signed, ToPrimitive, Zero};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// The arithmetic items and the values computed from them have in common.
+pub trait Number:
+    Zero
+    + One
+    + NumAssign
+    + ToPrimitive
+    + FromPrimitive
+    + PartialOrd
+    + Bounded
+    + Clone
+    + Copy
+    + Debug
+    + Send
+    + Sync
+    + 'static
+    + Serialize
+    + DeserializeOwned
 {
-    fn sqrt(self) -> Self {
-        let v = Self::to_f64(&self)
-            .map(|v| v.sqrt())
-            .and_then(Self::from_f64);
-        v.unwrap_or_else(|| Self::zero())
+    /// The square root, rounded down for integers and NaN for negative
+    /// floats.
+    ///
+    /// # Panics
+    ///
+    /// Panics for a negative integer, which has no square root to round.
+    fn sqrt(self) -> Self;
+
+    /// `self + other`, stopping at the bounds of integer types instead of
+    /// overflowing.
+    fn saturating_add(self, other: Self) -> Self;
+
+    /// `self * other`, stopping at the bounds of integer types instead of
+    /// overflowing.
+    fn saturating_mul(self, other: Self) -> Self;
+
+    /// False for NaN and infinities, which integer types do not have.
+    fn is_finite(self) -> bool {
+        self.to_f64().is_some_and(f64::is_finite)
     }
 }
 
-impl Item for isize {}
+/// A type vectors are stored as.
+pub trait Item: Number {
+    /// What `Euclidean`, `Manhattan` and `Hamming` sum distances in, exactly
+    /// for integers, before they are returned as `f64`.
+    ///
+    /// It is signed, so that differences of items fit, and wide enough for
+    /// sums of squared differences of items: `i8` and `u8` accumulate into
+    /// `i32`, `i16` and `u16` into `i64`, and wider integers into `i128`,
+    /// where sums past its range saturate. Floats accumulate into
+    /// themselves.
+    type Acc: Number + Signed;
+
+    /// What split planes are stored as: the item type itself for floats,
+    /// so that an `f32` index stays half the size of an `f64` one, and
+    /// `f64` for integers, which cannot hold a unit normal. Planes and
+    /// margins are computed in `f64` for every item type.
+    type Plane: Number;
+
+    /// The bits of a value `Hamming` compares: all of them for integers,
+    /// and one for floats, which hold 0 or 1.
+    const BITS: usize;
+
+    /// The item as an accumulator, without loss.
+    fn widen(self) -> Self::Acc;
+
+    /// The `BITS` bits `Hamming` compares, in the lowest bits: the two's
+    /// complement of integers, and 1 for any float but zero.
+    fn bits(self) -> u64;
+}
+
+macro_rules! float {
+    ($($t:ty),*) => {$(
+        impl Number for $t {
+            fn sqrt(self) -> Self {
+                <$t>::sqrt(self)
+            }
+
+            fn saturating_add(self, other: Self) -> Self {
+                self + other
+            }
+
+            fn saturating_mul(self, other: Self) -> Self {
+                self * other
+            }
+        }
 
-impl Item for i8 {}
+        impl Item for $t {
+            type Acc = $t;
+            type Plane = $t;
 
-impl Item for i16 {}
+            const BITS: usize = 1;
 
-impl Item for f64 {}
+            fn widen(self) -> $t {
+                self
+            }
 
-impl Item for f32 {}
+            fn bits(self) -> u64 {
+                (self != 0.0) as u64
+            }
+        }
+    )*};
+}
+
+macro_rules! integer {
+    ($($t:ty),*) => {$(
+        impl Number for $t {
+            fn sqrt(self) -> Self {
+                Roots::sqrt(&self)
+            }
 
-impl Item for i64 {}
+            fn saturating_add(self, other: Self) -> Self {
+                <$t>::saturating_add(self, other)
+            }
+
+            fn saturating_mul(self, other: Self) -> Self {
+                <$t>::saturating_mul(self, other)
+            }
+        }
+    )*};
+}
 
-impl Item for i32 {}
+macro_rules! item {
+    ($($t:ty => $acc:ty),*) => {$(
+        impl Item for $t {
+            type Acc = $acc;
+            type Plane = f64;
+
+            const BITS: usize = <$t>::BITS as usize;
+
+            fn widen(self) -> $acc {
+                self as $acc
+            }
+
+            fn bits(self) -> u64 {
+                self as u64 & (u64::MAX >> (64 - Self::BITS))
+            }
+        }
+    )*};
+}
+
+float!(f32, f64);
+
+integer!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, usize);
+
+item!(
+    i8 => i32,
+    u8 => i32,
+    i16 => i64,
+    u16 => i64,
+    i32 => i128,
+    u32 => i128,
+    i64 => i128,
+    u64 => i128,
+    isize => i128,
+    usize => i128
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sqrt() {
+        assert_eq!(Number::sqrt(17u8), 4);
+        assert_eq!(Number::sqrt(u64::MAX), u32::MAX as u64);
+        assert_eq!(Number::sqrt(2.25f32), 1.5);
+        assert!(Number::sqrt(-1.0f64).is_nan());
+        assert!(std::panic::catch_unwind(|| Number::sqrt(-4i32)).is_err());
+    }
+
+    #[test]
+    fn test_widen() {
+        assert_eq!(u8::MAX.widen() * u8::MAX.widen() * 1000, 65_025_000);
+        assert_eq!(u64::MAX.widen(), u64::MAX as i128);
+        assert_eq!((-3i16).widen(), -3i64);
+    }
+
+    #[test]
+    fn test_bits() {
+        assert_eq!((-1i8).bits(), 0xff);
+        assert_eq!((-1i64).bits(), u64::MAX);
+        assert_eq!(u64::MAX.bits(), u64::MAX);
+        assert_eq!(0b101u16.bits(), 0b101);
+        assert_eq!((1.0f32.bits(), 0.0f64.bits()), (1, 0));
+    }
+}
@@ -1,29 +1,54 @@
 use std::cmp::Ordering;
 
 pub mod ann;
+#[cfg(feature = "async")]
+mod async_annoy;
+mod brute;
+mod checksum;
+mod collection;
 mod distance;
+mod error;
+mod format;
+mod handle;
+pub mod hnsw;
+mod index;
 mod item;
+pub mod loader;
+mod neighbor;
+mod options;
+mod segment;
+mod stats;
+mod store;
+mod verify;
 
+pub use brute::BruteForce;
+pub use collection::{AnnoyCollection, ShardNeighbor};
 pub use distance::Angular;
 pub use distance::Euclidean;
 pub use distance::Hamming;
 pub use distance::Manhattan;
+pub use distance::{Distance, Metric, NodeImpl, ParseMetricError};
+pub use error::Error;
+pub use handle::{AnnoyHandle, ServedIndex};
+pub use index::AnnIndex;
+pub use item::{Item, Number};
+pub use neighbor::{Neighbor, NeighborIter, Neighbors};
+pub use options::{BuildOptions, BuildProgress, CancellationToken, QueryOptions, SegmentOptions};
+#[cfg(feature = "parallel")]
+pub use segment::Maintenance;
+pub use segment::SegmentedIndex;
+pub use stats::{IndexStats, NodeKind, TreeIter, TreeNode, TreeStats};
+pub use verify::Corruption;
 
 pub use ann::Annoy;
+#[cfg(feature = "async")]
+pub use async_annoy::AsyncAnnoy;
+pub use hnsw::Hnsw;
 
-#[derive(PartialEq)]
-struct Numeric<T: item::Item>(T);
-
-impl<T: item::Item> Eq for Numeric<T> {}
-
-impl<T: item::Item> PartialOrd for Numeric<T> {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        self.0.partial_cmp(&other.0)
-    }
-}
-
-impl<T: item::Item> Ord for Numeric<T> {
-    fn cmp(&self, other: &Self) -> Ordering {
-        self.0.partial_cmp(&other.0).unwrap_or(Ordering::Equal)
-    }
+/// Orders distances as queries rank them: by value, with NaN after every
+/// number whatever its sign, so that a NaN distance is never preferred.
+/// Every ranking in the crate uses it, search priorities included.
+pub fn cmp_distance(a: f64, b: f64) -> Ordering {
+    a.partial_cmp(&b)
+        .unwrap_or_else(|| a.is_nan().cmp(&b.is_nan()))
 }
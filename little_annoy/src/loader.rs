@@ -0,0 +1,657 @@
+//! Streaming readers for the common vector file formats.
+//!
+//! Each reader yields `(id, vector)` pairs and knows the dimension of its
+//! vectors up front, so an `Annoy` can be created before the data is read.
+//! Ids come from the row number unless an id column is given.
+
+use std::fmt;
+use std::io::{self, BufRead, Read};
+use std::marker::PhantomData;
+
+use crate::ann::Annoy;
+use crate::distance::Distance;
+use crate::error::Error;
+use crate::item::Item;
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    Format(String),
+    /// The index refused an item, e.g. because it is already built.
+    Index(Error),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io(e) => write!(f, "{}", e),
+            LoadError::Format(s) => f.write_str(s),
+            LoadError::Index(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Io(e) => Some(e),
+            LoadError::Format(_) => None,
+            LoadError::Index(e) => Some(e),
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(e: io::Error) -> Self {
+        LoadError::Io(e)
+    }
+}
+
+impl From<Error> for LoadError {
+    fn from(e: Error) -> Self {
+        LoadError::Index(e)
+    }
+}
+
+fn format_error<S: Into<String>>(s: S) -> LoadError {
+    LoadError::Format(s.into())
+}
+
+pub type Row<T> = Result<(i64, Vec<T>), LoadError>;
+
+/// Adds every row to `ann`, returning the number of items added.
+pub fn add_items<T, D, I>(ann: &mut Annoy<T, D>, rows: I) -> Result<usize, LoadError>
+where
+    T: Item + Sync + Send + 'static,
+    D: Distance<T>,
+    I: IntoIterator<Item = Row<T>>,
+{
+    let mut count = 0;
+
+    for row in rows {
+        let (id, v) = row?;
+
+        if v.len() != ann._f {
+            return Err(format_error(format!(
+                "item {} has {} dimensions, expected {}",
+                id,
+                v.len(),
+                ann._f
+            )));
+        }
+        if id < 0 {
+            return Err(format_error(format!("item id {} is negative", id)));
+        }
+
+        ann.add_item(id, &v)?;
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dtype {
+    F4,
+    F8,
+    I1,
+    U1,
+    I2,
+    I4,
+    I8,
+}
+
+impl Dtype {
+    fn parse(descr: &str) -> Option<Self> {
+        match descr {
+            "<f4" => Some(Dtype::F4),
+            "<f8" => Some(Dtype::F8),
+            "|i1" | "<i1" => Some(Dtype::I1),
+            "|u1" | "<u1" => Some(Dtype::U1),
+            "<i2" => Some(Dtype::I2),
+            "<i4" => Some(Dtype::I4),
+            "<i8" => Some(Dtype::I8),
+            _ => None,
+        }
+    }
+
+    fn width(self) -> usize {
+        match self {
+            Dtype::I1 | Dtype::U1 => 1,
+            Dtype::I2 => 2,
+            Dtype::F4 | Dtype::I4 => 4,
+            Dtype::F8 | Dtype::I8 => 8,
+        }
+    }
+
+    fn decode<T: Item>(self, b: &[u8]) -> Option<T> {
+        match self {
+            Dtype::F4 => T::from_f32(f32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+            Dtype::F8 => T::from_f64(f64::from_le_bytes([
+                b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7],
+            ])),
+            Dtype::I1 => T::from_i8(b[0] as i8),
+            Dtype::U1 => T::from_u8(b[0]),
+            Dtype::I2 => T::from_i16(i16::from_le_bytes([b[0], b[1]])),
+            Dtype::I4 => T::from_i32(i32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+            Dtype::I8 => T::from_i64(i64::from_le_bytes([
+                b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7],
+            ])),
+        }
+    }
+}
+
+fn decode_row<T: Item>(dtype: Dtype, buf: &[u8], row: usize, id_column: Option<usize>) -> Row<T> {
+    let mut id = row as i64;
+    let mut v = Vec::with_capacity(buf.len() / dtype.width());
+
+    for (col, b) in buf.chunks_exact(dtype.width()).enumerate() {
+        if Some(col) == id_column {
+            id = dtype
+                .decode(b)
+                .ok_or_else(|| format_error(format!("row {}: id does not fit in i64", row)))?;
+        } else {
+            v.push(dtype.decode(b).ok_or_else(|| {
+                format_error(format!(
+                    "row {}, column {}: value does not fit the item type",
+                    row, col
+                ))
+            })?);
+        }
+    }
+
+    Ok((id, v))
+}
+
+/// Reads a 1-d or 2-d little-endian, C-ordered NumPy `.npy` array row by row.
+pub struct NpyReader<R, T> {
+    reader: R,
+    dtype: Dtype,
+    rows: usize,
+    cols: usize,
+    row: usize,
+    id_column: Option<usize>,
+    buf: Vec<u8>,
+    t: PhantomData<T>,
+}
+
+// Pulls the raw value of `key` out of the python dict literal in a npy header.
+fn header_value<'a>(header: &'a str, key: &str) -> Option<&'a str> {
+    let start = header.find(&format!("'{}'", key))? + key.len() + 2;
+    let rest = header[start..].trim_start().strip_prefix(':')?.trim_start();
+
+    let end = if rest.starts_with('(') {
+        rest.find(')')? + 1
+    } else {
+        rest.find(',').unwrap_or(rest.len())
+    };
+
+    Some(rest[..end].trim())
+}
+
+impl<R: Read, T: Item> NpyReader<R, T> {
+    pub fn new(mut reader: R) -> Result<Self, LoadError> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+
+        if &magic[..6] != b"\x93NUMPY" {
+            return Err(format_error("not a .npy file"));
+        }
+
+        let header_len = if magic[6] == 1 {
+            let mut len = [0u8; 2];
+            reader.read_exact(&mut len)?;
+            u16::from_le_bytes(len) as usize
+        } else {
+            let mut len = [0u8; 4];
+            reader.read_exact(&mut len)?;
+            u32::from_le_bytes(len) as usize
+        };
+
+        let mut header = vec![0u8; header_len];
+        reader.read_exact(&mut header)?;
+        let header = String::from_utf8_lossy(&header);
+
+        let descr = header_value(&header, "descr")
+            .ok_or_else(|| format_error("npy header has no `descr`"))?
+            .trim_matches(|c| c == '\'' || c == '"');
+        let dtype = Dtype::parse(descr)
+            .ok_or_else(|| format_error(format!("unsupported npy dtype `{}`", descr)))?;
+
+        if header_value(&header, "fortran_order") == Some("True") {
+            return Err(format_error("fortran-ordered npy arrays are not supported"));
+        }
+
+        let shape = header_value(&header, "shape")
+            .ok_or_else(|| format_error("npy header has no `shape`"))?;
+        let shape = shape
+            .trim_matches(|c| c == '(' || c == ')')
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|s| s.parse::<usize>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| format_error(format!("invalid npy shape {}", shape)))?;
+
+        let (rows, cols) = match shape[..] {
+            [rows, cols] => (rows, cols),
+            [cols] => (1, cols),
+            _ => {
+                return Err(format_error(format!(
+                    "expected a 1-d or 2-d npy array, got shape {:?}",
+                    shape
+                )))
+            }
+        };
+
+        Ok(Self {
+            reader,
+            dtype,
+            rows,
+            cols,
+            row: 0,
+            id_column: None,
+            buf: vec![0u8; dtype.width() * cols],
+            t: PhantomData,
+        })
+    }
+
+    /// Takes item ids from column `col` instead of the row number.
+    pub fn id_column(mut self, col: usize) -> Self {
+        self.id_column = Some(col);
+        self
+    }
+
+    /// Number of values per vector: the columns of the array less the id
+    /// column. Fails when the id column is not one of the columns.
+    pub fn dimension(&self) -> Result<usize, LoadError> {
+        match self.id_column {
+            Some(col) if col >= self.cols => Err(format_error(format!(
+                "id column {} is out of range for {} columns",
+                col, self.cols
+            ))),
+            Some(_) => Ok(self.cols - 1),
+            None => Ok(self.cols),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows == 0
+    }
+}
+
+impl<R: Read, T: Item> Iterator for NpyReader<R, T> {
+    type Item = Row<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.row >= self.rows {
+            return None;
+        }
+
+        let row = self.row;
+        self.row += 1;
+
+        if let Err(e) = self.reader.read_exact(&mut self.buf) {
+            self.row = self.rows;
+            return Some(Err(e.into()));
+        }
+
+        Some(decode_row(self.dtype, &self.buf, row, self.id_column))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VecsKind {
+    /// `.fvecs`: float32 components.
+    Fvecs,
+    /// `.ivecs`: int32 components.
+    Ivecs,
+    /// `.bvecs`: uint8 components.
+    Bvecs,
+}
+
+impl VecsKind {
+    fn dtype(self) -> Dtype {
+        match self {
+            VecsKind::Fvecs => Dtype::F4,
+            VecsKind::Ivecs => Dtype::I4,
+            VecsKind::Bvecs => Dtype::U1,
+        }
+    }
+}
+
+// Reads a little-endian u32, or None on a clean end of file.
+fn read_dimension<R: Read>(reader: &mut R) -> io::Result<Option<usize>> {
+    let mut buf = [0u8; 4];
+    let mut filled = 0;
+
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) if filled == 0 => return Ok(None),
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(Some(u32::from_le_bytes(buf) as usize))
+}
+
+/// Reads the `.fvecs`/`.ivecs`/`.bvecs` formats of the standard ANN benchmarks,
+/// where every vector is prefixed with its dimension as a little-endian int32.
+pub struct VecsReader<R, T> {
+    reader: R,
+    dtype: Dtype,
+    dimension: usize,
+    pending: Option<usize>,
+    row: usize,
+    buf: Vec<u8>,
+    t: PhantomData<T>,
+}
+
+impl<R: Read, T: Item> VecsReader<R, T> {
+    pub fn new(mut reader: R, kind: VecsKind) -> Result<Self, LoadError> {
+        let dimension = read_dimension(&mut reader)?.unwrap_or(0);
+        let dtype = kind.dtype();
+
+        Ok(Self {
+            reader,
+            dtype,
+            dimension,
+            pending: Some(dimension).filter(|d| *d > 0),
+            row: 0,
+            buf: vec![0u8; dtype.width() * dimension],
+            t: PhantomData,
+        })
+    }
+
+    pub fn dimension(&self) -> usize {
+        self.dimension
+    }
+}
+
+impl<R: Read, T: Item> Iterator for VecsReader<R, T> {
+    type Item = Row<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let dimension = match self.pending.take() {
+            Some(d) => d,
+            None => match read_dimension(&mut self.reader) {
+                Ok(Some(d)) => d,
+                Ok(None) => return None,
+                Err(e) => return Some(Err(e.into())),
+            },
+        };
+
+        let row = self.row;
+        self.row += 1;
+
+        if dimension != self.dimension {
+            return Some(Err(format_error(format!(
+                "row {} has {} dimensions, expected {}",
+                row, dimension, self.dimension
+            ))));
+        }
+
+        if let Err(e) = self.reader.read_exact(&mut self.buf) {
+            return Some(Err(e.into()));
+        }
+
+        Some(decode_row(self.dtype, &self.buf, row, None))
+    }
+}
+
+/// Reads delimiter-separated numbers, one vector per line.
+pub struct CsvReader<R, T> {
+    lines: io::Lines<R>,
+    delimiter: char,
+    id_column: Option<usize>,
+    dimension: usize,
+    pending: Option<Row<T>>,
+    row: usize,
+}
+
+impl<R: BufRead, T: Item> CsvReader<R, T> {
+    /// Reads the first record to learn the dimension; a header line is skipped
+    /// when `has_header` is set.
+    pub fn new(
+        reader: R,
+        delimiter: char,
+        has_header: bool,
+        id_column: Option<usize>,
+    ) -> Result<Self, LoadError> {
+        let mut csv = Self {
+            lines: reader.lines(),
+            delimiter,
+            id_column,
+            dimension: 0,
+            pending: None,
+            row: 0,
+        };
+
+        if has_header {
+            csv.lines.next().transpose()?;
+        }
+
+        csv.pending = csv.read_row();
+        if let Some(Ok((_, v))) = &csv.pending {
+            csv.dimension = v.len();
+        }
+
+        Ok(csv)
+    }
+
+    pub fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    fn read_row(&mut self) -> Option<Row<T>> {
+        let line = loop {
+            match self.lines.next()? {
+                Ok(line) if line.trim().is_empty() => continue,
+                Ok(line) => break line,
+                Err(e) => return Some(Err(e.into())),
+            }
+        };
+
+        let row = self.row;
+        self.row += 1;
+
+        let mut id = row as i64;
+        let mut v = Vec::new();
+
+        for (col, field) in line.split(self.delimiter).map(str::trim).enumerate() {
+            if Some(col) == self.id_column {
+                id = match field.parse() {
+                    Ok(id) => id,
+                    Err(_) => {
+                        return Some(Err(format_error(format!(
+                            "row {}: invalid id `{}`",
+                            row, field
+                        ))))
+                    }
+                };
+            } else {
+                match field.parse::<f64>().ok().and_then(T::from_f64) {
+                    Some(x) => v.push(x),
+                    None => {
+                        return Some(Err(format_error(format!(
+                            "row {}, column {}: invalid value `{}`",
+                            row, col, field
+                        ))))
+                    }
+                }
+            }
+        }
+
+        Some(Ok((id, v)))
+    }
+}
+
+impl<R: BufRead, T: Item> Iterator for CsvReader<R, T> {
+    type Item = Row<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.pending.take() {
+            Some(row) => Some(row),
+            None => self.read_row(),
+        }
+    }
+}
+
+/// Streams a `.npy` array into `ann`.
+pub fn load_npy<T, D, R>(ann: &mut Annoy<T, D>, reader: R) -> Result<usize, LoadError>
+where
+    T: Item + Sync + Send + 'static,
+    D: Distance<T>,
+    R: Read,
+{
+    add_items(ann, NpyReader::new(reader)?)
+}
+
+/// Streams a `.fvecs`, `.ivecs` or `.bvecs` file into `ann`.
+pub fn load_vecs<T, D, R>(
+    ann: &mut Annoy<T, D>,
+    reader: R,
+    kind: VecsKind,
+) -> Result<usize, LoadError>
+where
+    T: Item + Sync + Send + 'static,
+    D: Distance<T>,
+    R: Read,
+{
+    add_items(ann, VecsReader::new(reader, kind)?)
+}
+
+/// Streams a comma-separated file into `ann`, taking ids from `id_column` when given.
+pub fn load_csv<T, D, R>(
+    ann: &mut Annoy<T, D>,
+    reader: R,
+    has_header: bool,
+    id_column: Option<usize>,
+) -> Result<usize, LoadError>
+where
+    T: Item + Sync + Send + 'static,
+    D: Distance<T>,
+    R: BufRead,
+{
+    add_items(ann, CsvReader::new(reader, ',', has_header, id_column)?)
+}
+
+/// Opens the array `name` (without the `.npy` suffix) of a `.npz` archive,
+/// or the first array when `name` is `None`.
+#[cfg(feature = "npz")]
+pub fn npz_array<'a, R, T>(
+    archive: &'a mut zip::ZipArchive<R>,
+    name: Option<&str>,
+) -> Result<NpyReader<zip::read::ZipFile<'a>, T>, LoadError>
+where
+    R: Read + io::Seek,
+    T: Item,
+{
+    let file = match name {
+        Some(name) => archive.by_name(&format!("{}.npy", name)),
+        None => archive.by_index(0),
+    }
+    .map_err(|e| format_error(format!("npz: {}", e)))?;
+
+    NpyReader::new(file)
+}
+
+/// Streams the array `name` of a `.npz` archive into `ann`.
+#[cfg(feature = "npz")]
+pub fn load_npz<T, D, R>(
+    ann: &mut Annoy<T, D>,
+    reader: R,
+    name: Option<&str>,
+) -> Result<usize, LoadError>
+where
+    T: Item + Sync + Send + 'static,
+    D: Distance<T>,
+    R: Read + io::Seek,
+{
+    let mut archive =
+        zip::ZipArchive::new(reader).map_err(|e| format_error(format!("npz: {}", e)))?;
+
+    let count = add_items(ann, npz_array(&mut archive, name)?)?;
+
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Euclidean;
+
+    fn npy(descr: &str, shape: &str, data: &[u8]) -> Vec<u8> {
+        let header = format!(
+            "{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}",
+            descr, shape
+        );
+        let mut out = b"\x93NUMPY\x01\x00".to_vec();
+        out.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        out.extend_from_slice(header.as_bytes());
+        out.extend_from_slice(data);
+        out
+    }
+
+    #[test]
+    fn test_npy() {
+        let data: Vec<u8> = [1.0f32, 2.0, 3.0, 4.0, 5.0, 6.0]
+            .iter()
+            .flat_map(|x| x.to_le_bytes().to_vec())
+            .collect();
+        let mut ann: Annoy<f64, Euclidean> = Annoy::new(2);
+
+        let file = npy("<f4", "(2, 3)", &data);
+        let reader = NpyReader::new(file.as_slice()).unwrap().id_column(0);
+        assert_eq!(reader.dimension().unwrap(), 2);
+        assert_eq!(add_items(&mut ann, reader).unwrap(), 2);
+        assert_eq!(ann._nodes[&4].v, vec![5.0, 6.0]);
+
+        let empty = npy("<f4", "(2, 0)", &[]);
+        let reader = NpyReader::<_, f64>::new(empty.as_slice()).unwrap();
+        assert_eq!(reader.dimension().unwrap(), 0);
+        assert!(reader.id_column(0).dimension().is_err());
+    }
+
+    #[test]
+    fn test_vecs() {
+        let mut data = Vec::new();
+        for row in &[[1u8, 2, 3], [4, 5, 6]] {
+            data.extend_from_slice(&3u32.to_le_bytes());
+            data.extend_from_slice(row);
+        }
+
+        let rows: Vec<_> = VecsReader::<_, i32>::new(data.as_slice(), VecsKind::Bvecs)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(rows, vec![(0, vec![1, 2, 3]), (1, vec![4, 5, 6])]);
+
+        data.truncate(data.len() - 1);
+        let last = VecsReader::<_, i32>::new(data.as_slice(), VecsKind::Bvecs)
+            .unwrap()
+            .last()
+            .unwrap();
+        assert!(last.is_err());
+    }
+
+    #[test]
+    fn test_csv() {
+        let data = "id,x,y\n7, 1.0, 2.0\n\n9, 3.0, 4.0\n";
+        let reader = CsvReader::<_, f64>::new(data.as_bytes(), ',', true, Some(0)).unwrap();
+        assert_eq!(reader.dimension(), 2);
+
+        let rows: Vec<_> = reader.collect::<Result<_, _>>().unwrap();
+        assert_eq!(rows, vec![(7, vec![1.0, 2.0]), (9, vec![3.0, 4.0])]);
+
+        let mut ann: Annoy<f64, Euclidean> = Annoy::new(3);
+        assert!(load_csv(&mut ann, "1,2\n".as_bytes(), false, None).is_err());
+    }
+}
@@ -0,0 +1,298 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::iter::FromIterator;
+use std::ops::Deref;
+
+use crate::ann::Annoy;
+use crate::cmp_distance;
+use crate::distance::Distance;
+use crate::item::Item;
+use crate::options::QueryOptions;
+
+/// A candidate and its distance, ordered by distance with NaN last and
+/// ties broken by id.
+pub(crate) struct AnnResult(pub f64, pub i64);
+
+impl PartialEq for AnnResult {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for AnnResult {}
+
+impl PartialOrd for AnnResult {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for AnnResult {
+    fn cmp(&self, other: &AnnResult) -> Ordering {
+        cmp_distance(self.0, other.0).then(self.1.cmp(&other.1))
+    }
+}
+
+/// An item found by a query.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Neighbor<T: Item> {
+    pub id: i64,
+    /// The normalized distance, or `raw_distance` when the query does not
+    /// normalize.
+    pub distance: f64,
+    /// The distance as the metric computes it, before normalizing.
+    pub raw_distance: f64,
+    /// The item's vector, if the query asked for vectors.
+    pub vector: Option<Vec<T>>,
+}
+
+impl<T: Item> Neighbor<T> {
+    /// A neighbor at `raw_distance` as `options` asks for it, where `vector`
+    /// is the item's vector if it is at hand.
+    pub(crate) fn new<D: Distance<T>>(
+        id: i64,
+        raw_distance: f64,
+        options: QueryOptions,
+        vector: Option<&[T]>,
+    ) -> Self {
+        let distance = if options.normalize {
+            D::normalized_distance(raw_distance)
+        } else {
+            raw_distance
+        };
+        let vector = vector
+            .filter(|_| options.include_vectors)
+            .map(<[T]>::to_vec);
+
+        Neighbor {
+            id,
+            distance,
+            raw_distance,
+            vector,
+        }
+    }
+}
+
+/// The neighbors returned by `Annoy::query`, nearest first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Neighbors<T: Item>(Vec<Neighbor<T>>);
+
+impl<T: Item> Neighbors<T> {
+    pub fn ids(&self) -> Vec<i64> {
+        self.0.iter().map(|n| n.id).collect()
+    }
+
+    pub fn distances(&self) -> Vec<f64> {
+        self.0.iter().map(|n| n.distance).collect()
+    }
+
+    /// The ids and distances as `get_nns_by_vector` returns them.
+    pub fn into_parts(self) -> (Vec<i64>, Vec<f64>) {
+        self.0.into_iter().map(|n| (n.id, n.distance)).unzip()
+    }
+
+    pub fn into_vec(self) -> Vec<Neighbor<T>> {
+        self.0
+    }
+}
+
+impl<T: Item> Deref for Neighbors<T> {
+    type Target = [Neighbor<T>];
+
+    fn deref(&self) -> &[Neighbor<T>] {
+        &self.0
+    }
+}
+
+impl<T: Item> FromIterator<Neighbor<T>> for Neighbors<T> {
+    fn from_iter<I: IntoIterator<Item = Neighbor<T>>>(iter: I) -> Self {
+        Neighbors(iter.into_iter().collect())
+    }
+}
+
+impl<T: Item> IntoIterator for Neighbors<T> {
+    type Item = Neighbor<T>;
+    type IntoIter = std::vec::IntoIter<Neighbor<T>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a, T: Item> IntoIterator for &'a Neighbors<T> {
+    type Item = &'a Neighbor<T>;
+    type IntoIter = std::slice::Iter<'a, Neighbor<T>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+/// The candidates of a query, nearest first, from `Annoy::query_iter`.
+///
+/// Distances are computed up front but the candidates are only ordered as
+/// they are taken, so stopping early skips most of the sorting.
+pub struct NeighborIter<'a, T: Item, D: Distance<T>> {
+    ann: &'a Annoy<T, D>,
+    heap: BinaryHeap<Reverse<AnnResult>>,
+    options: QueryOptions,
+}
+
+impl<'a, T: Item, D: Distance<T>> NeighborIter<'a, T, D> {
+    pub(crate) fn new(
+        ann: &'a Annoy<T, D>,
+        heap: BinaryHeap<Reverse<AnnResult>>,
+        options: QueryOptions,
+    ) -> Self {
+        Self { ann, heap, options }
+    }
+}
+
+impl<T, D> Iterator for NeighborIter<'_, T, D>
+where
+    T: Item + Sync + Send + 'static,
+    D: Distance<T>,
+{
+    type Item = Neighbor<T>;
+
+    fn next(&mut self) -> Option<Neighbor<T>> {
+        let Reverse(AnnResult(raw_distance, id)) = self.heap.pop()?;
+
+        Some(Neighbor::new::<D>(
+            id,
+            raw_distance,
+            self.options,
+            self.ann.get_item_vector(id).as_deref(),
+        ))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.heap.len(), Some(self.heap.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Error, Euclidean};
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_query() {
+        let mut ann: Annoy<f64, Euclidean> = Annoy::new(2);
+        ann.set_seed(5);
+        for i in 0..100 {
+            ann.add_item(i, &[(i % 10) as f64, (i / 10) as f64])
+                .unwrap();
+        }
+        ann.build(5);
+
+        // A search_k covering every item makes the results exact.
+        let v = [2.2, 3.1];
+        let options = QueryOptions::new(5).search_k(1000);
+        let neighbors = ann.query(&v, options).unwrap();
+        assert_eq!(neighbors.len(), 5);
+        assert_eq!(
+            neighbors.clone().into_parts(),
+            ann.get_nns_by_vector(&v, 5, 1000)
+        );
+        assert_eq!(neighbors[0].id, 32);
+        assert!(neighbors.iter().all(|n| n.vector.is_none()));
+
+        let raw = ann
+            .query(&v, options.normalize(false).include_vectors(true))
+            .unwrap();
+        assert_eq!(raw.ids(), neighbors.ids());
+        for (n, r) in neighbors.iter().zip(&raw) {
+            assert_eq!(r.distance, r.raw_distance);
+            assert!((r.distance.sqrt() - n.distance).abs() < 1e-12);
+            assert_eq!(r.vector.as_deref(), ann.get_item_vector(r.id).as_deref());
+        }
+
+        let near: Vec<i64> = ann
+            .query_iter(&v, options)
+            .unwrap()
+            .take_while(|n| n.distance < 1.)
+            .map(|n| n.id)
+            .collect();
+        assert_eq!(near, vec![32, 33, 42]);
+
+        assert_eq!(ann.query_by_item(32, options).unwrap()[0].id, 32);
+        assert!(ann.query_by_item(100, options).is_none());
+    }
+
+    fn value() -> impl Strategy<Value = f64> {
+        // Few distinct values make ties and zero vectors likely.
+        prop_oneof![Just(0.), Just(1.), Just(-1.), -3.0..3.0]
+    }
+
+    #[test]
+    fn test_cmp_distance() {
+        assert_eq!(cmp_distance(1., 2.), Ordering::Less);
+        assert_eq!(cmp_distance(f64::NAN, f64::INFINITY), Ordering::Greater);
+        assert_eq!(cmp_distance(-f64::NAN, 1.), Ordering::Greater);
+        assert_eq!(cmp_distance(f64::NAN, -f64::NAN), Ordering::Equal);
+    }
+
+    fn check_order<D>(items: &[Vec<f64>], q: &[f64]) -> Result<(), TestCaseError>
+    where
+        D: Distance<f64> + 'static,
+        D::Node: Send + Sync + 'static,
+    {
+        let build = || {
+            let mut ann: Annoy<f64, D> = Annoy::new(3);
+            ann.set_seed(1);
+            for (i, v) in items.iter().enumerate() {
+                ann.add_item(i as i64, v).unwrap();
+            }
+            ann.build_with_threads(3, 1);
+            ann
+        };
+
+        let ann = build();
+        let options = QueryOptions::new(items.len()).search_k(1000);
+        let neighbors = ann.query(q, options).unwrap();
+
+        for w in neighbors.windows(2) {
+            prop_assert_ne!(w[0].id, w[1].id);
+            prop_assert_eq!(
+                cmp_distance(w[0].raw_distance, w[1].raw_distance).then(w[0].id.cmp(&w[1].id)),
+                Ordering::Less
+            );
+        }
+        prop_assert_eq!(&neighbors, &build().query(q, options).unwrap());
+
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn prop_total_order(
+            items in prop::collection::vec(prop::collection::vec(value(), 3), 1..40),
+            q in prop::collection::vec(value(), 3),
+        ) {
+            check_order::<crate::Angular>(&items, &q)?;
+            check_order::<Euclidean>(&items, &q)?;
+            check_order::<crate::Manhattan>(&items, &q)?;
+        }
+
+        #[test]
+        fn prop_rejects_non_finite(
+            v in prop::collection::vec(value(), 3),
+            at in 0..3usize,
+            bad in prop_oneof![Just(f64::NAN), Just(f64::INFINITY), Just(f64::NEG_INFINITY)],
+        ) {
+            let mut ann: Annoy<f64, Euclidean> = Annoy::new(3);
+            ann.add_item(0, &v).unwrap();
+
+            let mut w = v.clone();
+            w[at] = bad;
+            prop_assert!(matches!(ann.add_item(1, &w), Err(Error::NonFinite)));
+            prop_assert!(!ann.contains(1));
+
+            ann.build(2);
+            prop_assert!(matches!(ann.query(&w, QueryOptions::new(1)), Err(Error::NonFinite)));
+            prop_assert_eq!(ann.query(&v, QueryOptions::new(1)).unwrap().ids(), vec![0]);
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::ann::DEFAULT_N_THREADS;
+
+/// Stops a running build when cancelled. Clones share the same flag, so one
+/// clone can be handed to `BuildOptions` and the other kept to cancel with.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Reported to the progress callback after every finished tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BuildProgress {
+    pub trees_built: usize,
+    pub nodes_created: usize,
+    pub elapsed: Duration,
+}
+
+type ProgressFn<'a> = Box<dyn FnMut(&BuildProgress) + 'a>;
+
+/// Settings for `Annoy::build_with`.
+pub struct BuildOptions<'a> {
+    pub(crate) n_trees: i64,
+    pub(crate) n_threads: usize,
+    pub(crate) progress: Option<ProgressFn<'a>>,
+    pub(crate) cancel: Option<CancellationToken>,
+}
+
+impl<'a> BuildOptions<'a> {
+    /// Builds `n_trees` trees, or as many as fit in twice the number of
+    /// items when `n_trees` is -1.
+    pub fn new(n_trees: i64) -> Self {
+        Self {
+            n_trees,
+            n_threads: DEFAULT_N_THREADS,
+            progress: None,
+            cancel: None,
+        }
+    }
+
+    /// Number of workers building trees, each on a thread of its own with
+    /// the `parallel` feature. A seed gives the same trees for the same
+    /// number of workers.
+    pub fn n_threads(mut self, n_threads: usize) -> Self {
+        self.n_threads = n_threads.max(1);
+        self
+    }
+
+    pub fn on_progress<F: FnMut(&BuildProgress) + 'a>(mut self, progress: F) -> Self {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    pub fn cancel_token(mut self, token: CancellationToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+}
+
+impl fmt::Debug for BuildOptions<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BuildOptions")
+            .field("n_trees", &self.n_trees)
+            .field("n_threads", &self.n_threads)
+            .field("progress", &self.progress.is_some())
+            .field("cancel", &self.cancel)
+            .finish()
+    }
+}
+
+/// Settings for `Annoy::query` and `Annoy::query_iter`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueryOptions {
+    pub(crate) n: usize,
+    pub(crate) search_k: i64,
+    pub(crate) normalize: bool,
+    pub(crate) include_vectors: bool,
+}
+
+impl QueryOptions {
+    /// Asks for the `n` nearest neighbors.
+    pub fn new(n: usize) -> Self {
+        Self {
+            n,
+            search_k: -1,
+            normalize: true,
+            include_vectors: false,
+        }
+    }
+
+    /// Number of items to inspect, by default `n` times the number of trees
+    /// when -1. An item reached by several trees counts once per tree.
+    pub fn search_k(mut self, search_k: i64) -> Self {
+        self.search_k = search_k;
+        self
+    }
+
+    /// Whether `Neighbor::distance` is passed through the metric's
+    /// normalization, e.g. the square root for euclidean. On by default.
+    pub fn normalize(mut self, normalize: bool) -> Self {
+        self.normalize = normalize;
+        self
+    }
+
+    /// Whether every neighbor carries a copy of its vector. Off by default.
+    pub fn include_vectors(mut self, include_vectors: bool) -> Self {
+        self.include_vectors = include_vectors;
+        self
+    }
+}
+
+/// Settings for `SegmentedIndex`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SegmentOptions {
+    pub(crate) n_trees: i64,
+    pub(crate) seal_at: usize,
+    pub(crate) max_segments: usize,
+    pub(crate) seed: Option<u64>,
+}
+
+impl SegmentOptions {
+    pub fn new() -> Self {
+        Self {
+            n_trees: 10,
+            seal_at: 1000,
+            max_segments: 4,
+            seed: None,
+        }
+    }
+
+    /// Trees built for every sealed or merged segment, 10 by default.
+    pub fn n_trees(mut self, n_trees: i64) -> Self {
+        self.n_trees = n_trees;
+        self
+    }
+
+    /// Number of buffered items at which `SegmentedIndex::maintain` seals
+    /// the buffer, 1000 by default.
+    pub fn seal_at(mut self, seal_at: usize) -> Self {
+        self.seal_at = seal_at.max(1);
+        self
+    }
+
+    /// Number of segments above which `SegmentedIndex::maintain` merges
+    /// them, 4 by default.
+    pub fn max_segments(mut self, max_segments: usize) -> Self {
+        self.max_segments = max_segments.max(1);
+        self
+    }
+
+    /// Seed for the trees of every segment, for reproducible builds.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+}
+
+impl Default for SegmentOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
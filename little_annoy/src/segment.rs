@@ -0,0 +1,582 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+#[cfg(feature = "parallel")]
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, RwLock};
+#[cfg(feature = "parallel")]
+use std::thread::JoinHandle;
+#[cfg(feature = "parallel")]
+use std::time::Duration;
+
+use crate::ann::Annoy;
+use crate::brute::brute_force;
+use crate::cmp_distance;
+use crate::distance::Distance;
+use crate::error::Error;
+use crate::item::Item;
+use crate::neighbor::Neighbor;
+use crate::options::{BuildOptions, QueryOptions, SegmentOptions};
+
+/// The items of a segment. Segments never change once created; deletions
+/// are kept next to them in `Segment::deleted`.
+enum Data<T: Item, D: Distance<T>> {
+    /// A sealed buffer whose trees are still being built, or failed to.
+    Flat(HashMap<i64, Vec<T>>),
+    /// Items numbered from 0 in `ann`, where item `i` has the id `ids[i]`.
+    Built {
+        ann: Box<Annoy<T, D>>,
+        ids: Vec<i64>,
+        local: HashMap<i64, i64>,
+    },
+}
+
+impl<T, D> Data<T, D>
+where
+    T: Item + Sync + Send + 'static,
+    D: Distance<T>,
+{
+    fn get(&self, id: i64) -> Option<Cow<'_, [T]>> {
+        match self {
+            Data::Flat(items) => items.get(&id).map(|v| Cow::Borrowed(v.as_slice())),
+            Data::Built { ann, local, .. } => ann.get_item_vector(*local.get(&id)?),
+        }
+    }
+
+    fn items(&self) -> Box<dyn Iterator<Item = (i64, Cow<'_, [T]>)> + '_> {
+        match self {
+            Data::Flat(items) => Box::new(
+                items
+                    .iter()
+                    .map(|(id, v)| (*id, Cow::Borrowed(v.as_slice()))),
+            ),
+            Data::Built { ann, ids, .. } => {
+                Box::new(ann.items().map(move |(i, v)| (ids[i as usize], v)))
+            }
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Data::Flat(items) => items.len(),
+            Data::Built { ids, .. } => ids.len(),
+        }
+    }
+
+    /// The nearest `options.n` items that are not in `deleted`, nearest
+    /// first.
+    fn query(
+        &self,
+        v: &[T],
+        f: usize,
+        options: QueryOptions,
+        deleted: &HashSet<i64>,
+    ) -> Vec<Neighbor<T>> {
+        match self {
+            Data::Flat(items) => brute_force::<T, D, _>(
+                items
+                    .iter()
+                    .filter(|(id, _)| !deleted.contains(id))
+                    .map(|(id, v)| (*id, v.as_slice())),
+                v,
+                f,
+                options,
+            ),
+            // Vectors were checked when they were added.
+            Data::Built { ann, ids, .. } => match ann.query_iter(v, options) {
+                Ok(neighbors) => neighbors
+                    .map(|n| Neighbor {
+                        id: ids[n.id as usize],
+                        ..n
+                    })
+                    .filter(|n| !deleted.contains(&n.id))
+                    .take(options.n)
+                    .collect(),
+                Err(_) => Vec::new(),
+            },
+        }
+    }
+}
+
+struct Segment<T: Item, D: Distance<T>> {
+    data: Arc<Data<T, D>>,
+    // Ids of `data` that were removed or replaced since it was sealed.
+    deleted: HashSet<i64>,
+}
+
+impl<T: Item, D: Distance<T>> Clone for Segment<T, D> {
+    fn clone(&self) -> Self {
+        Self {
+            data: Arc::clone(&self.data),
+            deleted: self.deleted.clone(),
+        }
+    }
+}
+
+struct State<T: Item, D: Distance<T>> {
+    buffer: HashMap<i64, Vec<T>>,
+    // Oldest first.
+    segments: Vec<Segment<T, D>>,
+}
+
+struct Inner<T: Item, D: Distance<T>> {
+    f: usize,
+    options: SegmentOptions,
+    state: RwLock<State<T, D>>,
+    // Held while sealing or merging, so that one runs at a time.
+    maintenance: Mutex<()>,
+}
+
+/// An index that takes new items at any time.
+///
+/// Added items go to a buffer that is searched exhaustively until `seal`
+/// turns it into a segment, an `Annoy` index of its own. `merge` combines
+/// the segments into one and drops removed items. Both build their trees
+/// without blocking queries or writes, and `maintain` runs them when the
+/// buffer or the number of segments grows past the limits of the
+/// `SegmentOptions`.
+///
+/// Clones share the same index, so one can be handed to a background
+/// thread while others serve queries.
+pub struct SegmentedIndex<T: Item, D: Distance<T>> {
+    inner: Arc<Inner<T, D>>,
+}
+
+impl<T: Item, D: Distance<T>> Clone for SegmentedIndex<T, D> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<T, D> SegmentedIndex<T, D>
+where
+    T: Item + Sync + Send + 'static,
+    D: Distance<T> + 'static,
+    D::Node: Sync + Send,
+{
+    pub fn new(f: usize, options: SegmentOptions) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                f,
+                options,
+                state: RwLock::new(State {
+                    buffer: HashMap::new(),
+                    segments: Vec::new(),
+                }),
+                maintenance: Mutex::new(()),
+            }),
+        }
+    }
+
+    pub fn dimension(&self) -> usize {
+        self.inner.f
+    }
+
+    /// Number of items that can be found.
+    pub fn len(&self) -> usize {
+        let state = self.inner.state.read().unwrap();
+        let sealed: usize = state
+            .segments
+            .iter()
+            .map(|s| s.data.len() - s.deleted.len())
+            .sum();
+
+        state.buffer.len() + sealed
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of items added since the last seal.
+    pub fn n_buffered(&self) -> usize {
+        self.inner.state.read().unwrap().buffer.len()
+    }
+
+    pub fn n_segments(&self) -> usize {
+        self.inner.state.read().unwrap().segments.len()
+    }
+
+    pub fn contains(&self, item: i64) -> bool {
+        self.get_item_vector(item).is_some()
+    }
+
+    pub fn get_item_vector(&self, item: i64) -> Option<Vec<T>> {
+        let state = self.inner.state.read().unwrap();
+        if let Some(v) = state.buffer.get(&item) {
+            return Some(v.clone());
+        }
+
+        state
+            .segments
+            .iter()
+            .filter(|s| !s.deleted.contains(&item))
+            .find_map(|s| s.data.get(item).map(Cow::into_owned))
+    }
+
+    /// Adds `item` to the buffer, replacing any earlier vector for it.
+    pub fn add_item(&self, item: i64, w: &[T]) -> Result<(), Error> {
+        if w.len() != self.inner.f {
+            return Err(Error::DimensionMismatch {
+                expected: self.inner.f,
+                found: w.len(),
+            });
+        }
+        if !w.iter().all(|x| x.is_finite()) {
+            return Err(Error::NonFinite);
+        }
+
+        let mut state = self.inner.state.write().unwrap();
+        Self::delete_sealed(&mut state, item);
+        state.buffer.insert(item, w.to_vec());
+
+        Ok(())
+    }
+
+    /// Removes `item`, returning whether it was there.
+    pub fn remove_item(&self, item: i64) -> bool {
+        let mut state = self.inner.state.write().unwrap();
+        let buffered = state.buffer.remove(&item).is_some();
+
+        Self::delete_sealed(&mut state, item) || buffered
+    }
+
+    fn delete_sealed(state: &mut State<T, D>, item: i64) -> bool {
+        let mut found = false;
+        for s in state.segments.iter_mut() {
+            if s.data.get(item).is_some() {
+                found |= s.deleted.insert(item);
+            }
+        }
+
+        found
+    }
+
+    /// Finds the `n` of `options` nearest neighbors of `v` in the buffer and
+    /// every segment. `search_k` applies to every segment on its own.
+    pub fn query(&self, v: &[T], options: QueryOptions) -> Result<Vec<Neighbor<T>>, Error> {
+        if v.len() != self.inner.f {
+            return Err(Error::DimensionMismatch {
+                expected: self.inner.f,
+                found: v.len(),
+            });
+        }
+        if !v.iter().all(|x| x.is_finite()) {
+            return Err(Error::NonFinite);
+        }
+
+        let f = self.inner.f;
+        let state = self.inner.state.read().unwrap();
+        let buffer = state.buffer.iter().map(|(id, w)| (*id, w.as_slice()));
+
+        let mut nns = brute_force::<T, D, _>(buffer, v, f, options);
+        for s in state.segments.iter() {
+            nns.extend(s.data.query(v, f, options, &s.deleted));
+        }
+
+        nns.sort_by(|a, b| cmp_distance(a.raw_distance, b.raw_distance).then(a.id.cmp(&b.id)));
+        nns.truncate(options.n);
+
+        Ok(nns)
+    }
+
+    /// Like `query` for the vector of `item`, or `None` if there is no such
+    /// item.
+    pub fn query_by_item(&self, item: i64, options: QueryOptions) -> Option<Vec<Neighbor<T>>> {
+        let v = self.get_item_vector(item)?;
+
+        self.query(&v, options).ok()
+    }
+
+    /// Turns the buffer into a new segment, returning false if it was
+    /// empty. The buffered items stay searchable while the trees are built.
+    /// If the build fails they are kept, searched exhaustively, until the
+    /// next `merge`.
+    pub fn seal(&self) -> Result<bool, Error> {
+        let _maintenance = self.inner.maintenance.lock().unwrap();
+
+        let flat = {
+            let mut state = self.inner.state.write().unwrap();
+            if state.buffer.is_empty() {
+                return Ok(false);
+            }
+
+            let flat = Arc::new(Data::Flat(std::mem::take(&mut state.buffer)));
+            state.segments.push(Segment {
+                data: Arc::clone(&flat),
+                deleted: HashSet::new(),
+            });
+            flat
+        };
+
+        let built = Arc::new(self.build(flat.items().collect())?);
+
+        let mut state = self.inner.state.write().unwrap();
+        if let Some(s) = state
+            .segments
+            .iter_mut()
+            .find(|s| Arc::ptr_eq(&s.data, &flat))
+        {
+            s.data = built;
+        }
+
+        Ok(true)
+    }
+
+    /// Combines every segment into one without the removed items,
+    /// returning false if there was nothing to do. Items removed during the
+    /// merge stay removed.
+    pub fn merge(&self) -> Result<bool, Error> {
+        let _maintenance = self.inner.maintenance.lock().unwrap();
+
+        let sources: Vec<Segment<T, D>> = {
+            let state = self.inner.state.read().unwrap();
+            let clean = state.segments.iter().all(|s| s.deleted.is_empty());
+            if state.segments.len() < 2 && clean {
+                return Ok(false);
+            }
+
+            state.segments.clone()
+        };
+
+        let items: Vec<(i64, Cow<'_, [T]>)> = sources
+            .iter()
+            .flat_map(|s| {
+                let deleted = &s.deleted;
+                s.data.items().filter(move |(id, _)| !deleted.contains(id))
+            })
+            .collect();
+        let merged = if items.is_empty() {
+            None
+        } else {
+            Some(Arc::new(self.build(items)?))
+        };
+
+        let mut state = self.inner.state.write().unwrap();
+        let mut deleted = HashSet::new();
+        let mut at = None;
+        for source in sources.iter() {
+            let i = match state
+                .segments
+                .iter()
+                .position(|s| Arc::ptr_eq(&s.data, &source.data))
+            {
+                Some(i) => i,
+                None => continue,
+            };
+
+            let s = state.segments.remove(i);
+            deleted.extend(s.deleted.difference(&source.deleted).copied());
+            at = Some(at.map_or(i, |at: usize| at.min(i)));
+        }
+
+        if let Some(data) = merged {
+            state
+                .segments
+                .insert(at.unwrap_or(0), Segment { data, deleted });
+        }
+
+        Ok(true)
+    }
+
+    /// Seals the buffer and merges the segments when the `SegmentOptions`
+    /// call for it.
+    pub fn maintain(&self) -> Result<(), Error> {
+        if self.n_buffered() >= self.inner.options.seal_at {
+            self.seal()?;
+        }
+        if self.n_segments() > self.inner.options.max_segments {
+            self.merge()?;
+        }
+
+        Ok(())
+    }
+
+    /// Runs `maintain` on a background thread every `every` until the
+    /// returned handle is stopped or dropped, or `maintain` fails.
+    #[cfg(feature = "parallel")]
+    pub fn spawn_maintenance(&self, every: Duration) -> Maintenance {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let index = self.clone();
+
+        let handle = std::thread::spawn(move || loop {
+            match stopped.recv_timeout(every) {
+                Err(RecvTimeoutError::Timeout) => index.maintain()?,
+                _ => return Ok(()),
+            }
+        });
+
+        Maintenance {
+            stop: Some(stop),
+            handle: Some(handle),
+        }
+    }
+
+    /// An index of `items` sorted by id, so that a seed gives the same
+    /// trees for the same items.
+    fn build(&self, mut items: Vec<(i64, Cow<'_, [T]>)>) -> Result<Data<T, D>, Error> {
+        items.sort_unstable_by_key(|(id, _)| *id);
+
+        let mut ann = Annoy::new(self.inner.f);
+        if let Some(seed) = self.inner.options.seed {
+            ann.set_seed(seed);
+        }
+
+        let mut ids = Vec::with_capacity(items.len());
+        for (i, (id, w)) in items.into_iter().enumerate() {
+            ann.add_item(i as i64, &w)?;
+            ids.push(id);
+        }
+        ann.build_with(BuildOptions::new(self.inner.options.n_trees))?;
+
+        let local = ids
+            .iter()
+            .enumerate()
+            .map(|(i, id)| (*id, i as i64))
+            .collect();
+        Ok(Data::Built {
+            ann: Box::new(ann),
+            ids,
+            local,
+        })
+    }
+}
+
+/// The background thread of `SegmentedIndex::spawn_maintenance`.
+#[cfg(feature = "parallel")]
+pub struct Maintenance {
+    stop: Option<Sender<()>>,
+    handle: Option<JoinHandle<Result<(), Error>>>,
+}
+
+#[cfg(feature = "parallel")]
+impl Maintenance {
+    /// Stops the thread once the current seal or merge is done, returning
+    /// the error that stopped it earlier if any.
+    pub fn stop(mut self) -> Result<(), Error> {
+        self.join()
+    }
+
+    fn join(&mut self) -> Result<(), Error> {
+        self.stop.take();
+        match self.handle.take() {
+            Some(handle) => handle.join().unwrap(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(feature = "parallel")]
+impl Drop for Maintenance {
+    fn drop(&mut self) {
+        let _ = self.join();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Euclidean;
+
+    fn index() -> SegmentedIndex<f64, Euclidean> {
+        let index = SegmentedIndex::new(2, SegmentOptions::new().n_trees(3).seed(1));
+        for i in 0..30 {
+            index.add_item(i, &[i as f64, 0.]).unwrap();
+        }
+        index
+    }
+
+    fn ids(index: &SegmentedIndex<f64, Euclidean>, x: f64, n: usize) -> Vec<i64> {
+        let options = QueryOptions::new(n).search_k(1000);
+        let nns = index.query(&[x, 0.], options).unwrap();
+        nns.iter().map(|n| n.id).collect()
+    }
+
+    #[test]
+    fn test_seal_merge() {
+        let index = index();
+        assert_eq!(ids(&index, 10.2, 3), vec![10, 11, 9]);
+
+        assert!(index.seal().unwrap());
+        assert!(!index.seal().unwrap());
+        assert_eq!((index.n_buffered(), index.n_segments()), (0, 1));
+        assert_eq!(ids(&index, 10.2, 3), vec![10, 11, 9]);
+
+        // A replaced item is only found where it is now.
+        index.add_item(11, &[100., 0.]).unwrap();
+        assert!(index.remove_item(9));
+        assert!(!index.remove_item(9));
+        assert_eq!(ids(&index, 10.2, 3), vec![10, 12, 8]);
+        assert_eq!(index.get_item_vector(11), Some(vec![100., 0.]));
+        assert_eq!(index.len(), 29);
+
+        index.seal().unwrap();
+        assert_eq!(index.n_segments(), 2);
+        assert!(index.merge().unwrap());
+        assert!(!index.merge().unwrap());
+        assert_eq!((index.n_segments(), index.len()), (1, 29));
+        assert_eq!(ids(&index, 10.2, 3), vec![10, 12, 8]);
+        assert_eq!(ids(&index, 99., 1), vec![11]);
+
+        assert_eq!(
+            index.query_by_item(12, QueryOptions::new(1)).unwrap()[0].id,
+            12
+        );
+        assert!(index.query_by_item(9, QueryOptions::new(1)).is_none());
+    }
+
+    #[test]
+    fn test_merge_drops_everything_removed() {
+        let index = index();
+        index.seal().unwrap();
+        for i in 0..30 {
+            index.remove_item(i);
+        }
+
+        assert!(index.merge().unwrap());
+        assert_eq!(index.n_segments(), 0);
+        assert!(index.is_empty());
+        assert!(ids(&index, 0., 3).is_empty());
+    }
+
+    #[test]
+    fn test_add_item_errors() {
+        let index = index();
+        assert!(matches!(
+            index.add_item(0, &[1.]),
+            Err(Error::DimensionMismatch {
+                expected: 2,
+                found: 1
+            })
+        ));
+        assert!(matches!(
+            index.add_item(0, &[f64::NAN, 0.]),
+            Err(Error::NonFinite)
+        ));
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_spawn_maintenance() {
+        let options = SegmentOptions::new().seal_at(10).max_segments(1);
+        let index: SegmentedIndex<f64, Euclidean> = SegmentedIndex::new(2, options);
+        let maintenance = index.spawn_maintenance(Duration::from_millis(1));
+
+        for i in 0..100 {
+            index.add_item(i, &[i as f64, 0.]).unwrap();
+            if i % 10 == 9 {
+                while index.n_buffered() > 0 {
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+            }
+        }
+        while index.n_segments() > 1 {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        maintenance.stop().unwrap();
+        assert_eq!(index.len(), 100);
+        assert_eq!(ids(&index, 42.2, 2), vec![42, 43]);
+    }
+}
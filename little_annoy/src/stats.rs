@@ -0,0 +1,297 @@
+use std::collections::{BTreeMap, HashMap};
+use std::mem;
+
+use crate::ann::Annoy;
+use crate::distance::{Distance, NodeImpl};
+use crate::item::Item;
+
+/// What a tree node is, by the same rules queries use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    /// An item vector.
+    Item,
+    /// A bucket of at most `_K` items, which are its children.
+    Leaf,
+    /// A hyperplane dividing its descendants between two children.
+    Split,
+    /// A child id with no node stored under it.
+    Missing,
+}
+
+/// A node visited by `Annoy::tree_iter`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeNode {
+    pub id: i64,
+    /// Distance from the root, which is at depth 0.
+    pub depth: usize,
+    pub kind: NodeKind,
+    pub descendants: usize,
+    /// Empty for items and missing nodes.
+    pub children: Vec<i64>,
+}
+
+/// Walks a tree depth first, left child before right.
+pub struct TreeIter<'a, T: Item, D: Distance<T>> {
+    ann: &'a Annoy<T, D>,
+    stack: Vec<(i64, usize)>,
+}
+
+impl<'a, T, D> Iterator for TreeIter<'a, T, D>
+where
+    T: Item + Sync + Send + 'static,
+    D: Distance<T>,
+{
+    type Item = TreeNode;
+
+    fn next(&mut self) -> Option<TreeNode> {
+        let (id, depth) = self.stack.pop()?;
+
+        let node = match self.ann._try_node(id) {
+            Some(node) => node,
+            None => {
+                return Some(TreeNode {
+                    id,
+                    depth,
+                    kind: NodeKind::Missing,
+                    descendants: 0,
+                    children: Vec::new(),
+                })
+            }
+        };
+
+        let descendants = node.descendant();
+        let (kind, children) = if descendants == 1 && id < self.ann._n_items {
+            (NodeKind::Item, Vec::new())
+        } else if descendants <= self.ann._K {
+            (NodeKind::Leaf, node.children())
+        } else {
+            (NodeKind::Split, node.children())
+        };
+
+        self.stack
+            .extend(children.iter().rev().map(|child| (*child, depth + 1)));
+
+        Some(TreeNode {
+            id,
+            depth,
+            kind,
+            descendants,
+            children,
+        })
+    }
+}
+
+/// The shape of one tree, as reported by `Annoy::stats`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeStats {
+    pub root: i64,
+    pub split_nodes: usize,
+    pub leaf_nodes: usize,
+    /// Depths of the items in the tree.
+    pub min_depth: usize,
+    pub mean_depth: f64,
+    pub max_depth: usize,
+    /// Items on the smaller side of a split divided by items on the larger
+    /// side, over all splits of the tree. Both are 1 without splits.
+    pub min_balance: f64,
+    pub mean_balance: f64,
+}
+
+/// Statistics over all trees of an index, from `Annoy::stats`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct IndexStats {
+    pub n_trees: usize,
+    pub split_nodes: usize,
+    pub leaf_nodes: usize,
+    pub trees: Vec<TreeStats>,
+    /// Number of items at each depth, summed over the trees.
+    pub depths: BTreeMap<usize, usize>,
+    /// Number of leaves holding each number of items.
+    pub leaf_sizes: BTreeMap<usize, usize>,
+    /// Splits counted by balance in tenths: `balance[i]` counts the ratios
+    /// from `i / 10` up to `(i + 1) / 10`, with 1 in the last bucket.
+    pub balance: [usize; 10],
+    /// Approximate bytes taken by the items and nodes held in memory.
+    pub memory_bytes: usize,
+}
+
+impl<T, D> Annoy<T, D>
+where
+    T: Item + Sync + Send + 'static,
+    D: Distance<T>,
+{
+    /// Iterates over the nodes of the tree under `root`, including the items
+    /// in its leaves.
+    pub fn tree_iter(&self, root: i64) -> TreeIter<'_, T, D> {
+        TreeIter {
+            ann: self,
+            stack: vec![(root, 0)],
+        }
+    }
+
+    /// Walks every tree to describe the shape of the index.
+    pub fn stats(&self) -> IndexStats {
+        let mut stats = IndexStats {
+            n_trees: self._roots.len(),
+            memory_bytes: self.memory_bytes(),
+            ..IndexStats::default()
+        };
+
+        for root in &self._roots {
+            let tree = self.tree_stats(*root, &mut stats);
+            stats.split_nodes += tree.split_nodes;
+            stats.leaf_nodes += tree.leaf_nodes;
+            stats.trees.push(tree);
+        }
+
+        stats
+    }
+
+    fn tree_stats(&self, root: i64, stats: &mut IndexStats) -> TreeStats {
+        let mut tree = TreeStats {
+            root,
+            split_nodes: 0,
+            leaf_nodes: 0,
+            min_depth: usize::MAX,
+            mean_depth: 0.,
+            max_depth: 0,
+            min_balance: 1.,
+            mean_balance: 1.,
+        };
+
+        let mut sizes = HashMap::new();
+        let mut splits = Vec::new();
+        let mut items = 0;
+        let mut total_depth = 0;
+
+        for node in self.tree_iter(root) {
+            sizes.insert(node.id, node.descendants);
+
+            match node.kind {
+                NodeKind::Item => {
+                    items += 1;
+                    total_depth += node.depth;
+                    tree.min_depth = tree.min_depth.min(node.depth);
+                    tree.max_depth = tree.max_depth.max(node.depth);
+                    *stats.depths.entry(node.depth).or_insert(0) += 1;
+                }
+                NodeKind::Leaf => {
+                    tree.leaf_nodes += 1;
+                    *stats.leaf_sizes.entry(node.children.len()).or_insert(0) += 1;
+                }
+                NodeKind::Split => {
+                    tree.split_nodes += 1;
+                    splits.push(node.children);
+                }
+                NodeKind::Missing => {}
+            }
+        }
+
+        if items > 0 {
+            tree.mean_depth = total_depth as f64 / items as f64;
+        } else {
+            tree.min_depth = 0;
+        }
+
+        // Children come after their parent, so every size is known by now.
+        let mut total_balance = 0.;
+        for children in &splits {
+            let size = |i: usize| children.get(i).and_then(|c| sizes.get(c)).copied();
+            let (l, r) = (size(0).unwrap_or(0), size(1).unwrap_or(0));
+            let balance = if l.max(r) == 0 {
+                0.
+            } else {
+                l.min(r) as f64 / l.max(r) as f64
+            };
+
+            tree.min_balance = tree.min_balance.min(balance);
+            total_balance += balance;
+            stats.balance[((balance * 10.) as usize).min(9)] += 1;
+        }
+        if !splits.is_empty() {
+            tree.mean_balance = total_balance / splits.len() as f64;
+        }
+
+        tree
+    }
+
+    fn memory_bytes(&self) -> usize {
+        let entry = mem::size_of::<(i64, D::Node)>();
+
+        self._nodes
+            .values()
+            .map(|node| entry + node.vector_bytes() + node.children().len() * mem::size_of::<i64>())
+            .sum::<usize>()
+            + self._roots.len() * mem::size_of::<i64>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Euclidean;
+
+    #[test]
+    fn test_stats() {
+        let mut ann: Annoy<f64, Euclidean> = Annoy::new(2);
+        ann.set_seed(7);
+        for i in 0..100 {
+            ann.add_item(i, &[(i % 10) as f64, (i / 10) as f64])
+                .unwrap();
+        }
+        ann.build(4);
+
+        let stats = ann.stats();
+        assert_eq!(stats.n_trees, 4);
+        assert_eq!(
+            (stats.split_nodes + stats.leaf_nodes) as i64,
+            ann._n_nodes - ann._n_items
+        );
+        assert_eq!(stats.depths.values().sum::<usize>(), 400);
+        assert_eq!(stats.balance.iter().sum::<usize>(), stats.split_nodes);
+        assert!(stats.memory_bytes > 100 * 2 * mem::size_of::<f64>());
+
+        for tree in &stats.trees {
+            assert!(tree.min_depth >= 1 && tree.min_depth <= tree.max_depth);
+            assert!(tree.min_balance > 0. && tree.min_balance <= tree.mean_balance);
+        }
+
+        let root = ann.tree_iter(ann._roots[0]).next().unwrap();
+        assert_eq!(
+            (root.depth, root.kind, root.descendants),
+            (0, NodeKind::Split, 100)
+        );
+
+        let mut items: Vec<i64> = ann
+            .tree_iter(ann._roots[0])
+            .filter(|node| node.kind == NodeKind::Item)
+            .map(|node| node.id)
+            .collect();
+        items.sort_unstable();
+        assert_eq!(items, (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_memory_f32() {
+        fn memory<T: crate::Item>() -> (usize, usize) {
+            let mut ann: Annoy<T, Euclidean> = Annoy::new(64);
+            ann.set_seed(3);
+            for i in 0..500 {
+                let v: Vec<T> = (0..64)
+                    .map(|z| T::from_i64((i * 31 + z * 7) % 101).unwrap())
+                    .collect();
+                ann.add_item(i, &v).unwrap();
+            }
+            ann.build(10);
+            let vectors = ann._nodes.values().map(|node| node.vector_bytes()).sum();
+            (ann.stats().memory_bytes, vectors)
+        }
+
+        // Items and split planes take half the bytes; the map entries and
+        // child ids of the nodes do not shrink.
+        let ((f32_bytes, f32_vectors), (f64_bytes, f64_vectors)) =
+            (memory::<f32>(), memory::<f64>());
+        assert_eq!(f32_vectors * 2, f64_vectors);
+        assert!(f32_bytes * 3 < f64_bytes * 2);
+    }
+}
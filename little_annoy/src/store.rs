@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::checksum;
+use crate::format::{self, to_io, Kind};
+
+/// Tree nodes kept in a file instead of in memory, for `Annoy::on_disk_build`.
+///
+/// The file has the layout `Annoy::save` writes: the format header, a
+/// bincode map from node id to node and a checksum trailer. Entries are
+/// appended as the build creates them; the entry count after the header and
+/// the trailer are filled in by
+/// `finish`, after which the file can be opened with `Annoy::load` and its
+/// entries read back. Only the offset of every entry stays in memory.
+///
+/// Reads are positional, so concurrent queries do not wait for each other.
+pub(crate) struct DiskStore {
+    file: BufWriter<File>,
+    offsets: HashMap<i64, (u64, u64)>,
+    entries: u64,
+    len: u64,
+}
+
+impl DiskStore {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+
+        let mut file = BufWriter::new(file);
+        format::write_header(&mut file, Kind::Annoy)?;
+        file.write_all(&0u64.to_le_bytes())?;
+
+        Ok(Self {
+            file,
+            offsets: HashMap::new(),
+            entries: 0,
+            len: (format::HEADER_LEN + 8) as u64,
+        })
+    }
+
+    pub fn append<N: Serialize>(&mut self, id: i64, node: &N) -> io::Result<()> {
+        let key = bincode::serialize(&id).map_err(to_io)?;
+        let value = bincode::serialize(node).map_err(to_io)?;
+
+        self.file.write_all(&key)?;
+        self.file.write_all(&value)?;
+
+        let offset = self.len + key.len() as u64;
+        self.offsets.insert(id, (offset, value.len() as u64));
+        self.len += (key.len() + value.len()) as u64;
+        self.entries += 1;
+
+        Ok(())
+    }
+
+    /// Forgets entries for which `f` is false, such as nodes that are no
+    /// longer part of any tree. They stay in the file.
+    pub fn retain(&mut self, f: impl Fn(i64) -> bool) {
+        self.offsets.retain(|id, _| f(*id));
+    }
+
+    /// Writes the entry count and the checksum trailer, making the file
+    /// loadable.
+    pub fn finish(&mut self) -> io::Result<()> {
+        let file = &mut self.file;
+        file.seek(SeekFrom::Start(format::HEADER_LEN as u64))?;
+        file.write_all(&self.entries.to_le_bytes())?;
+        file.flush()?;
+
+        file.get_mut().seek(SeekFrom::Start(0))?;
+        let crc = checksum::crc(file.get_mut(), self.len)?;
+        file.seek(SeekFrom::Start(self.len))?;
+        file.write_all(&checksum::trailer(crc))?;
+        file.flush()?;
+
+        self.len += checksum::TRAILER_LEN as u64;
+
+        Ok(())
+    }
+
+    pub fn contains(&self, id: i64) -> bool {
+        self.offsets.contains_key(&id)
+    }
+
+    /// Reads an entry back. Only valid once the store is finished.
+    pub fn get<N: DeserializeOwned>(&self, id: i64) -> io::Result<Option<N>> {
+        let (offset, len) = match self.offsets.get(&id) {
+            Some(entry) => *entry,
+            None => return Ok(None),
+        };
+
+        let mut buf = vec![0; len as usize];
+        read_exact_at(self.file.get_ref(), &mut buf, offset)?;
+
+        bincode::deserialize(&buf).map(Some).map_err(to_io)
+    }
+
+    /// Copies the finished file to `w`.
+    pub fn copy_to<W: Write>(&self, w: &mut W) -> io::Result<u64> {
+        let mut buf = vec![0; 64 * 1024];
+        let mut offset = 0;
+
+        while offset < self.len {
+            let n = buf.len().min((self.len - offset) as usize);
+            read_exact_at(self.file.get_ref(), &mut buf[..n], offset)?;
+            w.write_all(&buf[..n])?;
+            offset += n as u64;
+        }
+
+        Ok(self.len)
+    }
+}
+
+#[cfg(unix)]
+fn read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<()> {
+    std::os::unix::fs::FileExt::read_exact_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn read_exact_at(file: &File, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+    use std::os::windows::fs::FileExt;
+
+    while !buf.is_empty() {
+        match file.seek_read(buf, offset) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => {
+                buf = &mut buf[n..];
+                offset += n as u64;
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(any(unix, windows)))]
+fn read_exact_at(_file: &File, _buf: &mut [u8], _offset: u64) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "on-disk indexes need positional reads",
+    ))
+}
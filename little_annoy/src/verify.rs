@@ -0,0 +1,299 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::ann::Annoy;
+use crate::distance::{Distance, NodeImpl};
+use crate::error::Error;
+use crate::item::Item;
+use crate::stats::NodeKind;
+
+/// A problem found by `Annoy::verify`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Corruption {
+    /// A root id with no node stored under it.
+    MissingRoot(i64),
+    /// A child id with no node stored under it, or a leaf child that is not
+    /// an item.
+    InvalidChild { parent: i64, child: i64 },
+    /// A node reached a second time within the tree under `root`.
+    SharedNode { root: i64, node: i64 },
+    /// A node whose descendant count is not the number of items below it.
+    DescendantCount {
+        node: i64,
+        expected: usize,
+        found: usize,
+    },
+    /// An item found `count` times in the tree under `root` instead of once.
+    ItemCount { root: i64, item: i64, count: usize },
+    /// An item vector whose length is not the dimension of the index.
+    Dimension { item: i64, len: usize },
+}
+
+impl fmt::Display for Corruption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Corruption::MissingRoot(root) => write!(f, "root {} does not exist", root),
+            Corruption::InvalidChild { parent, child } => {
+                write!(f, "node {} has an invalid child {}", parent, child)
+            }
+            Corruption::SharedNode { root, node } => {
+                write!(f, "node {} is reached twice in tree {}", node, root)
+            }
+            Corruption::DescendantCount {
+                node,
+                expected,
+                found,
+            } => write!(
+                f,
+                "node {} counts {} descendants but has {}",
+                node, found, expected
+            ),
+            Corruption::ItemCount { root, item, count } => {
+                write!(f, "item {} appears {} times in tree {}", item, count, root)
+            }
+            Corruption::Dimension { item, len } => {
+                write!(f, "item {} has {} dimensions", item, len)
+            }
+        }
+    }
+}
+
+impl<T, D> Annoy<T, D>
+where
+    T: Item + Sync + Send + 'static,
+    D: Distance<T>,
+{
+    /// Checks that every item has `_f` dimensions and every tree is well
+    /// formed: its nodes exist, descendant counts add up and it holds every
+    /// item exactly once. The first problem found is returned as
+    /// `Error::Corrupt`.
+    pub fn verify(&self) -> Result<(), Error> {
+        let mut ids = Vec::new();
+        for item in 0..self._n_items {
+            let node = match self._try_node(item) {
+                Some(node) if node.descendant() >= 1 => node,
+                _ => continue,
+            };
+
+            let len = node.as_slice().len();
+            if len != self._f {
+                return Err(Error::Corrupt(Corruption::Dimension { item, len }));
+            }
+            ids.push(item);
+        }
+
+        for root in &self._roots {
+            self.verify_tree(*root, &ids).map_err(Error::Corrupt)?;
+        }
+
+        Ok(())
+    }
+
+    fn verify_tree(&self, root: i64, items: &[i64]) -> Result<(), Corruption> {
+        if self._try_node(root).is_none() {
+            return Err(Corruption::MissingRoot(root));
+        }
+
+        let mut parents: HashMap<i64, (i64, NodeKind)> = HashMap::new();
+        let mut visited = HashSet::new();
+        let mut counts: HashMap<i64, usize> = HashMap::new();
+        let mut sizes = HashMap::new();
+        let mut inner = Vec::new();
+
+        for node in self.tree_iter(root) {
+            let parent = parents.get(&node.id).copied();
+            let invalid = match (node.kind, parent) {
+                (NodeKind::Missing, _) => true,
+                (NodeKind::Item, _) => false,
+                (_, Some((_, NodeKind::Leaf))) => true,
+                _ => false,
+            };
+            if invalid {
+                let parent = parent.map_or(root, |(p, _)| p);
+                return Err(Corruption::InvalidChild {
+                    parent,
+                    child: node.id,
+                });
+            }
+
+            if node.kind == NodeKind::Item {
+                *counts.entry(node.id).or_insert(0) += 1;
+                continue;
+            }
+            if !visited.insert(node.id) {
+                return Err(Corruption::SharedNode {
+                    root,
+                    node: node.id,
+                });
+            }
+
+            for child in &node.children {
+                parents.insert(*child, (node.id, node.kind));
+            }
+            sizes.insert(node.id, node.descendants);
+            inner.push(node);
+        }
+
+        // Children first, so that a wrong count is reported where it is.
+        for node in inner.iter().rev() {
+            let expected = if node.id == root {
+                self._n_items as usize
+            } else if node.kind == NodeKind::Leaf {
+                node.children.len()
+            } else {
+                node.children
+                    .iter()
+                    .map(|c| sizes.get(c).copied().unwrap_or(1))
+                    .sum()
+            };
+
+            if node.descendants != expected {
+                return Err(Corruption::DescendantCount {
+                    node: node.id,
+                    expected,
+                    found: node.descendants,
+                });
+            }
+        }
+
+        for item in items {
+            let count = counts.get(item).copied().unwrap_or(0);
+            if count != 1 {
+                return Err(Corruption::ItemCount {
+                    root,
+                    item: *item,
+                    count,
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Euclidean;
+
+    fn built() -> Annoy<f64, Euclidean> {
+        let mut ann: Annoy<f64, Euclidean> = Annoy::new(2);
+        ann.set_seed(11);
+        for i in 0..100 {
+            ann.add_item(i, &[(i % 10) as f64, (i / 10) as f64])
+                .unwrap();
+        }
+        ann.build(3);
+        ann
+    }
+
+    fn corruption(ann: &Annoy<f64, Euclidean>) -> Corruption {
+        match ann.verify() {
+            Err(Error::Corrupt(c)) => c,
+            other => panic!("expected corruption, got {:?}", other),
+        }
+    }
+
+    fn leaf(ann: &Annoy<f64, Euclidean>) -> i64 {
+        ann.tree_iter(ann._roots[0])
+            .find(|node| node.kind == NodeKind::Leaf && node.children.len() > 1)
+            .unwrap()
+            .id
+    }
+
+    #[test]
+    fn test_verify() {
+        let ann = built();
+        assert!(ann.verify().is_ok());
+
+        let mut missing = built();
+        let root = missing._roots[0];
+        missing._nodes.remove(&root);
+        assert_eq!(corruption(&missing), Corruption::MissingRoot(root));
+
+        let mut dimension = built();
+        dimension._nodes.get_mut(&5).unwrap().v = vec![1.];
+        assert_eq!(
+            corruption(&dimension),
+            Corruption::Dimension { item: 5, len: 1 }
+        );
+
+        let mut descendants = built();
+        let id = leaf(&descendants);
+        descendants._nodes.get_mut(&id).unwrap().n_descendants += 1;
+        assert!(matches!(
+            corruption(&descendants),
+            Corruption::DescendantCount { node, .. } if node == id
+        ));
+
+        let mut duplicate = built();
+        let id = leaf(&duplicate);
+        let node = duplicate._nodes.get_mut(&id).unwrap();
+        node.children[1] = node.children[0];
+        assert!(matches!(
+            corruption(&duplicate),
+            Corruption::ItemCount { .. }
+        ));
+
+        let mut child = built();
+        let id = leaf(&child);
+        child._nodes.get_mut(&id).unwrap().children[0] = 1000;
+        assert_eq!(
+            corruption(&child),
+            Corruption::InvalidChild {
+                parent: id,
+                child: 1000
+            }
+        );
+    }
+
+    #[test]
+    fn test_load_checks_checksum() {
+        let ann = built();
+        let mut saved = Vec::new();
+        ann.save(&mut saved);
+
+        let mut loaded: Annoy<f64, Euclidean> = Annoy::new(2);
+        assert!(loaded.load(saved.as_slice()));
+        assert!(loaded.verify().is_ok());
+
+        let mut flipped = saved.clone();
+        flipped[20] ^= 1;
+        assert!(!Annoy::<f64, Euclidean>::new(2).load(flipped.as_slice()));
+
+        // Cut inside the trailer, right before it and inside the nodes.
+        let trailer = saved.len() - crate::checksum::TRAILER_LEN;
+        for len in [saved.len() - 1, trailer, saved.len() / 2] {
+            assert!(!Annoy::<f64, Euclidean>::new(2).load(&saved[..len]));
+        }
+        let e = Annoy::<f64, Euclidean>::new(2)
+            .try_load(&saved[..trailer])
+            .unwrap_err();
+        assert!(e.to_string().contains("trailer is missing"));
+
+        // Files written before format versions were added have neither a
+        // header nor a trailer.
+        let legacy = bincode::serialize(&ann._nodes).unwrap();
+        assert!(!Annoy::<f64, Euclidean>::new(2).load(legacy.as_slice()));
+    }
+
+    #[test]
+    fn test_load_rejects_baseline_format() {
+        // Saved by the crate before format versions, when split planes were
+        // stored in the node's vector.
+        let baseline = include_bytes!("../tests/fixtures/baseline-euclidean.ann");
+
+        let mut ann = built();
+        let roots = ann._roots.clone();
+        let e = ann.try_load(&baseline[..]).unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+        assert!(e.to_string().contains("before format versions"));
+        assert_eq!(ann._roots, roots);
+
+        let mut saved = Vec::new();
+        ann.save(&mut saved);
+        saved[4] += 1;
+        let e = ann.try_load(saved.as_slice()).unwrap_err();
+        assert!(e.to_string().contains("version 4 is not supported"));
+    }
+}
@@ -0,0 +1,113 @@
+//! The same data stored as f32 and as f64 gives the same neighbors, so an
+//! f32 index can stand in for an f64 one with half the memory for its
+//! vectors.
+
+use little_annoy::{
+    Angular, AnnIndex, Annoy, BruteForce, Distance, Euclidean, Hamming, Hnsw, Item, Manhattan,
+    QueryOptions,
+};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+const F: usize = 8;
+const N_ITEMS: usize = 500;
+const N_QUERIES: usize = 20;
+const K: usize = 10;
+
+/// The same kind of index over each item type.
+type Pair = (Box<dyn AnnIndex<f32>>, Box<dyn AnnIndex<f64>>);
+
+/// Values on a grid of 1/64, which f32 holds exactly, so both item types
+/// start from identical vectors.
+fn vectors(rng: &mut StdRng, n: usize, bits: bool) -> Vec<Vec<f32>> {
+    (0..n)
+        .map(|_| {
+            (0..F)
+                .map(|_| match bits {
+                    true => rng.gen_range(0..2) as f32,
+                    false => rng.gen_range(-64..64) as f32 / 64.,
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn fill<T: Item>(index: &mut dyn AnnIndex<T>, items: &[Vec<f32>], cast: fn(f32) -> T) {
+    for (i, v) in items.iter().enumerate() {
+        let v: Vec<T> = v.iter().copied().map(cast).collect();
+        index.add_item(i as i64, &v).unwrap();
+    }
+    index.build(-1).unwrap();
+}
+
+fn neighbors<T: Item>(
+    index: &dyn AnnIndex<T>,
+    queries: &[Vec<f32>],
+    cast: fn(f32) -> T,
+) -> Vec<Vec<i64>> {
+    let options = QueryOptions::new(K).search_k((N_ITEMS * 100) as i64);
+
+    queries
+        .iter()
+        .map(|q| {
+            let q: Vec<T> = q.iter().copied().map(cast).collect();
+            index.query(&q, options).unwrap().ids()
+        })
+        .collect()
+}
+
+fn check<D>(bits: bool)
+where
+    D: Distance<f32> + Distance<f64> + 'static,
+    <D as Distance<f32>>::Node: Send + Sync,
+    <D as Distance<f64>>::Node: Send + Sync,
+{
+    let mut rng = StdRng::seed_from_u64(42);
+    let items = vectors(&mut rng, N_ITEMS, bits);
+    let queries = vectors(&mut rng, N_QUERIES, bits);
+
+    let indexes: Vec<Pair> = vec![
+        (
+            Box::new(Annoy::<f32, D>::new(F)),
+            Box::new(Annoy::<f64, D>::new(F)),
+        ),
+        (
+            Box::new(Hnsw::<f32, D>::new(F)),
+            Box::new(Hnsw::<f64, D>::new(F)),
+        ),
+        (
+            Box::new(BruteForce::<f32, D>::new(F)),
+            Box::new(BruteForce::<f64, D>::new(F)),
+        ),
+    ];
+
+    for (mut single, mut double) in indexes {
+        fill(single.as_mut(), &items, |x| x);
+        fill(double.as_mut(), &items, f64::from);
+
+        assert_eq!(
+            neighbors(single.as_ref(), &queries, |x| x),
+            neighbors(double.as_ref(), &queries, f64::from),
+        );
+    }
+}
+
+#[test]
+fn test_euclidean() {
+    check::<Euclidean>(false);
+}
+
+#[test]
+fn test_angular() {
+    check::<Angular>(false);
+}
+
+#[test]
+fn test_manhattan() {
+    check::<Manhattan>(false);
+}
+
+#[test]
+fn test_hamming() {
+    check::<Hamming>(true);
+}
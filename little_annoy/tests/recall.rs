@@ -0,0 +1,179 @@
+//! Recall against brute force on synthetic data, so that changes to the
+//! search show up as numbers rather than anecdotes.
+
+use little_annoy::{Angular, Annoy, Distance, Euclidean, Item, Manhattan, QueryOptions};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+const F: usize = 16;
+const N_ITEMS: usize = 2000;
+const N_QUERIES: usize = 50;
+const N_TREES: usize = 10;
+const K: usize = 10;
+
+fn uniform(rng: &mut StdRng, n: usize) -> Vec<Vec<f64>> {
+    (0..n)
+        .map(|_| (0..F).map(|_| rng.gen_range(-1.0..1.0)).collect())
+        .collect()
+}
+
+/// Points scattered around a few random centers, which is where the splits
+/// matter most.
+fn clustered(rng: &mut StdRng, n: usize) -> Vec<Vec<f64>> {
+    let centers = uniform(rng, 8);
+    (0..n)
+        .map(|i| {
+            let c = &centers[i % centers.len()];
+            c.iter()
+                .map(|x| x * 4. + rng.gen_range(-0.5..0.5))
+                .collect()
+        })
+        .collect()
+}
+
+struct Bench<T: Item, D: Distance<T>> {
+    ann: Annoy<T, D>,
+    queries: Vec<Vec<T>>,
+    truth: Vec<Vec<i64>>,
+}
+
+impl<D> Bench<f64, D>
+where
+    D: Distance<f64> + 'static,
+    D::Node: Send + Sync + 'static,
+{
+    fn new(data: fn(&mut StdRng, usize) -> Vec<Vec<f64>>) -> Self {
+        Bench::with_items(data, <[f64]>::to_vec)
+    }
+}
+
+impl<T, D> Bench<T, D>
+where
+    T: Item + Sync + Send + 'static,
+    D: Distance<T> + 'static,
+    D::Node: Send + Sync + 'static,
+{
+    /// A bench on `data` stored as `T`, converted by `to_item`.
+    fn with_items(
+        data: fn(&mut StdRng, usize) -> Vec<Vec<f64>>,
+        to_item: fn(&[f64]) -> Vec<T>,
+    ) -> Self {
+        let mut rng = StdRng::seed_from_u64(42);
+        let items: Vec<Vec<T>> = data(&mut rng, N_ITEMS).iter().map(|v| to_item(v)).collect();
+        let queries: Vec<Vec<T>> = data(&mut rng, N_QUERIES)
+            .iter()
+            .map(|v| to_item(v))
+            .collect();
+
+        let mut ann: Annoy<T, D> = Annoy::new(F);
+        ann.set_seed(7);
+        for (i, v) in items.iter().enumerate() {
+            ann.add_item(i as i64, v).unwrap();
+        }
+        ann.build_with_threads(N_TREES as i64, 1);
+
+        let truth = queries
+            .iter()
+            .map(|q| {
+                let mut all: Vec<(f64, i64)> = items
+                    .iter()
+                    .enumerate()
+                    .map(|(i, v)| (D::distance(q, v, F), i as i64))
+                    .collect();
+                all.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                all.iter().take(K).map(|&(_, i)| i).collect()
+            })
+            .collect();
+
+        Bench {
+            ann,
+            queries,
+            truth,
+        }
+    }
+
+    fn recall(&self, search_k: i64) -> f64 {
+        let mut found = 0;
+        for (q, truth) in self.queries.iter().zip(&self.truth) {
+            let ids = self
+                .ann
+                .query(q, QueryOptions::new(K).search_k(search_k))
+                .unwrap()
+                .ids();
+            found += truth.iter().filter(|i| ids.contains(i)).count();
+        }
+
+        found as f64 / (self.queries.len() * K) as f64
+    }
+
+    /// Checks that inspecting every item is exact, that a larger
+    /// `search_k` never loses neighbors and that the default reaches
+    /// `min_recall`.
+    fn check(&self, min_recall: f64) {
+        let exhaustive = (N_ITEMS * N_TREES) as i64;
+        assert_eq!(self.recall(exhaustive), 1.0);
+
+        let recalls: Vec<f64> = [-1, 10 * (K * N_TREES) as i64, exhaustive]
+            .iter()
+            .map(|&search_k| self.recall(search_k))
+            .collect();
+        assert!(
+            recalls.windows(2).all(|w| w[0] <= w[1]),
+            "recall drops as search_k grows: {:?}",
+            recalls
+        );
+        assert!(
+            recalls[0] >= min_recall,
+            "default recall {} below {}",
+            recalls[0],
+            min_recall
+        );
+    }
+}
+
+#[test]
+fn test_recall_euclidean() {
+    Bench::<f64, Euclidean>::new(uniform).check(0.6);
+    Bench::<f64, Euclidean>::new(clustered).check(0.7);
+}
+
+#[test]
+fn test_recall_angular() {
+    Bench::<f64, Angular>::new(uniform).check(0.6);
+    // Clusters spread over few directions are hard to split by angle.
+    Bench::<f64, Angular>::new(clustered).check(0.35);
+}
+
+#[test]
+fn test_recall_manhattan() {
+    Bench::<f64, Manhattan>::new(uniform).check(0.55);
+    Bench::<f64, Manhattan>::new(clustered).check(0.5);
+}
+
+#[test]
+fn test_recall_integer() {
+    // Split planes of integer items are f64 too, so recall matches floats.
+    let bytes = |v: &[f64]| v.iter().map(|x| (x * 100.) as i8).collect();
+    Bench::<i8, Euclidean>::with_items(uniform, bytes).check(0.6);
+    Bench::<i8, Angular>::with_items(uniform, bytes).check(0.6);
+    Bench::<i8, Manhattan>::with_items(uniform, bytes).check(0.55);
+}
+
+#[test]
+fn test_search_k_bounds_inspected_items() {
+    let bench = Bench::<f64, Euclidean>::new(uniform);
+
+    // Without inspecting any item there is nothing to return.
+    let q = &bench.queries[0];
+    let none = bench
+        .ann
+        .query(q, QueryOptions::new(K).search_k(0))
+        .unwrap();
+    assert!(none.is_empty());
+
+    let few = bench
+        .ann
+        .query(q, QueryOptions::new(K).search_k(3))
+        .unwrap();
+    assert!(!few.is_empty() && few.len() <= 3);
+}
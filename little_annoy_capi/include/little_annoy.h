@@ -0,0 +1,258 @@
+#ifndef LITTLE_ANNOY_H
+#define LITTLE_ANNOY_H
+
+/* Generated by cbindgen from little_annoy_capi/src/lib.rs. Do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/*
+ Distance metric of an index.
+ */
+typedef enum LittleAnnoyMetric {
+  LITTLE_ANNOY_METRIC_ANGULAR = 0,
+  LITTLE_ANNOY_METRIC_EUCLIDEAN = 1,
+  LITTLE_ANNOY_METRIC_MANHATTAN = 2,
+  LITTLE_ANNOY_METRIC_HAMMING = 3,
+} LittleAnnoyMetric;
+
+/*
+ Result of a call that can fail.
+ */
+typedef enum LittleAnnoyStatus {
+  LITTLE_ANNOY_STATUS_OK = 0,
+  /*
+   A required pointer argument was null.
+   */
+  LITTLE_ANNOY_STATUS_NULL_POINTER = 1,
+  /*
+   An argument was out of range, such as a negative item id or a path
+   that is not UTF-8.
+   */
+  LITTLE_ANNOY_STATUS_INVALID_ARGUMENT = 2,
+  /*
+   The item is not in the index.
+   */
+  LITTLE_ANNOY_STATUS_NO_SUCH_ITEM = 3,
+  /*
+   The file could not be opened or created.
+   */
+  LITTLE_ANNOY_STATUS_IO = 4,
+  /*
+   The file is not an index saved by `little_annoy_save`.
+   */
+  LITTLE_ANNOY_STATUS_INVALID_FILE = 5,
+  /*
+   The index is built, so it cannot take items or be built again until
+   it is unbuilt.
+   */
+  LITTLE_ANNOY_STATUS_ALREADY_BUILT = 6,
+  /*
+   The index has no trees to unbuild.
+   */
+  LITTLE_ANNOY_STATUS_NOT_BUILT = 7,
+  /*
+   The build was cancelled before it finished.
+   */
+  LITTLE_ANNOY_STATUS_CANCELLED = 8,
+  /*
+   The file holds an index of another dimension than the handle.
+   */
+  LITTLE_ANNOY_STATUS_DIMENSION_MISMATCH = 9,
+  /*
+   The library hit a bug and stopped the call instead of unwinding into
+   the caller. The index may be left partly updated.
+   */
+  LITTLE_ANNOY_STATUS_PANIC = 10,
+} LittleAnnoyStatus;
+
+/*
+ An index of `f`-dimensional `double` vectors.
+ */
+typedef struct LittleAnnoy LittleAnnoy;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/*
+ Creates an empty index. Returns null if `f` is zero.
+ */
+struct LittleAnnoy *little_annoy_new(enum LittleAnnoyMetric metric, size_t f);
+
+/*
+ Releases an index. Passing null is a no-op.
+
+ # Safety
+
+ `index` must be null or a handle returned by `little_annoy_new` that has
+ not been freed yet.
+ */
+void little_annoy_free(struct LittleAnnoy *index);
+
+/*
+ Number of dimensions the index was created with.
+
+ # Safety
+
+ `index` must be a valid handle.
+ */
+size_t little_annoy_dimension(const struct LittleAnnoy *index);
+
+/*
+ Stores the metric the index was created with in `metric`.
+
+ # Safety
+
+ `index` must be a valid handle and `metric` a valid pointer.
+ */
+enum LittleAnnoyStatus little_annoy_metric(const struct LittleAnnoy *index,
+                                           enum LittleAnnoyMetric *metric);
+
+/*
+ Number of items, i.e. one more than the largest item id added.
+
+ # Safety
+
+ `index` must be a valid handle.
+ */
+int64_t little_annoy_get_n_items(const struct LittleAnnoy *index);
+
+/*
+ Number of trees built or loaded.
+
+ # Safety
+
+ `index` must be a valid handle.
+ */
+size_t little_annoy_get_n_trees(const struct LittleAnnoy *index);
+
+/*
+ Adds item `item` with the `f` values at `vector`.
+
+ # Safety
+
+ `index` must be a valid handle and `vector` must point to `f` doubles.
+ */
+enum LittleAnnoyStatus little_annoy_add_item(struct LittleAnnoy *index,
+                                             int64_t item,
+                                             const double *vector);
+
+/*
+ Builds `n_trees` trees, or lets the index decide when `n_trees` is -1.
+ Fails with `LITTLE_ANNOY_STATUS_ALREADY_BUILT` if the index is built.
+
+ # Safety
+
+ `index` must be a valid handle.
+ */
+enum LittleAnnoyStatus little_annoy_build(struct LittleAnnoy *index, int64_t n_trees);
+
+/*
+ Drops the trees and keeps the items, so that items can be added and the
+ index built again.
+
+ # Safety
+
+ `index` must be a valid handle.
+ */
+enum LittleAnnoyStatus little_annoy_unbuild(struct LittleAnnoy *index);
+
+/*
+ Seeds the random number generator used by `little_annoy_build`.
+
+ # Safety
+
+ `index` must be a valid handle.
+ */
+enum LittleAnnoyStatus little_annoy_set_seed(struct LittleAnnoy *index, uint64_t seed);
+
+/*
+ Writes the index to the file at `path`.
+
+ # Safety
+
+ `index` must be a valid handle and `path` a NUL-terminated UTF-8 string.
+ */
+enum LittleAnnoyStatus little_annoy_save(const struct LittleAnnoy *index, const char *path);
+
+/*
+ Replaces the contents of the index with the file at `path`, which must
+ hold an index of the same metric and dimension. The index is left
+ untouched when loading fails.
+
+ # Safety
+
+ `index` must be a valid handle and `path` a NUL-terminated UTF-8 string.
+ */
+enum LittleAnnoyStatus little_annoy_load(struct LittleAnnoy *index, const char *path);
+
+/*
+ Finds up to `n` nearest neighbours of the `f` values at `vector`.
+
+ Ids are written to `ids` and distances to `distances`, which may be null;
+ both must have room for `n` values. The number of results is stored in
+ `count`.
+
+ # Safety
+
+ `index` must be a valid handle, `vector` must point to `f` doubles and
+ the output buffers must be as described above.
+ */
+enum LittleAnnoyStatus little_annoy_get_nns_by_vector(const struct LittleAnnoy *index,
+                                                      const double *vector,
+                                                      size_t n,
+                                                      int64_t search_k,
+                                                      int64_t *ids,
+                                                      double *distances,
+                                                      size_t *count);
+
+/*
+ Like `little_annoy_get_nns_by_vector`, using the vector of item `item`.
+
+ # Safety
+
+ `index` must be a valid handle and the output buffers as described for
+ `little_annoy_get_nns_by_vector`.
+ */
+enum LittleAnnoyStatus little_annoy_get_nns_by_item(const struct LittleAnnoy *index,
+                                                    int64_t item,
+                                                    size_t n,
+                                                    int64_t search_k,
+                                                    int64_t *ids,
+                                                    double *distances,
+                                                    size_t *count);
+
+/*
+ Copies the vector of item `item` into `vector`, which must have room for
+ `f` doubles.
+
+ # Safety
+
+ `index` must be a valid handle and `vector` must point to `f` writable
+ doubles.
+ */
+enum LittleAnnoyStatus little_annoy_get_item_vector(const struct LittleAnnoy *index,
+                                                    int64_t item,
+                                                    double *vector);
+
+/*
+ Stores the distance between items `i` and `j` in `distance`.
+
+ # Safety
+
+ `index` must be a valid handle and `distance` a valid pointer.
+ */
+enum LittleAnnoyStatus little_annoy_get_distance(const struct LittleAnnoy *index,
+                                                 int64_t i,
+                                                 int64_t j,
+                                                 double *distance);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* LITTLE_ANNOY_H */
@@ -0,0 +1,748 @@
+//! C interface to little-annoy.
+//!
+//! An index is an opaque `LittleAnnoy` handle created by `little_annoy_new`
+//! and released with `little_annoy_free`. Functions that can fail return a
+//! `LittleAnnoyStatus`; query results are written into caller-provided
+//! buffers. A panic inside the library is caught and reported as
+//! `LITTLE_ANNOY_STATUS_PANIC` rather than unwinding into the caller. The header in `include/little_annoy.h` is generated by cbindgen.
+
+use std::ffi::CStr;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::slice;
+
+use little_annoy::{
+    Angular, Annoy, BuildOptions, Error, Euclidean, Hamming, Item, Manhattan, QueryOptions,
+};
+
+/// Distance metric of an index.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LittleAnnoyMetric {
+    Angular = 0,
+    Euclidean = 1,
+    Manhattan = 2,
+    Hamming = 3,
+}
+
+/// Result of a call that can fail.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LittleAnnoyStatus {
+    Ok = 0,
+    /// A required pointer argument was null.
+    NullPointer = 1,
+    /// An argument was out of range, such as a negative item id or a path
+    /// that is not UTF-8.
+    InvalidArgument = 2,
+    /// The item is not in the index.
+    NoSuchItem = 3,
+    /// The file could not be opened or created.
+    Io = 4,
+    /// The file is not an index saved by `little_annoy_save`.
+    InvalidFile = 5,
+    /// The index is built, so it cannot take items or be built again until
+    /// it is unbuilt.
+    AlreadyBuilt = 6,
+    /// The index has no trees to unbuild.
+    NotBuilt = 7,
+    /// The build was cancelled before it finished.
+    Cancelled = 8,
+    /// The file holds an index of another dimension than the handle.
+    DimensionMismatch = 9,
+    /// The library hit a bug and stopped the call instead of unwinding into
+    /// the caller. The index may be left partly updated.
+    Panic = 10,
+}
+
+impl From<Error> for LittleAnnoyStatus {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::AlreadyBuilt => LittleAnnoyStatus::AlreadyBuilt,
+            Error::NotBuilt => LittleAnnoyStatus::NotBuilt,
+            Error::Io(_) => LittleAnnoyStatus::Io,
+            Error::Corrupt(_) => LittleAnnoyStatus::InvalidFile,
+            Error::NonFinite | Error::DimensionMismatch { .. } => {
+                LittleAnnoyStatus::InvalidArgument
+            }
+            Error::Cancelled => LittleAnnoyStatus::Cancelled,
+        }
+    }
+}
+
+enum Index {
+    Angular(Annoy<f64, Angular>),
+    Euclidean(Annoy<f64, Euclidean>),
+    Manhattan(Annoy<f64, Manhattan>),
+    // Words of bits, which doubles hold exactly only up to 2^53.
+    Hamming(Annoy<u64, Hamming>),
+}
+
+// `$ann` is bound to the `Annoy` and `$t` to its item type.
+macro_rules! dispatch {
+    ($index:expr, $ann:ident => $body:expr) => {
+        dispatch!($index, $ann, _T => $body)
+    };
+    ($index:expr, $ann:ident, $t:ident => $body:expr) => {
+        match $index {
+            Index::Angular($ann) => {
+                type $t = f64;
+                $body
+            }
+            Index::Euclidean($ann) => {
+                type $t = f64;
+                $body
+            }
+            Index::Manhattan($ann) => {
+                type $t = f64;
+                $body
+            }
+            Index::Hamming($ann) => {
+                type $t = u64;
+                $body
+            }
+        }
+    };
+}
+
+/// `v` as items of type `T`, which `check_vector` made sure it holds
+/// exactly.
+fn items<T: Item>(v: &[f64]) -> Vec<T> {
+    v.iter().map(|&x| T::from_f64(x).unwrap()).collect()
+}
+
+/// Copies the items `v` into `out` as doubles.
+fn doubles<T: Item>(v: &[T], out: &mut [f64]) {
+    for (out, x) in out.iter_mut().zip(v) {
+        *out = x.to_f64().unwrap();
+    }
+}
+
+/// Runs `f`, returning `on_panic` if it panics instead of unwinding into C,
+/// which would abort the caller.
+fn guard<R>(on_panic: R, f: impl FnOnce() -> R) -> R {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(on_panic)
+}
+
+/// An index of `f`-dimensional `double` vectors.
+pub struct LittleAnnoy {
+    f: usize,
+    metric: LittleAnnoyMetric,
+    index: Index,
+}
+
+impl LittleAnnoy {
+    fn new(metric: LittleAnnoyMetric, f: usize) -> Self {
+        let index = match metric {
+            LittleAnnoyMetric::Angular => Index::Angular(Annoy::new(f)),
+            LittleAnnoyMetric::Euclidean => Index::Euclidean(Annoy::new(f)),
+            LittleAnnoyMetric::Manhattan => Index::Manhattan(Annoy::new(f)),
+            LittleAnnoyMetric::Hamming => Index::Hamming(Annoy::new(f)),
+        };
+
+        Self { f, metric, index }
+    }
+
+    fn contains(&self, item: i64) -> bool {
+        dispatch!(&self.index, ann => ann.contains(item))
+    }
+
+    /// Hamming vectors are stored as words of bits, so each value must be a
+    /// whole number from 0 to below 2^64.
+    fn check_vector(&self, v: &[f64]) -> Result<(), LittleAnnoyStatus> {
+        let word = |x: f64| x.fract() == 0.0 && (0.0..18_446_744_073_709_551_616.0).contains(&x);
+
+        if self.metric == LittleAnnoyMetric::Hamming && !v.iter().all(|&x| word(x)) {
+            Err(LittleAnnoyStatus::InvalidArgument)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+macro_rules! try_status {
+    ($e:expr) => {
+        match $e {
+            Ok(v) => v,
+            Err(e) => return LittleAnnoyStatus::from(e),
+        }
+    };
+}
+
+unsafe fn handle<'a>(ptr: *const LittleAnnoy) -> Result<&'a LittleAnnoy, LittleAnnoyStatus> {
+    ptr.as_ref().ok_or(LittleAnnoyStatus::NullPointer)
+}
+
+unsafe fn handle_mut<'a>(ptr: *mut LittleAnnoy) -> Result<&'a mut LittleAnnoy, LittleAnnoyStatus> {
+    ptr.as_mut().ok_or(LittleAnnoyStatus::NullPointer)
+}
+
+unsafe fn vector<'a>(ptr: *const f64, f: usize) -> Result<&'a [f64], LittleAnnoyStatus> {
+    if ptr.is_null() {
+        Err(LittleAnnoyStatus::NullPointer)
+    } else {
+        Ok(slice::from_raw_parts(ptr, f))
+    }
+}
+
+unsafe fn path<'a>(ptr: *const c_char) -> Result<&'a str, LittleAnnoyStatus> {
+    if ptr.is_null() {
+        return Err(LittleAnnoyStatus::NullPointer);
+    }
+
+    CStr::from_ptr(ptr)
+        .to_str()
+        .map_err(|_| LittleAnnoyStatus::InvalidArgument)
+}
+
+/// Copies up to `n` results into `ids` and, unless it is null, `distances`.
+unsafe fn write_results(
+    nns: (Vec<i64>, Vec<f64>),
+    n: usize,
+    ids: *mut i64,
+    distances: *mut f64,
+    count: *mut usize,
+) {
+    let (result, dist) = nns;
+    let m = result.len().min(n);
+
+    slice::from_raw_parts_mut(ids, m).copy_from_slice(&result[..m]);
+    if !distances.is_null() {
+        slice::from_raw_parts_mut(distances, m).copy_from_slice(&dist[..m]);
+    }
+    *count = m;
+}
+
+/// Creates an empty index. Returns null if `f` is zero.
+#[no_mangle]
+pub extern "C" fn little_annoy_new(metric: LittleAnnoyMetric, f: usize) -> *mut LittleAnnoy {
+    guard(std::ptr::null_mut(), || {
+        if f == 0 {
+            return std::ptr::null_mut();
+        }
+
+        Box::into_raw(Box::new(LittleAnnoy::new(metric, f)))
+    })
+}
+
+/// Releases an index. Passing null is a no-op.
+///
+/// # Safety
+///
+/// `index` must be null or a handle returned by `little_annoy_new` that has
+/// not been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn little_annoy_free(index: *mut LittleAnnoy) {
+    guard((), || {
+        if !index.is_null() {
+            drop(Box::from_raw(index));
+        }
+    })
+}
+
+/// Number of dimensions the index was created with.
+///
+/// # Safety
+///
+/// `index` must be a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn little_annoy_dimension(index: *const LittleAnnoy) -> usize {
+    handle(index).map_or(0, |index| index.f)
+}
+
+/// Stores the metric the index was created with in `metric`.
+///
+/// # Safety
+///
+/// `index` must be a valid handle and `metric` a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn little_annoy_metric(
+    index: *const LittleAnnoy,
+    metric: *mut LittleAnnoyMetric,
+) -> LittleAnnoyStatus {
+    let index = try_status!(handle(index));
+
+    if metric.is_null() {
+        return LittleAnnoyStatus::NullPointer;
+    }
+
+    *metric = index.metric;
+    LittleAnnoyStatus::Ok
+}
+
+/// Number of items, i.e. one more than the largest item id added.
+///
+/// # Safety
+///
+/// `index` must be a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn little_annoy_get_n_items(index: *const LittleAnnoy) -> i64 {
+    guard(0, || {
+        handle(index).map_or(
+            0,
+            |index| dispatch!(&index.index, ann => ann.n_items() as i64),
+        )
+    })
+}
+
+/// Number of trees built or loaded.
+///
+/// # Safety
+///
+/// `index` must be a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn little_annoy_get_n_trees(index: *const LittleAnnoy) -> usize {
+    guard(0, || {
+        handle(index).map_or(0, |index| dispatch!(&index.index, ann => ann.n_trees()))
+    })
+}
+
+/// Adds item `item` with the `f` values at `vector`.
+///
+/// # Safety
+///
+/// `index` must be a valid handle and `vector` must point to `f` doubles.
+#[no_mangle]
+pub unsafe extern "C" fn little_annoy_add_item(
+    index: *mut LittleAnnoy,
+    item: i64,
+    vector: *const f64,
+) -> LittleAnnoyStatus {
+    guard(LittleAnnoyStatus::Panic, || {
+        let index = try_status!(handle_mut(index));
+        let v = try_status!(self::vector(vector, index.f));
+
+        if item < 0 {
+            return LittleAnnoyStatus::InvalidArgument;
+        }
+        try_status!(index.check_vector(v));
+
+        try_status!(dispatch!(&mut index.index, ann, T => ann.add_item(item, &items::<T>(v))));
+        LittleAnnoyStatus::Ok
+    })
+}
+
+/// Builds `n_trees` trees, or lets the index decide when `n_trees` is -1.
+/// Fails with `LITTLE_ANNOY_STATUS_ALREADY_BUILT` if the index is built.
+///
+/// # Safety
+///
+/// `index` must be a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn little_annoy_build(
+    index: *mut LittleAnnoy,
+    n_trees: i64,
+) -> LittleAnnoyStatus {
+    guard(LittleAnnoyStatus::Panic, || {
+        let index = try_status!(handle_mut(index));
+
+        if n_trees < -1 {
+            return LittleAnnoyStatus::InvalidArgument;
+        }
+
+        try_status!(dispatch!(&mut index.index, ann => ann.build_with(BuildOptions::new(n_trees))));
+        LittleAnnoyStatus::Ok
+    })
+}
+
+/// Drops the trees and keeps the items, so that items can be added and the
+/// index built again.
+///
+/// # Safety
+///
+/// `index` must be a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn little_annoy_unbuild(index: *mut LittleAnnoy) -> LittleAnnoyStatus {
+    guard(LittleAnnoyStatus::Panic, || {
+        let index = try_status!(handle_mut(index));
+
+        try_status!(dispatch!(&mut index.index, ann => ann.unbuild()));
+        LittleAnnoyStatus::Ok
+    })
+}
+
+/// Seeds the random number generator used by `little_annoy_build`.
+///
+/// # Safety
+///
+/// `index` must be a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn little_annoy_set_seed(
+    index: *mut LittleAnnoy,
+    seed: u64,
+) -> LittleAnnoyStatus {
+    guard(LittleAnnoyStatus::Panic, || {
+        let index = try_status!(handle_mut(index));
+
+        dispatch!(&mut index.index, ann => ann.set_seed(seed));
+        LittleAnnoyStatus::Ok
+    })
+}
+
+/// Writes the index to the file at `path`.
+///
+/// # Safety
+///
+/// `index` must be a valid handle and `path` a NUL-terminated UTF-8 string.
+#[no_mangle]
+pub unsafe extern "C" fn little_annoy_save(
+    index: *const LittleAnnoy,
+    path: *const c_char,
+) -> LittleAnnoyStatus {
+    guard(LittleAnnoyStatus::Panic, || {
+        let index = try_status!(handle(index));
+        let path = try_status!(self::path(path));
+        let file = try_status!(File::create(path).map_err(|_| LittleAnnoyStatus::Io));
+
+        try_status!(
+            dispatch!(&index.index, ann => ann.try_save(BufWriter::new(file)))
+                .map_err(|_| LittleAnnoyStatus::Io)
+        );
+        LittleAnnoyStatus::Ok
+    })
+}
+
+/// Replaces the contents of the index with the file at `path`, which must
+/// hold an index of the same metric and dimension. The index is left
+/// untouched when loading fails.
+///
+/// # Safety
+///
+/// `index` must be a valid handle and `path` a NUL-terminated UTF-8 string.
+#[no_mangle]
+pub unsafe extern "C" fn little_annoy_load(
+    index: *mut LittleAnnoy,
+    path: *const c_char,
+) -> LittleAnnoyStatus {
+    guard(LittleAnnoyStatus::Panic, || {
+        let index = try_status!(handle_mut(index));
+        let path = try_status!(self::path(path));
+        let file = try_status!(File::open(path).map_err(|_| LittleAnnoyStatus::Io));
+
+        let mut loaded = LittleAnnoy::new(index.metric, index.f);
+        if !dispatch!(&mut loaded.index, ann => ann.load(BufReader::new(file))) {
+            return LittleAnnoyStatus::InvalidFile;
+        }
+        if dispatch!(&loaded.index, ann => ann.dimension()) != index.f {
+            return LittleAnnoyStatus::DimensionMismatch;
+        }
+
+        *index = loaded;
+        LittleAnnoyStatus::Ok
+    })
+}
+
+/// Finds up to `n` nearest neighbours of the `f` values at `vector`.
+///
+/// Ids are written to `ids` and distances to `distances`, which may be null;
+/// both must have room for `n` values. The number of results is stored in
+/// `count`.
+///
+/// # Safety
+///
+/// `index` must be a valid handle, `vector` must point to `f` doubles and
+/// the output buffers must be as described above.
+#[no_mangle]
+pub unsafe extern "C" fn little_annoy_get_nns_by_vector(
+    index: *const LittleAnnoy,
+    vector: *const f64,
+    n: usize,
+    search_k: i64,
+    ids: *mut i64,
+    distances: *mut f64,
+    count: *mut usize,
+) -> LittleAnnoyStatus {
+    guard(LittleAnnoyStatus::Panic, || {
+        let index = try_status!(handle(index));
+        let v = try_status!(self::vector(vector, index.f));
+
+        if ids.is_null() || count.is_null() {
+            return LittleAnnoyStatus::NullPointer;
+        }
+        try_status!(index.check_vector(v));
+
+        let options = QueryOptions::new(n).search_k(search_k);
+        let nns = try_status!(dispatch!(&index.index, ann, T => {
+            ann.query(&items::<T>(v), options).map(|nns| nns.into_parts())
+        }));
+        write_results(nns, n, ids, distances, count);
+        LittleAnnoyStatus::Ok
+    })
+}
+
+/// Like `little_annoy_get_nns_by_vector`, using the vector of item `item`.
+///
+/// # Safety
+///
+/// `index` must be a valid handle and the output buffers as described for
+/// `little_annoy_get_nns_by_vector`.
+#[no_mangle]
+pub unsafe extern "C" fn little_annoy_get_nns_by_item(
+    index: *const LittleAnnoy,
+    item: i64,
+    n: usize,
+    search_k: i64,
+    ids: *mut i64,
+    distances: *mut f64,
+    count: *mut usize,
+) -> LittleAnnoyStatus {
+    guard(LittleAnnoyStatus::Panic, || {
+        let index = try_status!(handle(index));
+
+        if ids.is_null() || count.is_null() {
+            return LittleAnnoyStatus::NullPointer;
+        }
+        if !index.contains(item) {
+            return LittleAnnoyStatus::NoSuchItem;
+        }
+
+        let nns = dispatch!(&index.index, ann => ann.get_nns_by_item(item, n, search_k));
+        write_results(nns, n, ids, distances, count);
+        LittleAnnoyStatus::Ok
+    })
+}
+
+/// Copies the vector of item `item` into `vector`, which must have room for
+/// `f` doubles.
+///
+/// # Safety
+///
+/// `index` must be a valid handle and `vector` must point to `f` writable
+/// doubles.
+#[no_mangle]
+pub unsafe extern "C" fn little_annoy_get_item_vector(
+    index: *const LittleAnnoy,
+    item: i64,
+    vector: *mut f64,
+) -> LittleAnnoyStatus {
+    guard(LittleAnnoyStatus::Panic, || {
+        let index = try_status!(handle(index));
+
+        if vector.is_null() {
+            return LittleAnnoyStatus::NullPointer;
+        }
+        if !index.contains(item) {
+            return LittleAnnoyStatus::NoSuchItem;
+        }
+
+        let out = slice::from_raw_parts_mut(vector, index.f);
+        dispatch!(&index.index, ann => doubles(&ann.get_item_vector(item).unwrap(), out));
+        LittleAnnoyStatus::Ok
+    })
+}
+
+/// Stores the distance between items `i` and `j` in `distance`.
+///
+/// # Safety
+///
+/// `index` must be a valid handle and `distance` a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn little_annoy_get_distance(
+    index: *const LittleAnnoy,
+    i: i64,
+    j: i64,
+    distance: *mut f64,
+) -> LittleAnnoyStatus {
+    guard(LittleAnnoyStatus::Panic, || {
+        let index = try_status!(handle(index));
+
+        if distance.is_null() {
+            return LittleAnnoyStatus::NullPointer;
+        }
+        if !index.contains(i) || !index.contains(j) {
+            return LittleAnnoyStatus::NoSuchItem;
+        }
+
+        *distance = dispatch!(&index.index, ann => ann.get_distance(i, j));
+        LittleAnnoyStatus::Ok
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn crate_dir() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+    }
+
+    fn generate_header() -> String {
+        let mut out = Vec::new();
+        cbindgen::generate(crate_dir())
+            .expect("failed to generate the header")
+            .write(&mut out);
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_header_is_up_to_date() {
+        let path = crate_dir().join("include/little_annoy.h");
+        let header = generate_header();
+
+        if std::env::var_os("LITTLE_ANNOY_BLESS").is_some() {
+            std::fs::write(&path, &header).unwrap();
+        }
+
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            header,
+            "include/little_annoy.h is stale; rerun with LITTLE_ANNOY_BLESS=1"
+        );
+    }
+
+    #[test]
+    fn test_handle() {
+        unsafe {
+            let index = little_annoy_new(LittleAnnoyMetric::Euclidean, 2);
+            for i in 0..10 {
+                let v = [i as f64, i as f64];
+                assert_eq!(
+                    little_annoy_add_item(index, i, v.as_ptr()),
+                    LittleAnnoyStatus::Ok
+                );
+            }
+            little_annoy_set_seed(index, 1);
+            assert_eq!(little_annoy_build(index, 5), LittleAnnoyStatus::Ok);
+            assert_eq!(little_annoy_get_n_trees(index), 5);
+
+            let mut ids = [0i64; 3];
+            let mut count = 0;
+            let status = little_annoy_get_nns_by_item(
+                index,
+                42,
+                3,
+                -1,
+                ids.as_mut_ptr(),
+                std::ptr::null_mut(),
+                &mut count,
+            );
+            assert_eq!(status, LittleAnnoyStatus::NoSuchItem);
+
+            let mut d = 0.0;
+            assert_eq!(
+                little_annoy_get_distance(index, 0, 3, &mut d),
+                LittleAnnoyStatus::Ok
+            );
+            assert_eq!(d, (18.0f64).sqrt());
+
+            little_annoy_free(index);
+        }
+
+        unsafe {
+            let index = little_annoy_new(LittleAnnoyMetric::Hamming, 2);
+            let mut metric = LittleAnnoyMetric::Angular;
+            assert_eq!(
+                little_annoy_metric(index, &mut metric),
+                LittleAnnoyStatus::Ok
+            );
+            assert_eq!(metric, LittleAnnoyMetric::Hamming);
+            assert_eq!(
+                little_annoy_metric(std::ptr::null(), &mut metric),
+                LittleAnnoyStatus::NullPointer
+            );
+
+            assert_eq!(
+                little_annoy_add_item(index, 0, [-1.0, 2.0].as_ptr()),
+                LittleAnnoyStatus::InvalidArgument
+            );
+            assert_eq!(
+                little_annoy_add_item(index, 0, [1.0, 2.0].as_ptr()),
+                LittleAnnoyStatus::Ok
+            );
+            assert_eq!(little_annoy_build(index, 1), LittleAnnoyStatus::Ok);
+
+            let mut ids = [0i64; 1];
+            let mut count = 0;
+            let status = little_annoy_get_nns_by_vector(
+                index,
+                [1.0, f64::MAX].as_ptr(),
+                1,
+                -1,
+                ids.as_mut_ptr(),
+                std::ptr::null_mut(),
+                &mut count,
+            );
+            assert_eq!(status, LittleAnnoyStatus::InvalidArgument);
+
+            let full = std::ffi::CString::new("/dev/full").unwrap();
+            assert_eq!(
+                little_annoy_save(index, full.as_ptr()),
+                LittleAnnoyStatus::Io
+            );
+
+            little_annoy_free(index);
+        }
+
+        assert_eq!(
+            LittleAnnoyStatus::from(Error::Cancelled),
+            LittleAnnoyStatus::Cancelled
+        );
+        assert!(little_annoy_new(LittleAnnoyMetric::Angular, 0).is_null());
+        assert_eq!(
+            unsafe { little_annoy_add_item(std::ptr::null_mut(), 0, [0.0].as_ptr()) },
+            LittleAnnoyStatus::NullPointer
+        );
+    }
+
+    #[test]
+    fn test_load_and_words() {
+        let path =
+            std::env::temp_dir().join(format!("little_annoy_capi_{}.ann", std::process::id()));
+        let path = std::ffi::CString::new(path.to_str().unwrap()).unwrap();
+        // 2^63 + 2^11 is past the 2^53 up to which doubles hold every integer.
+        let word = 9_223_372_036_854_777_856.0;
+
+        unsafe {
+            let index = little_annoy_new(LittleAnnoyMetric::Hamming, 2);
+            assert_eq!(
+                little_annoy_add_item(index, 0, [1.5, 0.0].as_ptr()),
+                LittleAnnoyStatus::InvalidArgument
+            );
+            assert_eq!(
+                little_annoy_add_item(index, 0, [word, 1.0].as_ptr()),
+                LittleAnnoyStatus::Ok
+            );
+            assert_eq!(
+                little_annoy_add_item(index, 1, [word - 2048.0, 1.0].as_ptr()),
+                LittleAnnoyStatus::Ok
+            );
+            assert_eq!(little_annoy_build(index, 1), LittleAnnoyStatus::Ok);
+
+            let mut v = [0.0; 2];
+            assert_eq!(
+                little_annoy_get_item_vector(index, 0, v.as_mut_ptr()),
+                LittleAnnoyStatus::Ok
+            );
+            assert_eq!(v, [word, 1.0]);
+            let mut d = 0.0;
+            assert_eq!(
+                little_annoy_get_distance(index, 0, 1, &mut d),
+                LittleAnnoyStatus::Ok
+            );
+            assert_eq!(d, 1.0);
+
+            assert_eq!(
+                little_annoy_save(index, path.as_ptr()),
+                LittleAnnoyStatus::Ok
+            );
+            little_annoy_free(index);
+
+            let wider = little_annoy_new(LittleAnnoyMetric::Hamming, 3);
+            assert_eq!(
+                little_annoy_load(wider, path.as_ptr()),
+                LittleAnnoyStatus::DimensionMismatch
+            );
+            assert_eq!(little_annoy_get_n_items(wider), 0);
+            little_annoy_free(wider);
+        }
+
+        std::fs::remove_file(path.to_str().unwrap()).unwrap();
+        assert_eq!(
+            guard(LittleAnnoyStatus::Panic, || panic!("a bug")),
+            LittleAnnoyStatus::Panic
+        );
+    }
+}
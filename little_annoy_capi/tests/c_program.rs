@@ -0,0 +1,54 @@
+//! Compiles `tests/test.c` against the static library and runs it.
+
+use std::path::PathBuf;
+use std::process::Command;
+
+fn compiler() -> Option<String> {
+    let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_owned());
+    Command::new(&cc).arg("--version").output().ok().map(|_| cc)
+}
+
+fn static_lib() -> Option<PathBuf> {
+    // target/<profile>/deps/<test binary>
+    let exe = std::env::current_exe().ok()?;
+    let lib = exe.parent()?.parent()?.join("liblittle_annoy_capi.a");
+    Some(lib).filter(|lib| lib.exists())
+}
+
+#[test]
+fn test_c_program() {
+    let (cc, lib) = match (compiler(), static_lib()) {
+        (Some(cc), Some(lib)) => (cc, lib),
+        _ => {
+            eprintln!("skipping: no C compiler or static library");
+            return;
+        }
+    };
+
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let tmp = std::env::temp_dir();
+    let exe = tmp.join(format!("little-annoy-capi-{}", std::process::id()));
+    let index = tmp.join(format!("little-annoy-capi-{}.ann", std::process::id()));
+
+    let status = Command::new(cc)
+        .arg(dir.join("tests/test.c"))
+        .arg("-I")
+        .arg(dir.join("include"))
+        .arg(&lib)
+        .args(["-lpthread", "-ldl", "-lm", "-o"])
+        .arg(&exe)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let out = Command::new(&exe).arg(&index).output().unwrap();
+    let _ = std::fs::remove_file(&exe);
+    let _ = std::fs::remove_file(&index);
+
+    assert!(
+        out.status.success(),
+        "{}{}",
+        String::from_utf8_lossy(&out.stdout),
+        String::from_utf8_lossy(&out.stderr)
+    );
+}
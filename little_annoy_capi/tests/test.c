@@ -0,0 +1,133 @@
+/* Exercises the C API: build, query, save, load and error reporting. */
+
+#include <math.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+#include "little_annoy.h"
+
+#define CHECK(cond)                                                     \
+  do {                                                                  \
+    if (!(cond)) {                                                      \
+      fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__, __LINE__,  \
+              #cond);                                                   \
+      exit(1);                                                          \
+    }                                                                   \
+  } while (0)
+
+#define F 4
+#define N_ITEMS 100
+#define N 5
+
+static void build(LittleAnnoy *index) {
+  double v[F];
+  for (int64_t i = 0; i < N_ITEMS; i++) {
+    for (int j = 0; j < F; j++) {
+      v[j] = (double)((i * (j + 1)) % 17);
+    }
+    CHECK(little_annoy_add_item(index, i, v) == LITTLE_ANNOY_STATUS_OK);
+  }
+
+  CHECK(little_annoy_set_seed(index, 42) == LITTLE_ANNOY_STATUS_OK);
+  CHECK(little_annoy_build(index, 10) == LITTLE_ANNOY_STATUS_OK);
+  CHECK(little_annoy_get_n_items(index) == N_ITEMS);
+  CHECK(little_annoy_get_n_trees(index) == 10);
+}
+
+static void query(const LittleAnnoy *index) {
+  int64_t ids[N];
+  double distances[N];
+  size_t count = 0;
+  double v[F];
+
+  CHECK(little_annoy_get_item_vector(index, 7, v) == LITTLE_ANNOY_STATUS_OK);
+  CHECK(little_annoy_get_nns_by_vector(index, v, N, -1, ids, distances,
+                                       &count) == LITTLE_ANNOY_STATUS_OK);
+  CHECK(count > 0 && count <= N);
+  for (size_t k = 1; k < count; k++) {
+    CHECK(distances[k - 1] <= distances[k]);
+  }
+
+  /* Distances are optional. */
+  CHECK(little_annoy_get_nns_by_item(index, 7, N, -1, ids, NULL, &count) ==
+        LITTLE_ANNOY_STATUS_OK);
+  CHECK(count > 0 && count <= N);
+
+  double d = -1.0;
+  CHECK(little_annoy_get_distance(index, 7, 7, &d) == LITTLE_ANNOY_STATUS_OK);
+  CHECK(fabs(d) < 1e-9);
+}
+
+static void test_metric(LittleAnnoyMetric metric, const char *path) {
+  LittleAnnoy *index = little_annoy_new(metric, F);
+  CHECK(index != NULL);
+  LittleAnnoyMetric stored;
+  CHECK(little_annoy_metric(index, &stored) == LITTLE_ANNOY_STATUS_OK);
+  CHECK(stored == metric);
+  CHECK(little_annoy_dimension(index) == F);
+
+  build(index);
+  query(index);
+  CHECK(little_annoy_save(index, path) == LITTLE_ANNOY_STATUS_OK);
+  little_annoy_free(index);
+
+  LittleAnnoy *wider = little_annoy_new(metric, F + 1);
+  CHECK(little_annoy_load(wider, path) ==
+        LITTLE_ANNOY_STATUS_DIMENSION_MISMATCH);
+  little_annoy_free(wider);
+
+  LittleAnnoy *loaded = little_annoy_new(metric, F);
+  CHECK(little_annoy_load(loaded, path) == LITTLE_ANNOY_STATUS_OK);
+  CHECK(little_annoy_get_n_items(loaded) == N_ITEMS);
+  CHECK(little_annoy_get_n_trees(loaded) == 10);
+  query(loaded);
+  little_annoy_free(loaded);
+}
+
+static void test_errors(void) {
+  int64_t ids[N];
+  size_t count;
+  double d;
+
+  CHECK(little_annoy_new(LITTLE_ANNOY_METRIC_ANGULAR, 0) == NULL);
+  CHECK(little_annoy_build(NULL, 10) == LITTLE_ANNOY_STATUS_NULL_POINTER);
+
+  LittleAnnoy *index = little_annoy_new(LITTLE_ANNOY_METRIC_EUCLIDEAN, F);
+  CHECK(little_annoy_add_item(index, 0, NULL) ==
+        LITTLE_ANNOY_STATUS_NULL_POINTER);
+  CHECK(little_annoy_get_nns_by_item(index, 3, N, -1, ids, NULL, &count) ==
+        LITTLE_ANNOY_STATUS_NO_SUCH_ITEM);
+  CHECK(little_annoy_get_distance(index, 0, 1, &d) ==
+        LITTLE_ANNOY_STATUS_NO_SUCH_ITEM);
+  CHECK(little_annoy_load(index, "/nonexistent/index.ann") ==
+        LITTLE_ANNOY_STATUS_IO);
+  little_annoy_free(index);
+
+  double v[F] = {0};
+  index = little_annoy_new(LITTLE_ANNOY_METRIC_EUCLIDEAN, F);
+  CHECK(little_annoy_unbuild(index) == LITTLE_ANNOY_STATUS_NOT_BUILT);
+  build(index);
+  CHECK(little_annoy_add_item(index, N_ITEMS, v) ==
+        LITTLE_ANNOY_STATUS_ALREADY_BUILT);
+  CHECK(little_annoy_build(index, 10) == LITTLE_ANNOY_STATUS_ALREADY_BUILT);
+  CHECK(little_annoy_unbuild(index) == LITTLE_ANNOY_STATUS_OK);
+  CHECK(little_annoy_get_n_trees(index) == 0);
+  CHECK(little_annoy_add_item(index, N_ITEMS, v) == LITTLE_ANNOY_STATUS_OK);
+  CHECK(little_annoy_build(index, 2) == LITTLE_ANNOY_STATUS_OK);
+  little_annoy_free(index);
+  little_annoy_free(NULL);
+}
+
+int main(int argc, char **argv) {
+  const char *path = argc > 1 ? argv[1] : "test.ann";
+
+  test_metric(LITTLE_ANNOY_METRIC_ANGULAR, path);
+  test_metric(LITTLE_ANNOY_METRIC_EUCLIDEAN, path);
+  test_metric(LITTLE_ANNOY_METRIC_MANHATTAN, path);
+  test_metric(LITTLE_ANNOY_METRIC_HAMMING, path);
+  test_errors();
+
+  remove(path);
+  printf("ok\n");
+  return 0;
+}
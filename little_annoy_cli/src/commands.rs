@@ -0,0 +1,223 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use std::time::Instant;
+
+use little_annoy::{cmp_distance, Annoy, BuildOptions, Distance, QueryOptions};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use serde_json::json;
+
+use crate::input::{Format, Input};
+use crate::{BuildArgs, EvalArgs, IndexArgs, QueryArgs};
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+/// Loads the index at `path`. Its dimension comes from the file; `f`, when
+/// given, must agree with it.
+fn load<D>(path: &Path, f: Option<usize>) -> Result<Annoy<f64, D>>
+where
+    D: Distance<f64> + 'static,
+    D::Node: Send + Sync,
+{
+    let file = File::open(path)?;
+    let mut ann = Annoy::new(0);
+    if !ann.load(BufReader::new(file)) {
+        return Err(format!("{} is not a valid index file", path.display()).into());
+    }
+
+    match f {
+        Some(f) if f != ann.dimension() => Err(format!(
+            "{} has {} dimensions, but -f is {}",
+            path.display(),
+            ann.dimension(),
+            f
+        )
+        .into()),
+        _ => Ok(ann),
+    }
+}
+
+fn item_ids<D: Distance<f64> + 'static>(ann: &Annoy<f64, D>) -> Vec<i64> {
+    ann.items().map(|(i, _)| i).collect()
+}
+
+fn print_json(value: &serde_json::Value) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+pub fn build<D>(args: &BuildArgs) -> Result<()>
+where
+    D: Distance<f64> + 'static,
+    D::Node: Send + Sync,
+{
+    let format = match args.format.or_else(|| Format::from_path(&args.input)) {
+        Some(format) => format,
+        None => return Err("cannot guess the input format, pass --format".into()),
+    };
+
+    let input = Input {
+        path: &args.input,
+        format,
+        id_column: args.id_column,
+        has_header: args.header,
+    };
+
+    let f = input.dimension()?;
+    if f == 0 {
+        return Err("input contains no vectors".into());
+    }
+
+    let mut ann: Annoy<f64, D> = Annoy::new(f);
+    if let Some(seed) = args.seed {
+        ann.set_seed(seed);
+    }
+
+    let items = input.load(&mut ann)?;
+    if args.on_disk {
+        ann.on_disk_build(&args.output)?;
+    }
+
+    let started = Instant::now();
+    ann.build_with(BuildOptions::new(args.trees).n_threads(args.threads))?;
+    let elapsed = started.elapsed();
+
+    if !args.on_disk {
+        ann.save(BufWriter::new(File::create(&args.output)?));
+    }
+
+    print_json(&json!({
+        "output": args.output,
+        "metric": args.metric.name(),
+        "dimensions": f,
+        "items": items,
+        "trees": ann.n_trees(),
+        "build_seconds": elapsed.as_secs_f64(),
+    }))
+}
+
+pub fn query<D>(args: &QueryArgs) -> Result<()>
+where
+    D: Distance<f64> + 'static,
+    D::Node: Send + Sync,
+{
+    let ann: Annoy<f64, D> = load(&args.index.index, args.dimensions)?;
+
+    let (ids, distances) = match (&args.vector, args.item) {
+        (Some(v), _) => {
+            if v.len() != ann.dimension() {
+                return Err(format!(
+                    "query has {} dimensions, expected {}",
+                    v.len(),
+                    ann.dimension()
+                )
+                .into());
+            }
+            let options = QueryOptions::new(args.n).search_k(args.search_k);
+            ann.query(v, options)?.into_parts()
+        }
+        (None, Some(item)) => {
+            if !ann.contains(item) {
+                return Err(format!("item {} is not in the index", item).into());
+            }
+            ann.get_nns_by_item(item, args.n, args.search_k)
+        }
+        (None, None) => return Err("pass either --vector or --item".into()),
+    };
+
+    let neighbors: Vec<_> = ids
+        .iter()
+        .zip(distances.iter())
+        .map(|(id, distance)| json!({ "id": id, "distance": distance }))
+        .collect();
+
+    print_json(&json!(neighbors))
+}
+
+pub fn info<D>(args: &IndexArgs) -> Result<()>
+where
+    D: Distance<f64> + 'static,
+    D::Node: Send + Sync,
+{
+    let file_size = std::fs::metadata(&args.index)?.len();
+    let ann: Annoy<f64, D> = load(&args.index, None)?;
+
+    let items = item_ids(&ann);
+
+    let stats = ann.stats();
+
+    print_json(&json!({
+        "metric": args.metric.name(),
+        "dimensions": ann.dimension(),
+        "items": items.len(),
+        "trees": ann.n_trees(),
+        "nodes": items.len() + stats.split_nodes + stats.leaf_nodes,
+        "split_nodes": stats.split_nodes,
+        "leaf_nodes": stats.leaf_nodes,
+        "depth_histogram": stats.depths,
+        "leaf_sizes": stats.leaf_sizes,
+        "balance_histogram": stats.balance,
+        "memory_bytes": stats.memory_bytes,
+        "file_size": file_size,
+    }))
+}
+
+pub fn eval<D>(args: &EvalArgs) -> Result<()>
+where
+    D: Distance<f64> + 'static,
+    D::Node: Send + Sync,
+{
+    let ann: Annoy<f64, D> = load(&args.index.index, args.dimensions)?;
+    let items = item_ids(&ann);
+
+    if items.is_empty() {
+        return Err("index contains no items".into());
+    }
+
+    let mut rng = match args.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let queries: Vec<i64> = items
+        .choose_multiple(&mut rng, args.queries)
+        .copied()
+        .collect();
+
+    let mut recall = 0.0;
+    let mut ann_seconds = 0.0;
+    let mut exact_seconds = 0.0;
+
+    for q in &queries {
+        let started = Instant::now();
+        let (found, _) = ann.get_nns_by_item(*q, args.n, args.search_k);
+        ann_seconds += started.elapsed().as_secs_f64();
+
+        let started = Instant::now();
+        let mut exact: Vec<(f64, i64)> = items
+            .iter()
+            .map(|j| (ann.get_distance(*q, *j), *j))
+            .collect();
+        exact.sort_by(|a, b| cmp_distance(a.0, b.0).then(a.1.cmp(&b.1)));
+        exact.truncate(args.n);
+        exact_seconds += started.elapsed().as_secs_f64();
+
+        let found: HashSet<i64> = found.into_iter().collect();
+        let hits = exact.iter().filter(|(_, j)| found.contains(j)).count();
+        recall += hits as f64 / exact.len() as f64;
+    }
+
+    let n_queries = queries.len() as f64;
+
+    print_json(&json!({
+        "queries": queries.len(),
+        "n": args.n,
+        "search_k": args.search_k,
+        "recall": recall / n_queries,
+        "ann_query_ms": ann_seconds * 1000.0 / n_queries,
+        "exact_query_ms": exact_seconds * 1000.0 / n_queries,
+    }))
+}
@@ -0,0 +1,185 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use std::str::FromStr;
+
+use little_annoy::loader::{
+    add_items, npz_array, CsvReader, LoadError, NpyReader, Row, VecsKind, VecsReader,
+};
+use little_annoy::{Annoy, Distance};
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    Npy,
+    Npz,
+    Fvecs,
+    Ivecs,
+    Bvecs,
+    Jsonl,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?;
+        ext.parse().ok()
+    }
+
+    fn vecs_kind(self) -> Option<VecsKind> {
+        match self {
+            Format::Fvecs => Some(VecsKind::Fvecs),
+            Format::Ivecs => Some(VecsKind::Ivecs),
+            Format::Bvecs => Some(VecsKind::Bvecs),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "csv" => Ok(Format::Csv),
+            "npy" => Ok(Format::Npy),
+            "npz" => Ok(Format::Npz),
+            "fvecs" => Ok(Format::Fvecs),
+            "ivecs" => Ok(Format::Ivecs),
+            "bvecs" => Ok(Format::Bvecs),
+            "jsonl" | "ndjson" => Ok(Format::Jsonl),
+            _ => Err(format!("unknown input format `{}`", s)),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonRow {
+    Vector(Vec<f64>),
+    Item { id: i64, vector: Vec<f64> },
+}
+
+/// Reads one vector per line. Blank lines are skipped and do not use up a
+/// row id, so bare vectors are numbered by their position among the rows.
+fn jsonl_rows<R: BufRead>(reader: R) -> impl Iterator<Item = Row<f64>> {
+    reader
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| line.as_ref().map_or(true, |l| !l.trim().is_empty()))
+        .enumerate()
+        .map(|(row, (n, line))| {
+            let line = line?;
+            let parsed = serde_json::from_str(&line)
+                .map_err(|e| LoadError::Format(format!("line {}: {}", n + 1, e)))?;
+
+            Ok(match parsed {
+                JsonRow::Vector(v) => (row as i64, v),
+                JsonRow::Item { id, vector } => (id, vector),
+            })
+        })
+}
+
+/// A vector file given on the command line.
+pub struct Input<'a> {
+    pub path: &'a Path,
+    pub format: Format,
+    pub id_column: Option<usize>,
+    pub has_header: bool,
+}
+
+impl<'a> Input<'a> {
+    fn open(&self) -> std::io::Result<BufReader<File>> {
+        File::open(self.path).map(BufReader::new)
+    }
+
+    fn with_id_column<R: Read>(&self, npy: NpyReader<R, f64>) -> NpyReader<R, f64> {
+        match self.id_column {
+            Some(col) => npy.id_column(col),
+            None => npy,
+        }
+    }
+
+    fn npy<R: Read>(&self, reader: R) -> Result<NpyReader<R, f64>, LoadError> {
+        Ok(self.with_id_column(NpyReader::new(reader)?))
+    }
+
+    fn csv<R: BufRead>(&self, reader: R) -> Result<CsvReader<R, f64>, LoadError> {
+        CsvReader::new(reader, ',', self.has_header, self.id_column)
+    }
+
+    /// Reads just enough of the input to learn the vector dimension.
+    pub fn dimension(&self) -> Result<usize, Box<dyn Error>> {
+        let reader = self.open()?;
+
+        let f = match self.format {
+            Format::Csv => self.csv(reader)?.dimension(),
+            Format::Npy => self.npy(reader)?.dimension()?,
+            Format::Npz => {
+                let mut archive = zip_archive(reader)?;
+                let f = self
+                    .with_id_column(npz_array(&mut archive, None)?)
+                    .dimension()?;
+                f
+            }
+            Format::Fvecs | Format::Ivecs | Format::Bvecs => {
+                VecsReader::<_, f64>::new(reader, self.format.vecs_kind().unwrap())?.dimension()
+            }
+            Format::Jsonl => match jsonl_rows(reader).next() {
+                Some(row) => row?.1.len(),
+                None => 0,
+            },
+        };
+
+        Ok(f)
+    }
+
+    /// Streams every vector of the input into `ann`.
+    pub fn load<D: Distance<f64>>(&self, ann: &mut Annoy<f64, D>) -> Result<usize, Box<dyn Error>> {
+        let reader = self.open()?;
+
+        let count = match self.format {
+            Format::Csv => add_items(ann, self.csv(reader)?)?,
+            Format::Npy => add_items(ann, self.npy(reader)?)?,
+            Format::Npz => {
+                let mut archive = zip_archive(reader)?;
+                let count = add_items(ann, self.with_id_column(npz_array(&mut archive, None)?))?;
+                count
+            }
+            Format::Fvecs | Format::Ivecs | Format::Bvecs => add_items(
+                ann,
+                VecsReader::new(reader, self.format.vecs_kind().unwrap())?,
+            )?,
+            Format::Jsonl => add_items(ann, jsonl_rows(reader))?,
+        };
+
+        Ok(count)
+    }
+}
+
+fn zip_archive<R: Read + std::io::Seek>(reader: R) -> Result<zip::ZipArchive<R>, LoadError> {
+    zip::ZipArchive::new(reader).map_err(|e| LoadError::Format(format!("npz: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jsonl_rows() {
+        let data = "[1.0, 2.0]\n\n[3.0, 4.0]\n{\"id\": 5, \"vector\": [5.0, 6.0]}\n";
+        let v: Vec<_> = jsonl_rows(data.as_bytes())
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        assert_eq!(
+            v,
+            vec![
+                (0, vec![1.0, 2.0]),
+                (1, vec![3.0, 4.0]),
+                (5, vec![5.0, 6.0])
+            ]
+        );
+    }
+}
@@ -0,0 +1,172 @@
+use std::error::Error;
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand};
+use little_annoy::{Angular, Euclidean, Hamming, Manhattan, Metric};
+
+mod commands;
+mod input;
+
+use input::Format;
+
+#[derive(Parser)]
+#[command(
+    name = "little-annoy",
+    version,
+    about = "Build, query and inspect Annoy indexes"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Build an index from a CSV, NPY, NPZ, fvecs/ivecs/bvecs or JSONL file of vectors.
+    Build(BuildArgs),
+    /// Query an index by vector or by item.
+    Query(QueryArgs),
+    /// Print the shape of an index.
+    Info(IndexArgs),
+    /// Measure recall against a brute force search.
+    Eval(EvalArgs),
+}
+
+#[derive(Args)]
+pub struct IndexArgs {
+    /// Path of the index file.
+    #[arg(short, long)]
+    pub index: PathBuf,
+
+    /// Distance metric the index was built with.
+    #[arg(short, long, default_value = "angular")]
+    pub metric: Metric,
+}
+
+#[derive(Args)]
+pub struct BuildArgs {
+    /// Input file of vectors.
+    #[arg(long)]
+    pub input: PathBuf,
+
+    /// Input format; guessed from the file extension when omitted.
+    #[arg(long)]
+    pub format: Option<Format>,
+
+    /// CSV or NPY column holding the item id; row numbers are used otherwise.
+    #[arg(long)]
+    pub id_column: Option<usize>,
+
+    /// Skip the first CSV row.
+    #[arg(long)]
+    pub header: bool,
+
+    /// Path of the index file to write.
+    #[arg(short, long)]
+    pub output: PathBuf,
+
+    /// Distance metric.
+    #[arg(short, long, default_value = "angular")]
+    pub metric: Metric,
+
+    /// Number of trees; -1 builds until the index holds twice as many nodes as items.
+    #[arg(short = 't', long, default_value_t = 10, allow_negative_numbers = true)]
+    pub trees: i64,
+
+    /// Number of build workers.
+    #[arg(long, default_value_t = little_annoy::ann::DEFAULT_N_THREADS)]
+    pub threads: usize,
+
+    /// Seed for reproducible builds.
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Write tree nodes straight to the output file instead of holding them in memory.
+    #[arg(long)]
+    pub on_disk: bool,
+}
+
+#[derive(Args)]
+pub struct QueryArgs {
+    #[command(flatten)]
+    pub index: IndexArgs,
+
+    /// Number of dimensions of the indexed vectors; read from the index when omitted.
+    #[arg(short = 'f', long)]
+    pub dimensions: Option<usize>,
+
+    /// Comma separated query vector.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        allow_negative_numbers = true,
+        conflicts_with = "item",
+        required_unless_present = "item"
+    )]
+    pub vector: Option<Vec<f64>>,
+
+    /// Query with the vector of a stored item.
+    #[arg(long)]
+    pub item: Option<i64>,
+
+    /// Number of neighbors to return.
+    #[arg(short, long, default_value_t = 10)]
+    pub n: usize,
+
+    /// Number of items to inspect, once per tree that reaches them; -1 uses n * trees.
+    #[arg(long, default_value_t = -1, allow_negative_numbers = true)]
+    pub search_k: i64,
+}
+
+#[derive(Args)]
+pub struct EvalArgs {
+    #[command(flatten)]
+    pub index: IndexArgs,
+
+    /// Number of dimensions of the indexed vectors; read from the index when omitted.
+    #[arg(short = 'f', long)]
+    pub dimensions: Option<usize>,
+
+    /// Number of items sampled as queries.
+    #[arg(short, long, default_value_t = 100)]
+    pub queries: usize,
+
+    /// Number of neighbors compared per query.
+    #[arg(short, long, default_value_t = 10)]
+    pub n: usize,
+
+    /// Number of items to inspect, once per tree that reaches them; -1 uses n * trees.
+    #[arg(long, default_value_t = -1, allow_negative_numbers = true)]
+    pub search_k: i64,
+
+    /// Seed for sampling the queries.
+    #[arg(long)]
+    pub seed: Option<u64>,
+}
+
+macro_rules! with_metric {
+    ($metric:expr, $f:ident($($arg:expr),*)) => {
+        match $metric {
+            Metric::Angular => commands::$f::<Angular>($($arg),*),
+            Metric::Euclidean => commands::$f::<Euclidean>($($arg),*),
+            Metric::Manhattan => commands::$f::<Manhattan>($($arg),*),
+            Metric::Hamming => commands::$f::<Hamming>($($arg),*),
+        }
+    };
+}
+
+fn run(cli: Cli) -> Result<(), Box<dyn Error>> {
+    match cli.command {
+        Command::Build(args) => with_metric!(args.metric, build(&args)),
+        Command::Query(args) => with_metric!(args.index.metric, query(&args)),
+        Command::Info(args) => with_metric!(args.metric, info(&args)),
+        Command::Eval(args) => with_metric!(args.index.metric, eval(&args)),
+    }
+}
+
+fn main() {
+    if let Err(e) = run(Cli::parse()) {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
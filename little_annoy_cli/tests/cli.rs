@@ -0,0 +1,121 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_little-annoy"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn json(output: &Output) -> serde_json::Value {
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("little-annoy-cli-{}-{}", std::process::id(), name))
+}
+
+#[test]
+fn test_build_info_query_eval() {
+    let input = temp_path("vectors.csv");
+    let index = temp_path("index.ann");
+    let rows: Vec<String> = (0..50).map(|i| format!("{},{},1", i, i % 7)).collect();
+    fs::write(&input, rows.join("\n")).unwrap();
+
+    let input_arg = input.to_str().unwrap();
+    let index_arg = index.to_str().unwrap();
+
+    let built = json(&run(&[
+        "build",
+        "--input",
+        input_arg,
+        "-o",
+        index_arg,
+        "-m",
+        "euclidean",
+        "-t",
+        "5",
+        "--seed",
+        "1",
+    ]));
+    assert_eq!(built["dimensions"], 3);
+    assert_eq!(built["items"], 50);
+
+    let info = json(&run(&["info", "-i", index_arg, "-m", "euclidean"]));
+    assert_eq!(info["dimensions"], 3);
+    assert_eq!(info["items"], 50);
+    assert_eq!(info["trees"], 5);
+
+    let found = json(&run(&[
+        "query",
+        "-i",
+        index_arg,
+        "-m",
+        "euclidean",
+        "--vector",
+        "10,3,1",
+        "-n",
+        "1",
+        "--search-k",
+        "1000",
+    ]));
+    assert_eq!(found[0]["id"], 10);
+    assert_eq!(found[0]["distance"], 0.0);
+
+    let by_item = json(&run(&[
+        "query",
+        "-i",
+        index_arg,
+        "-m",
+        "euclidean",
+        "-f",
+        "3",
+        "--item",
+        "20",
+        "-n",
+        "1",
+    ]));
+    assert_eq!(by_item[0]["id"], 20);
+
+    let eval = json(&run(&[
+        "eval",
+        "-i",
+        index_arg,
+        "-m",
+        "euclidean",
+        "--queries",
+        "10",
+        "-n",
+        "5",
+        "--search-k",
+        "1000",
+        "--seed",
+        "1",
+    ]));
+    assert_eq!(eval["queries"], 10);
+    assert_eq!(eval["recall"], 1.0);
+
+    let mismatch = run(&[
+        "query",
+        "-i",
+        index_arg,
+        "-m",
+        "euclidean",
+        "-f",
+        "2",
+        "--item",
+        "0",
+    ]);
+    assert!(!mismatch.status.success());
+    assert!(String::from_utf8_lossy(&mismatch.stderr).contains("3 dimensions"));
+
+    fs::remove_file(input).unwrap();
+    fs::remove_file(index).unwrap();
+}
@@ -0,0 +1,475 @@
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+
+use little_annoy::{
+    Angular, Annoy, BuildOptions, Error, Euclidean, Hamming, Item, Manhattan, Metric, QueryOptions,
+};
+use numpy::PyReadonlyArray1;
+use pyo3::exceptions::{PyIOError, PyIndexError, PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyList, PyTuple};
+
+enum Index {
+    Angular(Annoy<f64, Angular>),
+    Euclidean(Annoy<f64, Euclidean>),
+    Manhattan(Annoy<f64, Manhattan>),
+    // Words of bits, which floats hold exactly only up to 2**53.
+    Hamming(Annoy<u64, Hamming>),
+}
+
+// `$ann` is bound to the `Annoy` and `$t` to its item type.
+macro_rules! dispatch {
+    ($index:expr, $ann:ident => $body:expr) => {
+        dispatch!($index, $ann, _T => $body)
+    };
+    ($index:expr, $ann:ident, $t:ident => $body:expr) => {
+        match $index {
+            Index::Angular($ann) => {
+                type $t = f64;
+                $body
+            }
+            Index::Euclidean($ann) => {
+                type $t = f64;
+                $body
+            }
+            Index::Manhattan($ann) => {
+                type $t = f64;
+                $body
+            }
+            Index::Hamming($ann) => {
+                type $t = u64;
+                $body
+            }
+        }
+    };
+}
+
+/// An item type of the indexes, converted from and to Python floats.
+trait Value: Item {
+    /// `v` as items, which `check_vector` made sure it holds exactly.
+    fn from_floats(v: &[f64]) -> Cow<'_, [Self]>;
+
+    fn to_float(self) -> f64;
+}
+
+impl Value for f64 {
+    fn from_floats(v: &[f64]) -> Cow<'_, [Self]> {
+        Cow::Borrowed(v)
+    }
+
+    fn to_float(self) -> f64 {
+        self
+    }
+}
+
+impl Value for u64 {
+    fn from_floats(v: &[f64]) -> Cow<'_, [Self]> {
+        Cow::Owned(v.iter().map(|&x| x as u64).collect())
+    }
+
+    fn to_float(self) -> f64 {
+        self as f64
+    }
+}
+
+impl Index {
+    fn new(f: usize, metric: Metric) -> Self {
+        match metric {
+            Metric::Angular => Index::Angular(Annoy::new(f)),
+            Metric::Euclidean => Index::Euclidean(Annoy::new(f)),
+            Metric::Manhattan => Index::Manhattan(Annoy::new(f)),
+            Metric::Hamming => Index::Hamming(Annoy::new(f)),
+        }
+    }
+}
+
+/// Calls `f` with a vector passed from Python. A contiguous float64 NumPy
+/// array is borrowed as is; lists, tuples and other arrays are converted.
+/// NumPy is only touched for objects that look like arrays, so the module
+/// works without it installed.
+fn with_vector<R>(obj: &Bound<'_, PyAny>, f: impl FnOnce(&[f64]) -> PyResult<R>) -> PyResult<R> {
+    if !obj.is_instance_of::<PyList>()
+        && !obj.is_instance_of::<PyTuple>()
+        && obj.hasattr("__array_interface__")?
+    {
+        if let Ok(array) = obj.extract::<PyReadonlyArray1<'_, f64>>() {
+            if let Ok(v) = array.as_slice() {
+                return f(v);
+            }
+        }
+    }
+
+    let v: Vec<f64> = obj.extract()?;
+    f(&v)
+}
+
+#[pyclass(name = "AnnoyIndex", module = "little_annoy")]
+pub struct AnnoyIndex {
+    f: usize,
+    metric: Metric,
+    index: Index,
+}
+
+impl AnnoyIndex {
+    fn check_item(&self, i: i64) -> PyResult<()> {
+        let exists = dispatch!(&self.index, ann => ann.contains(i));
+
+        if exists {
+            Ok(())
+        } else {
+            Err(PyIndexError::new_err(format!("Item {} does not exist", i)))
+        }
+    }
+
+    /// Hamming vectors are stored as words of bits, so each value must be a
+    /// whole number from 0 to below 2**64.
+    fn check_vector(&self, v: &[f64]) -> PyResult<()> {
+        if v.len() != self.f {
+            return Err(PyIndexError::new_err(format!(
+                "Vector has wrong length (expected {}, got {})",
+                self.f,
+                v.len()
+            )));
+        }
+
+        let word = |x: f64| x.fract() == 0.0 && (0.0..18_446_744_073_709_551_616.0).contains(&x);
+        match v.iter().find(|&&x| !word(x)) {
+            Some(x) if self.metric == Metric::Hamming => Err(PyValueError::new_err(format!(
+                "Hamming vectors hold whole numbers from 0 to 2**64 - 1, got {}",
+                x
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn index_error(e: Error) -> PyErr {
+    match e {
+        Error::Io(e) => PyIOError::new_err(e.to_string()),
+        Error::NonFinite | Error::DimensionMismatch { .. } => PyValueError::new_err(e.to_string()),
+        e => PyRuntimeError::new_err(e.to_string()),
+    }
+}
+
+fn nns_result(
+    py: Python<'_>,
+    nns: (Vec<i64>, Vec<f64>),
+    include_distances: bool,
+) -> PyResult<Py<PyAny>> {
+    let (ids, distances) = nns;
+
+    if include_distances {
+        Ok((ids, distances).into_pyobject(py)?.into_any().unbind())
+    } else {
+        Ok(ids.into_pyobject(py)?.into_any().unbind())
+    }
+}
+
+#[pymethods]
+impl AnnoyIndex {
+    #[new]
+    #[pyo3(signature = (f, metric = "angular"))]
+    fn new(f: usize, metric: &str) -> PyResult<Self> {
+        let metric: Metric = metric
+            .parse()
+            .map_err(|e: little_annoy::ParseMetricError| PyValueError::new_err(e.to_string()))?;
+
+        Ok(Self {
+            f,
+            metric,
+            index: Index::new(f, metric),
+        })
+    }
+
+    #[getter]
+    fn f(&self) -> usize {
+        self.f
+    }
+
+    #[getter]
+    fn metric(&self) -> &'static str {
+        self.metric.name()
+    }
+
+    fn add_item(&mut self, i: i64, vector: &Bound<'_, PyAny>) -> PyResult<()> {
+        if i < 0 {
+            return Err(PyIndexError::new_err(format!(
+                "Item index {} is negative",
+                i
+            )));
+        }
+
+        with_vector(vector, |v| {
+            self.check_vector(v)?;
+            dispatch!(&mut self.index, ann, T => ann.add_item(i, &T::from_floats(v)))
+                .map_err(index_error)
+        })
+    }
+
+    #[pyo3(signature = (n_trees, n_jobs = -1))]
+    fn build(&mut self, py: Python<'_>, n_trees: i64, n_jobs: i64) -> PyResult<bool> {
+        let index = &mut self.index;
+
+        py.detach(|| {
+            let mut options = BuildOptions::new(n_trees);
+            if n_jobs > 0 {
+                options = options.n_threads(n_jobs as usize);
+            }
+
+            dispatch!(index, ann => ann.build_with(options))
+        })
+        .map_err(index_error)?;
+
+        Ok(true)
+    }
+
+    fn unbuild(&mut self) -> PyResult<bool> {
+        dispatch!(&mut self.index, ann => ann.unbuild()).map_err(index_error)?;
+        Ok(true)
+    }
+
+    fn set_seed(&mut self, seed: u64) {
+        dispatch!(&mut self.index, ann => ann.set_seed(seed))
+    }
+
+    fn save(&self, py: Python<'_>, r#fn: &str) -> PyResult<bool> {
+        let file = File::create(r#fn).map_err(|e| PyIOError::new_err(e.to_string()))?;
+        let index = &self.index;
+
+        py.detach(|| dispatch!(index, ann => ann.try_save(BufWriter::new(file))))
+            .map_err(|e| PyIOError::new_err(e.to_string()))?;
+
+        Ok(true)
+    }
+
+    fn load(&mut self, py: Python<'_>, r#fn: &str) -> PyResult<bool> {
+        let file = File::open(r#fn).map_err(|e| PyIOError::new_err(e.to_string()))?;
+        let mut index = Index::new(self.f, self.metric);
+
+        let loaded = py.detach(|| dispatch!(&mut index, ann => ann.load(BufReader::new(file))));
+        if !loaded {
+            return Err(PyIOError::new_err(format!(
+                "{} is not a valid index file",
+                r#fn
+            )));
+        }
+        let f = dispatch!(&index, ann => ann.dimension());
+        if f != self.f {
+            return Err(PyValueError::new_err(format!(
+                "{} holds vectors of length {}, expected {}",
+                r#fn, f, self.f
+            )));
+        }
+
+        self.index = index;
+        Ok(true)
+    }
+
+    #[pyo3(signature = (i, n, search_k = -1, include_distances = false))]
+    fn get_nns_by_item(
+        &self,
+        py: Python<'_>,
+        i: i64,
+        n: usize,
+        search_k: i64,
+        include_distances: bool,
+    ) -> PyResult<Py<PyAny>> {
+        self.check_item(i)?;
+
+        let index = &self.index;
+        let nns = py.detach(|| dispatch!(index, ann => ann.get_nns_by_item(i, n, search_k)));
+
+        nns_result(py, nns, include_distances)
+    }
+
+    #[pyo3(signature = (vector, n, search_k = -1, include_distances = false))]
+    fn get_nns_by_vector(
+        &self,
+        py: Python<'_>,
+        vector: &Bound<'_, PyAny>,
+        n: usize,
+        search_k: i64,
+        include_distances: bool,
+    ) -> PyResult<Py<PyAny>> {
+        let nns = with_vector(vector, |v| {
+            self.check_vector(v)?;
+            let options = QueryOptions::new(n).search_k(search_k);
+            dispatch!(&self.index, ann, T => {
+                ann.query(&T::from_floats(v), options).map(|nns| nns.into_parts())
+            })
+            .map_err(index_error)
+        })?;
+
+        nns_result(py, nns, include_distances)
+    }
+
+    fn get_item_vector(&self, i: i64) -> PyResult<Vec<f64>> {
+        self.check_item(i)?;
+        Ok(dispatch!(&self.index, ann => {
+            ann.get_item_vector(i).unwrap().iter().map(|&x| x.to_float()).collect()
+        }))
+    }
+
+    fn get_distance(&self, i: i64, j: i64) -> PyResult<f64> {
+        self.check_item(i)?;
+        self.check_item(j)?;
+        Ok(dispatch!(&self.index, ann => ann.get_distance(i, j)))
+    }
+
+    fn get_n_items(&self) -> usize {
+        dispatch!(&self.index, ann => ann.n_items())
+    }
+
+    fn get_n_trees(&self) -> usize {
+        dispatch!(&self.index, ann => ann.n_trees())
+    }
+}
+
+#[pymodule]
+#[pyo3(name = "little_annoy")]
+fn little_annoy_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<AnnoyIndex>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyo3::types::PySlice;
+
+    #[test]
+    fn test_annoy_index() {
+        Python::attach(|py| {
+            let mut index = AnnoyIndex::new(2, "euclidean").unwrap();
+            for i in 0..10 {
+                let v = PyList::new(py, [i as f64, i as f64]).unwrap();
+                index.add_item(i, &v).unwrap();
+            }
+            index.set_seed(1);
+            index.build(py, 5, -1).unwrap();
+
+            assert_eq!(index.get_n_items(), 10);
+            assert_eq!(index.get_n_trees(), 5);
+            assert_eq!(index.get_item_vector(3).unwrap(), vec![3.0, 3.0]);
+            assert!(index.get_item_vector(10).is_err());
+            let long = PyTuple::new(py, [1.0, 2.0, 3.0]).unwrap();
+            assert!(index.add_item(11, &long).is_err());
+
+            let v = PyList::new(py, [1.0, 1.0]).unwrap();
+            assert!(index.add_item(10, &v).is_err());
+            assert!(index.build(py, 5, -1).is_err());
+            assert!(index.unbuild().unwrap());
+            assert!(index.unbuild().is_err());
+            index.add_item(10, &v).unwrap();
+            index.build(py, 5, -1).unwrap();
+            assert_eq!(index.get_n_items(), 11);
+
+            let nns = index
+                .get_nns_by_vector(py, &PyList::new(py, [0.0, 0.0]).unwrap(), 3, -1, true)
+                .unwrap();
+            let (ids, distances): (Vec<i64>, Vec<f64>) = nns.extract(py).unwrap();
+            assert_eq!(ids.len(), distances.len());
+
+            assert!(AnnoyIndex::new(2, "cosine").is_err());
+        });
+    }
+
+    #[test]
+    fn test_with_vector_numpy() {
+        Python::attach(|py| {
+            // NumPy is optional, so there are no arrays to pass without it.
+            let np = match py.import("numpy") {
+                Ok(np) => np,
+                Err(_) => return,
+            };
+
+            // A contiguous float64 array is borrowed, not copied.
+            let contiguous = np.call_method1("arange", (3.0,)).unwrap();
+            let data = contiguous
+                .extract::<PyReadonlyArray1<'_, f64>>()
+                .unwrap()
+                .as_slice()
+                .unwrap()
+                .as_ptr();
+            with_vector(&contiguous, |v| {
+                assert_eq!(v, [0.0, 1.0, 2.0]);
+                assert_eq!(v.as_ptr(), data);
+                Ok(())
+            })
+            .unwrap();
+
+            // Strided views and other dtypes are converted.
+            let strided = np
+                .call_method1("arange", (6.0,))
+                .unwrap()
+                .get_item(PySlice::new(py, 0, 6, 2))
+                .unwrap();
+            with_vector(&strided, |v| {
+                assert_eq!(v, [0.0, 2.0, 4.0]);
+                Ok(())
+            })
+            .unwrap();
+
+            let ints = np.call_method1("arange", (3,)).unwrap();
+            with_vector(&ints, |v| {
+                assert_eq!(v, [0.0, 1.0, 2.0]);
+                Ok(())
+            })
+            .unwrap();
+
+            let strings = np.call_method1("array", (vec!["a", "b"],)).unwrap();
+            assert!(with_vector(&strings, |_| Ok(())).is_err());
+        });
+    }
+
+    #[test]
+    fn test_save_error() {
+        Python::attach(|py| {
+            let index = AnnoyIndex::new(2, "euclidean").unwrap();
+            let err = index.save(py, "/dev/full").unwrap_err();
+            assert!(err.is_instance_of::<PyIOError>(py));
+        });
+    }
+
+    #[test]
+    fn test_hamming_and_load() {
+        Python::attach(|py| {
+            let mut index = AnnoyIndex::new(2, "hamming").unwrap();
+            for bad in [[1.5, 0.0], [-1.0, 0.0]] {
+                let err = index
+                    .add_item(0, &PyList::new(py, bad).unwrap())
+                    .unwrap_err();
+                assert!(err.is_instance_of::<PyValueError>(py));
+            }
+
+            // 2^63 + 2^11 is past the 2^53 up to which floats hold every
+            // integer.
+            let word = 9_223_372_036_854_777_856.0;
+            index
+                .add_item(0, &PyList::new(py, [word, 1.0]).unwrap())
+                .unwrap();
+            index
+                .add_item(1, &PyList::new(py, [word - 2048.0, 1.0]).unwrap())
+                .unwrap();
+            index.build(py, 1, -1).unwrap();
+            assert_eq!(index.get_item_vector(0).unwrap(), vec![word, 1.0]);
+            assert_eq!(index.get_distance(0, 1).unwrap(), 1.0);
+
+            let path =
+                std::env::temp_dir().join(format!("little_annoy_py_{}.ann", std::process::id()));
+            let path = path.to_str().unwrap();
+            index.save(py, path).unwrap();
+
+            let mut wider = AnnoyIndex::new(3, "hamming").unwrap();
+            let err = wider.load(py, path).unwrap_err();
+            assert!(err.is_instance_of::<PyValueError>(py));
+            assert_eq!(wider.get_n_items(), 0);
+
+            let mut loaded = AnnoyIndex::new(2, "hamming").unwrap();
+            assert!(loaded.load(py, path).unwrap());
+            assert_eq!(loaded.get_item_vector(0).unwrap(), vec![word, 1.0]);
+            std::fs::remove_file(path).unwrap();
+        });
+    }
+}
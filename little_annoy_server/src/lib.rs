@@ -0,0 +1,307 @@
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+
+use little_annoy::{AnnoyHandle, Distance, Error};
+
+pub mod protocol;
+
+use protocol::{Envelope, Index, Reply, Response};
+
+// Modification time and length, used to notice that the index file was replaced.
+type Fingerprint = (Option<SystemTime>, u64);
+
+fn fingerprint(path: &Path) -> io::Result<Fingerprint> {
+    let meta = fs::metadata(path)?;
+    Ok((meta.modified().ok(), meta.len()))
+}
+
+fn io_error(e: Error) -> io::Error {
+    match e {
+        Error::Io(e) => e,
+        e => io::Error::new(io::ErrorKind::InvalidData, e.to_string()),
+    }
+}
+
+/// An `AnnoyHandle` of any metric.
+trait Handle: Send + Sync {
+    fn index(&self) -> Arc<dyn Index>;
+    fn load(&self, path: &Path) -> Result<u64, Error>;
+}
+
+impl<D> Handle for AnnoyHandle<f64, D>
+where
+    D: Distance<f64> + 'static,
+    D::Node: Send + Sync,
+{
+    fn index(&self) -> Arc<dyn Index> {
+        self.current()
+    }
+
+    fn load(&self, path: &Path) -> Result<u64, Error> {
+        AnnoyHandle::load(self, path)
+    }
+}
+
+pub struct Server {
+    path: PathBuf,
+    handle: Box<dyn Handle>,
+    fingerprint: Mutex<Fingerprint>,
+}
+
+impl Server {
+    /// Loads the index at `path`, built with metric `D`.
+    pub fn open<D>(path: &Path) -> io::Result<Self>
+    where
+        D: Distance<f64> + 'static,
+        D::Node: Send + Sync,
+    {
+        let fp = fingerprint(path)?;
+        let handle = AnnoyHandle::<f64, D>::open(path).map_err(io_error)?;
+
+        Ok(Self {
+            path: path.to_owned(),
+            handle: Box::new(handle),
+            fingerprint: Mutex::new(fp),
+        })
+    }
+
+    /// The index currently being served.
+    pub fn index(&self) -> Arc<dyn Index> {
+        self.handle.index()
+    }
+
+    /// Reloads the index when the file changed since the last load.
+    ///
+    /// The old index keeps serving when the new file cannot be loaded or
+    /// has another dimension, and queries already running finish against
+    /// the index they started with.
+    pub fn reload_if_changed(&self) -> io::Result<bool> {
+        let mut last = self.fingerprint.lock().unwrap();
+        let fp = fingerprint(&self.path)?;
+
+        if fp == *last {
+            return Ok(false);
+        }
+
+        self.handle.load(&self.path).map_err(io_error)?;
+        *last = fp;
+
+        Ok(true)
+    }
+
+    /// Polls the index file every `interval` on a background thread.
+    pub fn watch(self: &Arc<Self>, interval: Duration) -> thread::JoinHandle<()> {
+        let server = Arc::clone(self);
+
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+
+            match server.reload_if_changed() {
+                Ok(true) => eprintln!("reloaded {}", server.path.display()),
+                Ok(false) => {}
+                Err(e) => eprintln!("failed to reload {}: {}", server.path.display(), e),
+            }
+        })
+    }
+
+    /// Answers newline-delimited JSON requests until the reader is exhausted.
+    pub fn serve_connection<R: Read, W: Write>(&self, reader: R, mut writer: W) -> io::Result<()> {
+        for line in BufReader::new(reader).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let reply = match serde_json::from_str::<Envelope>(&line) {
+                Ok(envelope) => Reply {
+                    id: envelope.id,
+                    response: self.index().handle(&envelope.request),
+                },
+                Err(e) => Reply {
+                    id: None,
+                    response: Response::error(format!("invalid request: {}", e)),
+                },
+            };
+
+            serde_json::to_writer(&mut writer, &reply)?;
+            writer.write_all(b"\n")?;
+            writer.flush()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn serve_tcp(self: Arc<Self>, listener: TcpListener) -> io::Result<()> {
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let server = Arc::clone(&self);
+
+            thread::spawn(move || {
+                if let Err(e) = stream
+                    .try_clone()
+                    .and_then(|reader| server.serve_connection(reader, stream))
+                {
+                    eprintln!("connection closed: {}", e);
+                }
+            });
+        }
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    pub fn serve_unix(self: Arc<Self>, listener: UnixListener) -> io::Result<()> {
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let server = Arc::clone(&self);
+
+            thread::spawn(move || {
+                if let Err(e) = stream
+                    .try_clone()
+                    .and_then(|reader| server.serve_connection(reader, stream))
+                {
+                    eprintln!("connection closed: {}", e);
+                }
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use little_annoy::{Annoy, Euclidean};
+    use std::net::TcpStream;
+
+    fn write_index(path: &Path, n_items: i64) {
+        let mut ann: Annoy<f64, Euclidean> = Annoy::new(2);
+        for i in 0..n_items {
+            ann.add_item(i, &[i as f64, i as f64]).unwrap();
+        }
+        ann.build(5);
+        ann.save(fs::File::create(path).unwrap());
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "little-annoy-server-{}-{}",
+            std::process::id(),
+            name
+        ))
+    }
+
+    fn request(server: &Server, line: &str) -> serde_json::Value {
+        let mut out = Vec::new();
+        server.serve_connection(line.as_bytes(), &mut out).unwrap();
+        serde_json::from_slice(&out).unwrap()
+    }
+
+    #[test]
+    fn test_requests() {
+        let path = temp_path("requests");
+        write_index(&path, 20);
+        let server = Server::open::<Euclidean>(&path).unwrap();
+
+        let reply = request(
+            &server,
+            r#"{"id": 1, "op": "get_distance", "i": 0, "j": 3}"#,
+        );
+        assert_eq!(reply["id"], 1);
+        assert_eq!(reply["distance"], (18.0f64).sqrt());
+
+        let reply = request(&server, r#"{"op": "get_nns_by_item", "item": 99}"#);
+        assert_eq!(reply["error"], "item 99 is not in the index");
+
+        let reply = request(
+            &server,
+            r#"{"op": "batch", "requests": [{"op": "get_nns_by_vector", "vector": [1.0], "n": 1}, {"op": "get_distance", "i": 1, "j": 1}]}"#,
+        );
+        assert_eq!(
+            reply["results"][0]["error"],
+            "vector has 1 dimensions, expected 2"
+        );
+        assert_eq!(reply["results"][1]["distance"], 0.0);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_tcp_and_reload() {
+        let path = temp_path("reload");
+        write_index(&path, 20);
+        let server = Arc::new(Server::open::<Euclidean>(&path).unwrap());
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let serving = Arc::clone(&server);
+        thread::spawn(move || serving.serve_tcp(listener));
+
+        let query = |stream: &mut TcpStream| {
+            stream
+                .write_all(b"{\"op\": \"get_distance\", \"i\": 0, \"j\": 0}\n")
+                .unwrap();
+            let mut line = String::new();
+            BufReader::new(stream.try_clone().unwrap())
+                .read_line(&mut line)
+                .unwrap();
+            serde_json::from_str::<serde_json::Value>(&line).unwrap()
+        };
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        assert_eq!(query(&mut stream)["distance"], 0.0);
+        assert!(!server.reload_if_changed().unwrap());
+
+        // 40 items instead of 20 changes the file length.
+        write_index(&path, 40);
+
+        assert!(server.reload_if_changed().unwrap());
+        let reply = server.index().handle(&protocol::Request::GetNnsByItem {
+            item: 39,
+            n: 1,
+            search_k: -1,
+        });
+        assert!(matches!(reply, Response::Neighbors { .. }));
+        assert_eq!(query(&mut stream)["distance"], 0.0);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unix_socket() {
+        use std::os::unix::net::UnixStream;
+
+        let path = temp_path("unix");
+        let socket = temp_path("unix.sock");
+        write_index(&path, 20);
+        let server = Arc::new(Server::open::<Euclidean>(&path).unwrap());
+
+        let listener = UnixListener::bind(&socket).unwrap();
+        thread::spawn(move || server.serve_unix(listener));
+
+        let mut stream = UnixStream::connect(&socket).unwrap();
+        stream
+            .write_all(b"{\"id\": \"a\", \"op\": \"get_nns_by_item\", \"item\": 4, \"n\": 1}\n")
+            .unwrap();
+        let mut line = String::new();
+        BufReader::new(stream).read_line(&mut line).unwrap();
+        let reply: serde_json::Value = serde_json::from_str(&line).unwrap();
+
+        assert_eq!(reply["id"], "a");
+        assert_eq!(reply["ids"], serde_json::json!([4]));
+        assert_eq!(reply["distances"], serde_json::json!([0.0]));
+
+        fs::remove_file(socket).unwrap();
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,105 @@
+use std::error::Error;
+use std::net::{SocketAddr, TcpListener};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use clap::Parser;
+use little_annoy::{Angular, Euclidean, Hamming, Manhattan, Metric};
+use little_annoy_server::Server;
+
+/// Serve newline-delimited JSON queries against a saved index.
+#[derive(Parser)]
+#[command(name = "little-annoy-server", version)]
+struct Args {
+    /// Path of the index file.
+    #[arg(short, long)]
+    index: PathBuf,
+
+    /// Distance metric the index was built with.
+    #[arg(short, long, default_value = "angular")]
+    metric: Metric,
+
+    /// Number of dimensions of the indexed vectors; read from the index when omitted.
+    #[arg(short = 'f', long)]
+    dimensions: Option<usize>,
+
+    /// Listen on a Unix domain socket at this path.
+    #[arg(long, conflicts_with = "listen")]
+    socket: Option<PathBuf>,
+
+    /// Listen on a loopback TCP address.
+    #[arg(long, default_value = "127.0.0.1:7700")]
+    listen: SocketAddr,
+
+    /// How often to check the index file for changes, in milliseconds; 0 disables reloading.
+    #[arg(long, default_value_t = 1000)]
+    reload_interval: u64,
+}
+
+fn open(path: &Path, metric: Metric) -> std::io::Result<Server> {
+    match metric {
+        Metric::Angular => Server::open::<Angular>(path),
+        Metric::Euclidean => Server::open::<Euclidean>(path),
+        Metric::Manhattan => Server::open::<Manhattan>(path),
+        Metric::Hamming => Server::open::<Hamming>(path),
+    }
+}
+
+#[cfg(unix)]
+fn serve_unix(server: Arc<Server>, path: &Path) -> Result<(), Box<dyn Error>> {
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::UnixListener;
+
+    // A socket left behind by a previous run would make bind fail.
+    if let Ok(meta) = std::fs::symlink_metadata(path) {
+        if meta.file_type().is_socket() {
+            std::fs::remove_file(path)?;
+        }
+    }
+
+    let listener = UnixListener::bind(path)?;
+    eprintln!("listening on {}", path.display());
+    server.serve_unix(listener)?;
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn serve_unix(_server: Arc<Server>, _path: &Path) -> Result<(), Box<dyn Error>> {
+    Err("unix domain sockets are not supported on this platform".into())
+}
+
+fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let server = Arc::new(open(&args.index, args.metric)?);
+
+    let f = server.index().dimension();
+    if args.dimensions.is_some_and(|d| d != f) {
+        return Err(format!("{} has {} dimensions", args.index.display(), f).into());
+    }
+
+    if args.reload_interval > 0 {
+        server.watch(Duration::from_millis(args.reload_interval));
+    }
+
+    if let Some(path) = &args.socket {
+        return serve_unix(server, path);
+    }
+
+    if !args.listen.ip().is_loopback() {
+        return Err(format!("{} is not a loopback address", args.listen).into());
+    }
+
+    let listener = TcpListener::bind(args.listen)?;
+    eprintln!("listening on {}", listener.local_addr()?);
+    server.serve_tcp(listener)?;
+
+    Ok(())
+}
+
+fn main() {
+    if let Err(e) = run(Args::parse()) {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
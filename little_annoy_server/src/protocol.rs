@@ -0,0 +1,156 @@
+use little_annoy::{Annoy, Distance, QueryOptions, ServedIndex};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+fn default_n() -> usize {
+    10
+}
+
+fn default_search_k() -> i64 {
+    -1
+}
+
+/// One line of input: a request plus an optional `id` echoed back in the reply.
+#[derive(Debug, Deserialize)]
+pub struct Envelope {
+    #[serde(default)]
+    pub id: Option<Value>,
+    #[serde(flatten)]
+    pub request: Request,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Request {
+    GetNnsByVector {
+        vector: Vec<f64>,
+        #[serde(default = "default_n")]
+        n: usize,
+        #[serde(default = "default_search_k")]
+        search_k: i64,
+    },
+    GetNnsByItem {
+        item: i64,
+        #[serde(default = "default_n")]
+        n: usize,
+        #[serde(default = "default_search_k")]
+        search_k: i64,
+    },
+    GetDistance {
+        i: i64,
+        j: i64,
+    },
+    Batch {
+        requests: Vec<Request>,
+    },
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum Response {
+    Neighbors { ids: Vec<i64>, distances: Vec<f64> },
+    Distance { distance: f64 },
+    Batch { results: Vec<Response> },
+    Error { error: String },
+}
+
+impl Response {
+    pub fn error<S: Into<String>>(error: S) -> Self {
+        Response::Error {
+            error: error.into(),
+        }
+    }
+}
+
+/// One line of output.
+#[derive(Debug, Serialize)]
+pub struct Reply {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<Value>,
+    #[serde(flatten)]
+    pub response: Response,
+}
+
+/// A loaded index that can answer requests, independent of its metric.
+pub trait Index: Send + Sync {
+    fn dimension(&self) -> usize;
+
+    fn handle(&self, request: &Request) -> Response;
+}
+
+fn has_item<D: Distance<f64>>(ann: &Annoy<f64, D>, item: i64) -> bool {
+    ann.contains(item)
+}
+
+impl<D> Index for Annoy<f64, D>
+where
+    D: Distance<f64> + 'static,
+    D::Node: Send + Sync,
+{
+    fn dimension(&self) -> usize {
+        Annoy::dimension(self)
+    }
+
+    fn handle(&self, request: &Request) -> Response {
+        match request {
+            Request::GetNnsByVector {
+                vector,
+                n,
+                search_k,
+            } => {
+                if vector.len() != self.dimension() {
+                    return Response::error(format!(
+                        "vector has {} dimensions, expected {}",
+                        vector.len(),
+                        self.dimension()
+                    ));
+                }
+
+                match self.query(vector, QueryOptions::new(*n).search_k(*search_k)) {
+                    Ok(nns) => {
+                        let (ids, distances) = nns.into_parts();
+                        Response::Neighbors { ids, distances }
+                    }
+                    Err(e) => Response::error(e.to_string()),
+                }
+            }
+            Request::GetNnsByItem { item, n, search_k } => {
+                if !has_item(self, *item) {
+                    return Response::error(format!("item {} is not in the index", item));
+                }
+
+                let (ids, distances) = self.get_nns_by_item(*item, *n, *search_k);
+                Response::Neighbors { ids, distances }
+            }
+            Request::GetDistance { i, j } => match [*i, *j].iter().find(|x| !has_item(self, **x)) {
+                Some(x) => Response::error(format!("item {} is not in the index", x)),
+                None => Response::Distance {
+                    distance: self.get_distance(*i, *j),
+                },
+            },
+            Request::Batch { requests } => Response::Batch {
+                results: requests
+                    .iter()
+                    .map(|request| match request {
+                        Request::Batch { .. } => Response::error("batches cannot be nested"),
+                        request => self.handle(request),
+                    })
+                    .collect(),
+            },
+        }
+    }
+}
+
+impl<D> Index for ServedIndex<f64, D>
+where
+    D: Distance<f64> + 'static,
+    D::Node: Send + Sync,
+{
+    fn dimension(&self) -> usize {
+        Annoy::dimension(self)
+    }
+
+    fn handle(&self, request: &Request) -> Response {
+        (**self).handle(request)
+    }
+}
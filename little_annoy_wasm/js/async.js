@@ -0,0 +1,111 @@
+// Promise-based wrapper around an `Ann` running in a Web Worker, so that
+// building and querying large indexes does not block the page.
+//
+//   const ann = await AsyncAnn.create("angular", 64, "float32");
+//   await ann.addItems([0, 1], [v0, v1]);
+//   await ann.build(1000, { onProgress: (trees) => console.log(trees) });
+//   const [{ ids, distances }] = await ann.getNnsByVector([q], 10);
+
+const errorTypes = { TypeError, RangeError };
+
+export class AsyncAnn {
+  constructor(worker) {
+    this.worker = worker;
+    this.nextId = 0;
+    this.pending = new Map();
+
+    worker.onmessage = ({ data }) => {
+      const call = this.pending.get(data.id);
+      if (call === undefined) {
+        return;
+      }
+
+      if ("progress" in data) {
+        call.onProgress?.(data.progress);
+        return;
+      }
+
+      this.pending.delete(data.id);
+      if ("error" in data) {
+        const Type = errorTypes[data.error.name] ?? Error;
+        call.reject(new Type(data.error.message));
+      } else {
+        call.resolve(data.result);
+      }
+    };
+  }
+
+  static spawn() {
+    return new AsyncAnn(new Worker(new URL("./worker.js", import.meta.url)));
+  }
+
+  static async create(metric, f, dtype) {
+    const ann = AsyncAnn.spawn();
+    await ann.call("create", [metric, f, dtype]);
+    return ann;
+  }
+
+  static async deserialize(metric, f, bytes, dtype) {
+    const ann = AsyncAnn.spawn();
+    await ann.call("deserialize", [metric, f, bytes, dtype]);
+    return ann;
+  }
+
+  call(op, args, onProgress) {
+    const id = this.nextId++;
+
+    return new Promise((resolve, reject) => {
+      this.pending.set(id, { resolve, reject, onProgress });
+      this.worker.postMessage({ id, op, args });
+    });
+  }
+
+  addItems(ids, vectors) {
+    return this.call("addItems", [ids, vectors]);
+  }
+
+  setSeed(seed) {
+    return this.call("setSeed", [seed]);
+  }
+
+  build(nTrees, { onProgress } = {}) {
+    return this.call("build", [nTrees], onProgress);
+  }
+
+  unbuild() {
+    return this.call("unbuild", []);
+  }
+
+  // Both queries take a batch and resolve to one `{ ids, distances }` per entry.
+  getNnsByVector(vectors, n, searchK = -1) {
+    return this.call("getNnsByVector", [vectors, n, searchK]);
+  }
+
+  getNnsByItem(items, n, searchK = -1) {
+    return this.call("getNnsByItem", [items, n, searchK]);
+  }
+
+  getDistance(i, j) {
+    return this.call("getDistance", [i, j]);
+  }
+
+  getNItems() {
+    return this.call("getNItems", []);
+  }
+
+  getNTrees() {
+    return this.call("getNTrees", []);
+  }
+
+  serialize() {
+    return this.call("serialize", []);
+  }
+
+  terminate() {
+    this.worker.terminate();
+    for (const call of this.pending.values()) {
+      call.reject(new Error("the worker was terminated"));
+    }
+    this.pending.clear();
+  }
+}
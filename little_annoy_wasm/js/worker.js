@@ -0,0 +1,102 @@
+// Runs an `Ann` inside a Web Worker. Requests are `{ id, op, args }`;
+// replies are `{ id, result }`, `{ id, error }` or, during a build,
+// `{ id, progress }` with the number of trees finished.
+const wasm = import("../pkg");
+
+let ann = null;
+
+function index() {
+  if (ann === null) {
+    throw new Error("the index has not been created");
+  }
+  return ann;
+}
+
+// Float32 indexes take vectors as `Float32Array`s, which halves the copy
+// into wasm memory.
+function float32(a) {
+  return a.dtype() === "float32";
+}
+
+function neighbors(r) {
+  return { ids: r.result(), distances: r.distance() };
+}
+
+const ops = {
+  create(m, [metric, f, dtype]) {
+    ann = m.Ann.new(metric, f, dtype);
+  },
+
+  deserialize(m, [metric, f, bytes, dtype]) {
+    ann = m.Ann.deserialize(metric, f, bytes, dtype);
+  },
+
+  serialize() {
+    return index().serialize();
+  },
+
+  // `vectors` is an array of vectors, or one flat array of `ids.length * f` values.
+  addItems(m, [ids, vectors]) {
+    const a = index();
+    const f = a.dimension();
+    const add = float32(a) ? (id, v) => a.add_item_f32(id, v) : (id, v) => a.add_item(id, v);
+
+    ids.forEach((id, k) => {
+      const v = Array.isArray(vectors[k]) || ArrayBuffer.isView(vectors[k])
+        ? vectors[k]
+        : vectors.slice(k * f, (k + 1) * f);
+      add(id, v);
+    });
+  },
+
+  setSeed(m, [seed]) {
+    index().set_seed(BigInt(seed));
+  },
+
+  build(m, [nTrees], id) {
+    index().build_with_progress(nTrees, (trees) => {
+      postMessage({ id, progress: trees });
+    });
+  },
+
+  unbuild() {
+    index().unbuild();
+  },
+
+  getNnsByVector(m, [vectors, n, searchK]) {
+    const a = index();
+    const query = float32(a)
+      ? (v) => a.get_nns_by_vector_f32(v, n, searchK)
+      : (v) => a.get_nns_by_vector(v, n, searchK);
+    return vectors.map((v) => neighbors(query(v)));
+  },
+
+  getNnsByItem(m, [items, n, searchK]) {
+    const a = index();
+    return Array.from(items, (i) => neighbors(a.get_nns_by_item(i, n, searchK)));
+  },
+
+  getDistance(m, [i, j]) {
+    return index().get_distance(i, j);
+  },
+
+  getNItems() {
+    return index().get_n_items();
+  },
+
+  getNTrees() {
+    return index().get_n_trees();
+  },
+};
+
+onmessage = async ({ data: { id, op, args } }) => {
+  try {
+    const m = await wasm;
+    if (!(op in ops)) {
+      throw new TypeError(`unknown operation ${op}`);
+    }
+    postMessage({ id, result: ops[op](m, args, id) });
+  } catch (e) {
+    postMessage({ id, error: { name: e.name, message: e.message } });
+  }
+};
@@ -1,57 +1,560 @@
-use little_annoy::{Annoy, Euclidean};
+use std::fmt;
+
+use little_annoy::{
+    Angular, Annoy, BuildOptions, Euclidean, Hamming, Item, Manhattan, Metric, Neighbors,
+    QueryOptions,
+};
 use wasm_bindgen::prelude::*;
 
+/// Errors surfaced to JavaScript as exceptions.
+#[derive(Debug, Clone, PartialEq)]
+enum Error {
+    /// Thrown as a `TypeError`: an unknown metric or storage type.
+    Type(String),
+    /// Thrown as a `RangeError`: a bad id, vector length or argument.
+    Range(String),
+    /// Thrown as an `Error`: bytes that are not a serialized index.
+    Data(String),
+    /// Thrown as an `Error`: a call the index is not in the state for, such
+    /// as adding an item after the build.
+    State(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Type(msg) | Error::Range(msg) | Error::Data(msg) | Error::State(msg) => {
+                f.write_str(msg)
+            }
+        }
+    }
+}
+
+impl From<little_annoy::Error> for Error {
+    fn from(e: little_annoy::Error) -> Self {
+        match e {
+            little_annoy::Error::Io(_) | little_annoy::Error::Corrupt(_) => {
+                Error::Data(e.to_string())
+            }
+            little_annoy::Error::NonFinite | little_annoy::Error::DimensionMismatch { .. } => {
+                Error::Range(e.to_string())
+            }
+            e => Error::State(e.to_string()),
+        }
+    }
+}
+
+impl From<Error> for JsValue {
+    fn from(e: Error) -> Self {
+        let msg = e.to_string();
+
+        match e {
+            Error::Type(_) => js_sys::TypeError::new(&msg).into(),
+            Error::Range(_) => js_sys::RangeError::new(&msg).into(),
+            Error::Data(_) | Error::State(_) => js_sys::Error::new(&msg).into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dtype {
+    Float32,
+    Float64,
+}
+
+impl Dtype {
+    fn parse(dtype: Option<String>) -> Result<Self, Error> {
+        match dtype.as_deref() {
+            None | Some("float64") => Ok(Dtype::Float64),
+            Some("float32") => Ok(Dtype::Float32),
+            Some(other) => Err(Error::Type(format!(
+                "unknown dtype `{}` (expected float32 or float64)",
+                other
+            ))),
+        }
+    }
+}
+
+enum Index {
+    Angular32(Annoy<f32, Angular>),
+    Euclidean32(Annoy<f32, Euclidean>),
+    Manhattan32(Annoy<f32, Manhattan>),
+    Hamming32(Annoy<f32, Hamming>),
+    Angular64(Annoy<f64, Angular>),
+    Euclidean64(Annoy<f64, Euclidean>),
+    Manhattan64(Annoy<f64, Manhattan>),
+    Hamming64(Annoy<f64, Hamming>),
+}
+
+// `$ann` is bound to the `Annoy` and `$t` to its item type.
+macro_rules! dispatch {
+    ($index:expr, $ann:ident, $t:ident => $body:expr) => {
+        match $index {
+            Index::Angular32($ann) => {
+                type $t = f32;
+                $body
+            }
+            Index::Euclidean32($ann) => {
+                type $t = f32;
+                $body
+            }
+            Index::Manhattan32($ann) => {
+                type $t = f32;
+                $body
+            }
+            Index::Hamming32($ann) => {
+                type $t = f32;
+                $body
+            }
+            Index::Angular64($ann) => {
+                type $t = f64;
+                $body
+            }
+            Index::Euclidean64($ann) => {
+                type $t = f64;
+                $body
+            }
+            Index::Manhattan64($ann) => {
+                type $t = f64;
+                $body
+            }
+            Index::Hamming64($ann) => {
+                type $t = f64;
+                $body
+            }
+        }
+    };
+}
+
+impl Index {
+    fn new(metric: Metric, dtype: Dtype, f: usize) -> Self {
+        match (dtype, metric) {
+            (Dtype::Float32, Metric::Angular) => Index::Angular32(Annoy::new(f)),
+            (Dtype::Float32, Metric::Euclidean) => Index::Euclidean32(Annoy::new(f)),
+            (Dtype::Float32, Metric::Manhattan) => Index::Manhattan32(Annoy::new(f)),
+            (Dtype::Float32, Metric::Hamming) => Index::Hamming32(Annoy::new(f)),
+            (Dtype::Float64, Metric::Angular) => Index::Angular64(Annoy::new(f)),
+            (Dtype::Float64, Metric::Euclidean) => Index::Euclidean64(Annoy::new(f)),
+            (Dtype::Float64, Metric::Manhattan) => Index::Manhattan64(Annoy::new(f)),
+            (Dtype::Float64, Metric::Hamming) => Index::Hamming64(Annoy::new(f)),
+        }
+    }
+}
+
 #[wasm_bindgen]
 pub struct Ann {
-    ann: Annoy<f64, Euclidean>,
+    f: usize,
+    metric: Metric,
+    dtype: Dtype,
+    ann: Index,
+}
+
+/// Values in the precision of the index, handed to JavaScript as a
+/// `Float32Array` or a `Float64Array`.
+#[derive(Debug, Clone, PartialEq)]
+enum Floats {
+    F32(Box<[f32]>),
+    F64(Box<[f64]>),
+}
+
+impl Floats {
+    fn new<I: IntoIterator<Item = f64>>(dtype: Dtype, values: I) -> Self {
+        let values = values.into_iter();
+        match dtype {
+            Dtype::Float32 => Floats::F32(values.map(|x| x as f32).collect()),
+            Dtype::Float64 => Floats::F64(values.collect()),
+        }
+    }
+}
+
+impl From<&Floats> for JsValue {
+    fn from(floats: &Floats) -> Self {
+        match floats {
+            Floats::F32(v) => js_sys::Float32Array::from(&v[..]).into(),
+            Floats::F64(v) => js_sys::Float64Array::from(&v[..]).into(),
+        }
+    }
 }
 
 #[wasm_bindgen]
 pub struct AnnResult {
-    result: Box<[i32]>,
-    dist: Box<[f32]>,
+    result: Box<[u32]>,
+    dist: Floats,
 }
 
 #[wasm_bindgen]
 impl AnnResult {
-    pub fn result(&self) -> Box<[i32]> {
+    pub fn result(&self) -> Box<[u32]> {
         self.result.clone()
     }
 
-    pub fn distance(&self) -> Box<[f32]> {
-        self.dist.clone()
+    /// The distances as a `Float32Array` for a float32 index and a
+    /// `Float64Array` for a float64 one.
+    pub fn distance(&self) -> JsValue {
+        JsValue::from(&self.dist)
+    }
+}
+
+impl AnnResult {
+    fn new<T: Item>(dtype: Dtype, neighbors: Neighbors<T>) -> Self {
+        AnnResult {
+            // Ids are checked to fit in u32 when they enter the index.
+            result: neighbors.iter().map(|n| n.id as u32).collect(),
+            dist: Floats::new(dtype, neighbors.iter().map(|n| n.distance)),
+        }
+    }
+}
+
+impl Ann {
+    fn create(metric: &str, f: usize, dtype: Option<String>) -> Result<Ann, Error> {
+        let metric: Metric = metric.parse().map_err(|e| Error::Type(format!("{}", e)))?;
+        let dtype = Dtype::parse(dtype)?;
+
+        if f == 0 {
+            return Err(Error::Range("dimension must be positive".to_owned()));
+        }
+
+        Ok(Ann {
+            f,
+            metric,
+            dtype,
+            ann: Index::new(metric, dtype, f),
+        })
+    }
+
+    fn check_length<V>(&self, v: &[V]) -> Result<(), Error> {
+        if v.len() == self.f {
+            Ok(())
+        } else {
+            Err(Error::Range(format!(
+                "vector has {} dimensions, expected {}",
+                v.len(),
+                self.f
+            )))
+        }
+    }
+
+    fn check_item(&self, i: u32) -> Result<(), Error> {
+        let i = i as i64;
+        let exists = dispatch!(&self.ann, ann, _T => ann.contains(i));
+
+        if exists {
+            Ok(())
+        } else {
+            Err(Error::Range(format!("item {} is not in the index", i)))
+        }
+    }
+
+    // Values come in as f32 or f64; widening f32 to f64 and back is exact,
+    // so float32 storage keeps f32 input unchanged.
+    fn add<V: Copy + Into<f64>>(&mut self, idx: u32, v: &[V]) -> Result<(), Error> {
+        self.check_length(v)?;
+
+        // Keeps the item count itself within u32.
+        if idx == u32::MAX {
+            return Err(Error::Range(format!("item id {} is too large", idx)));
+        }
+
+        dispatch!(&mut self.ann, ann, T => {
+            let v: Vec<T> = v.iter().map(|&x| x.into() as T).collect();
+            ann.add_item(idx as i64, &v)?;
+        });
+
+        Ok(())
+    }
+
+    fn nns<V: Copy + Into<f64>>(&self, v: &[V], n: u32, search_k: i32) -> Result<AnnResult, Error> {
+        self.check_length(v)?;
+
+        let options = QueryOptions::new(n as usize).search_k(search_k as i64);
+        let dtype = self.dtype;
+        let nns = dispatch!(&self.ann, ann, T => {
+            let v: Vec<T> = v.iter().map(|&x| x.into() as T).collect();
+            ann.query(&v, options).map(|nns| AnnResult::new(dtype, nns))
+        })?;
+
+        Ok(nns)
+    }
+
+    // The conversion is a no-op for float64 storage.
+    #[allow(clippy::useless_conversion)]
+    fn item_vector(&self, idx: u32) -> Result<Floats, Error> {
+        self.check_item(idx)?;
+
+        let v = dispatch!(&self.ann, ann, _T => {
+            let v = ann.get_item_vector(idx as i64).unwrap();
+            Floats::new(self.dtype, v.iter().map(|&x| f64::from(x)))
+        });
+        Ok(v)
+    }
+
+    fn load(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        let invalid = || Error::Data("bytes are not a serialized index".to_owned());
+        let f = self.f;
+
+        dispatch!(&mut self.ann, ann, _T => {
+            if !ann.load(bytes) {
+                return Err(invalid());
+            }
+            if ann.n_items() > u32::MAX as usize {
+                return Err(Error::Range(format!("{} items do not fit in u32 ids", ann.n_items())));
+            }
+            if ann.items().any(|(_, v)| v.len() != f) {
+                return Err(Error::Data(format!("index does not have {} dimensions", f)));
+            }
+        });
+
+        Ok(())
     }
 }
 
 #[wasm_bindgen]
 impl Ann {
-    pub fn new(f: u8) -> Ann {
-        let ann: Annoy<f64, Euclidean> = Annoy::new(f as usize);
-        Ann { ann }
+    /// Creates an index over `f` dimensions. `metric` is one of angular,
+    /// euclidean, manhattan or hamming; `dtype` is float64 (default) or
+    /// float32.
+    pub fn new(metric: &str, f: u32, dtype: Option<String>) -> Result<Ann, JsValue> {
+        Ok(Self::create(metric, f as usize, dtype)?)
     }
 
-    pub fn add_item(&mut self, idx: i32, v: &[f64]) {
-        self.ann.add_item(idx as i64, v);
+    /// Restores an index from the bytes returned by `serialize`, or from a
+    /// file saved by `Annoy::save` with the same metric and item type.
+    pub fn deserialize(
+        metric: &str,
+        f: u32,
+        bytes: &[u8],
+        dtype: Option<String>,
+    ) -> Result<Ann, JsValue> {
+        let mut ann = Self::create(metric, f as usize, dtype)?;
+        ann.load(bytes)?;
+        Ok(ann)
     }
 
-    pub fn build(&mut self, n: i32) {
-        self.ann.build(n as i64);
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        dispatch!(&self.ann, ann, _T => ann.save(&mut buf));
+        buf
     }
 
-    pub fn get_nns_by_vector(&mut self, v: &[f64], n: i32, search_k: i32) -> AnnResult {
-        let (r, d) = self.ann.get_nns_by_vector(v, n as usize, search_k as i64);
+    pub fn dimension(&self) -> u32 {
+        self.f as u32
+    }
 
-        AnnResult {
-            result: r
-                .into_iter()
-                .map(|n| n as i32)
-                .collect::<Vec<i32>>()
-                .into_boxed_slice(),
-            dist: d
-                .into_iter()
-                .map(|n| n as f32)
-                .collect::<Vec<f32>>()
-                .into_boxed_slice(),
+    pub fn metric(&self) -> String {
+        self.metric.name().to_owned()
+    }
+
+    pub fn dtype(&self) -> String {
+        match self.dtype {
+            Dtype::Float32 => "float32",
+            Dtype::Float64 => "float64",
         }
+        .to_owned()
+    }
+
+    pub fn get_n_items(&self) -> u32 {
+        dispatch!(&self.ann, ann, _T => ann.n_items() as u32)
+    }
+
+    pub fn get_n_trees(&self) -> u32 {
+        dispatch!(&self.ann, ann, _T => ann.n_trees() as u32)
+    }
+
+    pub fn add_item(&mut self, idx: u32, v: &[f64]) -> Result<(), JsValue> {
+        Ok(self.add(idx, v)?)
+    }
+
+    /// Like `add_item` with a `Float32Array`, which a float32 index stores
+    /// without a float64 copy.
+    pub fn add_item_f32(&mut self, idx: u32, v: &[f32]) -> Result<(), JsValue> {
+        Ok(self.add(idx, v)?)
+    }
+
+    pub fn set_seed(&mut self, seed: u64) {
+        dispatch!(&mut self.ann, ann, _T => ann.set_seed(seed));
+    }
+
+    pub fn build(&mut self, n: i32) -> Result<(), JsValue> {
+        if n < -1 {
+            return Err(Error::Range(format!("invalid number of trees {}", n)).into());
+        }
+
+        dispatch!(&mut self.ann, ann, _T => ann.build_with(BuildOptions::new(n as i64)))
+            .map_err(Error::from)?;
+        Ok(())
+    }
+
+    /// Drops the trees and keeps the items, so that items can be added and
+    /// the index built again.
+    pub fn unbuild(&mut self) -> Result<(), JsValue> {
+        dispatch!(&mut self.ann, ann, _T => ann.unbuild()).map_err(Error::from)?;
+        Ok(())
+    }
+
+    /// Like `build`, calling `progress` with the number of trees finished
+    /// after each tree. An exception thrown by `progress` is rethrown once
+    /// the build is done.
+    pub fn build_with_progress(
+        &mut self,
+        n: i32,
+        progress: &js_sys::Function,
+    ) -> Result<(), JsValue> {
+        if n < -1 {
+            return Err(Error::Range(format!("invalid number of trees {}", n)).into());
+        }
+
+        let mut thrown = None;
+        let options = BuildOptions::new(n as i64).on_progress(|p| {
+            if thrown.is_none() {
+                let trees = JsValue::from(p.trees_built as u32);
+                if let Err(e) = progress.call1(&JsValue::NULL, &trees) {
+                    thrown = Some(e);
+                }
+            }
+        });
+
+        let built = dispatch!(&mut self.ann, ann, _T => ann.build_with(options));
+
+        if let Err(e) = built {
+            return Err(Error::from(e).into());
+        }
+        match thrown {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    pub fn get_nns_by_vector(
+        &self,
+        v: &[f64],
+        n: u32,
+        search_k: i32,
+    ) -> Result<AnnResult, JsValue> {
+        Ok(self.nns(v, n, search_k)?)
+    }
+
+    /// Like `get_nns_by_vector` with a `Float32Array`.
+    pub fn get_nns_by_vector_f32(
+        &self,
+        v: &[f32],
+        n: u32,
+        search_k: i32,
+    ) -> Result<AnnResult, JsValue> {
+        Ok(self.nns(v, n, search_k)?)
+    }
+
+    pub fn get_nns_by_item(&self, idx: u32, n: u32, search_k: i32) -> Result<AnnResult, JsValue> {
+        self.check_item(idx)?;
+
+        let options = QueryOptions::new(n as usize).search_k(search_k as i64);
+        let nns = dispatch!(&self.ann, ann, _T => AnnResult::new(self.dtype, ann.query_by_item(idx as i64, options).unwrap()));
+        Ok(nns)
+    }
+
+    /// The vector of item `idx` as a `Float32Array` for a float32 index and
+    /// a `Float64Array` for a float64 one.
+    pub fn get_item_vector(&self, idx: u32) -> Result<JsValue, JsValue> {
+        Ok(JsValue::from(&self.item_vector(idx)?))
+    }
+
+    /// Like `get_item_vector` as a `Float32Array`, which float32 storage
+    /// returns exactly.
+    // The conversion is a no-op for float32 storage.
+    #[allow(clippy::unnecessary_cast)]
+    pub fn get_item_vector_f32(&self, idx: u32) -> Result<Vec<f32>, JsValue> {
+        self.check_item(idx)?;
+
+        let v = dispatch!(&self.ann, ann, _T => {
+            ann.get_item_vector(idx as i64).unwrap().iter().map(|&x| x as f32).collect()
+        });
+        Ok(v)
+    }
+
+    pub fn get_distance(&self, i: u32, j: u32) -> Result<f64, JsValue> {
+        self.check_item(i)?;
+        self.check_item(j)?;
+
+        Ok(dispatch!(&self.ann, ann, _T => ann.get_distance(i as i64, j as i64)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serialize_round_trip() {
+        for metric in &["angular", "euclidean", "manhattan", "hamming"] {
+            let dtype = || Some("float32".to_owned());
+            let mut ann = Ann::create(metric, 2, dtype()).unwrap();
+            for i in 0..100u32 {
+                ann.add_item(i, &[(i % 7) as f64, 1.0]).unwrap();
+            }
+            ann.set_seed(1);
+            ann.build(3).unwrap();
+
+            let mut loaded = Ann::create(metric, 2, dtype()).unwrap();
+            loaded.load(&ann.serialize()).unwrap();
+
+            assert_eq!(loaded.get_n_items(), 100);
+            assert_eq!(loaded.get_n_trees(), 3);
+            assert_eq!(
+                loaded.item_vector(4).unwrap(),
+                Floats::F32(Box::new([4.0, 1.0]))
+            );
+
+            let mut wrong = Ann::create(metric, 3, dtype()).unwrap();
+            assert!(matches!(wrong.load(&ann.serialize()), Err(Error::Data(_))));
+            assert!(matches!(wrong.load(&[1, 2, 3]), Err(Error::Data(_))));
+        }
+    }
+
+    #[test]
+    fn test_f32() {
+        for dtype in &["float32", "float64"] {
+            let mut ann = Ann::create("euclidean", 2, Some(dtype.to_string())).unwrap();
+            for i in 0..100u32 {
+                ann.add_item_f32(i, &[(i % 10) as f32 * 0.1, (i / 10) as f32])
+                    .unwrap();
+            }
+            ann.build(3).unwrap();
+
+            let nns = ann.nns(&[0.31f32, 2.0], 3, 1000).unwrap();
+            assert_eq!(
+                nns.result(),
+                ann.nns(&[0.31f64, 2.0], 3, 1000).unwrap().result()
+            );
+            assert_eq!(&*nns.result(), &[23, 24, 22]);
+            assert_eq!(ann.get_item_vector_f32(23).unwrap(), vec![0.3, 2.0]);
+
+            match (*dtype, &nns.dist) {
+                ("float32", Floats::F32(d)) => assert!((d[0] - 0.01).abs() < 1e-6),
+                ("float64", Floats::F64(d)) => assert!((d[0] - 0.01).abs() < 1e-6),
+                (dtype, dist) => panic!("{} index returned {:?}", dtype, dist),
+            }
+            assert!(matches!(
+                (*dtype, ann.item_vector(23).unwrap()),
+                ("float32", Floats::F32(_)) | ("float64", Floats::F64(_))
+            ));
+        }
+
+        let mut ann = Ann::create("euclidean", 2, None).unwrap();
+        assert!(matches!(ann.add(0, &[1.0f32]), Err(Error::Range(_))));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(matches!(
+            Ann::create("cosine", 2, None),
+            Err(Error::Type(_))
+        ));
+        assert!(matches!(
+            Ann::create("angular", 2, Some("int8".to_owned())),
+            Err(Error::Type(_))
+        ));
+
+        let ann = Ann::create("euclidean", 2, None).unwrap();
+        assert!(matches!(ann.check_length(&[1.0]), Err(Error::Range(_))));
+        assert!(matches!(ann.check_item(0), Err(Error::Range(_))));
     }
 }
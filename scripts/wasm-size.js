@@ -0,0 +1,29 @@
+// Builds the bundle with and without the `parallel` feature and prints the
+// size of the emitted wasm module for each.
+const { execSync } = require("child_process");
+const fs = require("fs");
+const path = require("path");
+
+const root = path.resolve(__dirname, "..");
+
+function wasmSize(dir) {
+  return fs
+    .readdirSync(path.join(root, dir))
+    .filter((name) => name.endsWith(".wasm"))
+    .reduce((size, name) => size + fs.statSync(path.join(root, dir, name)).size, 0);
+}
+
+function build(args) {
+  execSync(`npx webpack --mode production ${args}`, { cwd: root, stdio: "inherit" });
+}
+
+build("");
+build("--env parallel");
+
+const sequential = wasmSize("dist");
+const parallel = wasmSize("dist-parallel");
+const kib = (n) => `${(n / 1024).toFixed(1)} KiB`;
+
+console.log(`sequential: ${kib(sequential)}`);
+console.log(`parallel:   ${kib(parallel)}`);
+console.log(`difference: ${kib(parallel - sequential)}`);
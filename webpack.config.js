@@ -3,12 +3,14 @@ const HtmlWebpackPlugin = require("html-webpack-plugin");
 const { CleanWebpackPlugin } = require("clean-webpack-plugin");
 const WasmPackPlugin = require("@wasm-tool/wasm-pack-plugin");
 
-module.exports = {
+// `webpack --env parallel` builds the wasm crate with the threaded tree
+// builder instead of the sequential one; `npm run size` compares the two.
+module.exports = (env = {}) => ({
   entry: {
     app: "./index.js",
   },
   output: {
-    path: path.resolve(__dirname, "dist"),
+    path: path.resolve(__dirname, env.parallel ? "dist-parallel" : "dist"),
     filename: "[name].js",
   },
   plugins: [
@@ -17,6 +19,7 @@ module.exports = {
     new WasmPackPlugin({
       crateDirectory: path.resolve(__dirname, "./little_annoy_wasm/"),
       outDir: path.resolve(__dirname, "./little_annoy_wasm/pkg/"),
+      extraArgs: env.parallel ? "-- --features parallel" : "",
     }),
   ],
   experiments: {
@@ -29,4 +32,4 @@ module.exports = {
       message: /Critical dependency: the request of a dependency is an expression/,
     },
   ],
-};
+});